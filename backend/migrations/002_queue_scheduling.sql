@@ -0,0 +1,2 @@
+-- The scheduler repeatedly asks for the next eligible queued item by priority.
+CREATE INDEX IF NOT EXISTS idx_queue_next ON queue_items(status, priority, added_at);
@@ -0,0 +1,32 @@
+-- Series subscriptions (PVR mode): brand/series PIDs whose new episodes are
+-- queued automatically.
+
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id              TEXT PRIMARY KEY NOT NULL,
+    pid             TEXT NOT NULL,          -- brand or series PID
+    title           TEXT NOT NULL,
+    media_type      TEXT NOT NULL DEFAULT 'tv',   -- tv | radio
+    quality         TEXT,                   -- NULL = default_quality setting
+    subtitles       INTEGER,                -- NULL = subtitles setting
+    enabled         INTEGER NOT NULL DEFAULT 1,
+    created_at      TEXT NOT NULL DEFAULT (datetime('now')),
+    last_checked_at TEXT,
+    last_error      TEXT,
+    user_id         TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE (pid, user_id)
+);
+
+-- Every PID that has been downloaded or auto-queued. Survives removal of the
+-- queue item so a subscription never fetches the same episode twice.
+CREATE TABLE IF NOT EXISTS download_history (
+    pid         TEXT PRIMARY KEY NOT NULL,
+    source      TEXT NOT NULL,              -- queue | subscription:<id>
+    recorded_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+INSERT OR IGNORE INTO download_history (pid, source)
+    SELECT pid, 'queue' FROM queue_items WHERE status = 'done';
+
+INSERT OR IGNORE INTO settings(key, value) VALUES
+    ('subscription_check_hours', '6');
@@ -0,0 +1,20 @@
+-- Saved search rules (PVR searches) evaluated against the get_iplayer
+-- programme cache after each refresh.
+
+CREATE TABLE IF NOT EXISTS watch_rules (
+    id              TEXT PRIMARY KEY NOT NULL,
+    name            TEXT NOT NULL,
+    query           TEXT NOT NULL,
+    is_regex        INTEGER NOT NULL DEFAULT 0,
+    media_type      TEXT NOT NULL DEFAULT 'tv',   -- tv | radio
+    channel         TEXT,                   -- case-insensitive substring of the channel
+    exclude         TEXT,                   -- comma-separated terms that veto a match
+    quality         TEXT,                   -- NULL = default_quality setting
+    subtitles       INTEGER,                -- NULL = subtitles setting
+    enabled         INTEGER NOT NULL DEFAULT 1,
+    created_at      TEXT NOT NULL DEFAULT (datetime('now')),
+    last_run_at     TEXT,
+    last_error      TEXT,
+    user_id         TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,25 @@
+-- Index of the recordings under output_dir, rebuilt by scanning the disk.
+
+CREATE TABLE IF NOT EXISTS library_files (
+    id              TEXT PRIMARY KEY NOT NULL,
+    path            TEXT NOT NULL UNIQUE,   -- absolute path of the media file
+    programme       TEXT NOT NULL,
+    series          TEXT,
+    episode         TEXT,
+    pid             TEXT,
+    media_type      TEXT NOT NULL DEFAULT 'tv',   -- tv | radio (from the file type)
+    size            INTEGER NOT NULL,
+    modified_at     TEXT NOT NULL,
+    duration_secs   REAL,
+    video_codec     TEXT,
+    audio_codec     TEXT,
+    width           INTEGER,
+    height          INTEGER,
+    audio_channels  INTEGER,
+    embedded_subtitles INTEGER NOT NULL DEFAULT 0,
+    sidecars        TEXT NOT NULL DEFAULT '[]',  -- JSON array of subtitle file paths
+    queue_item_id   TEXT,                   -- the download that produced it, if known
+    indexed_at      TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_library_programme ON library_files(programme, series, episode);
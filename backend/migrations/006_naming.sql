@@ -0,0 +1,7 @@
+-- Output naming templates: a global setting plus an optional per-item
+-- override. NULL on the item means "use the filename_template setting".
+
+ALTER TABLE queue_items ADD COLUMN filename_template TEXT;
+
+INSERT OR IGNORE INTO settings(key, value) VALUES
+    ('filename_template', '');
@@ -0,0 +1,9 @@
+-- Role-based access control. Admins manage users, settings and the caches;
+-- regular users can only queue, search and browse.
+
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';  -- admin | user
+
+-- Keep the existing install manageable: the oldest account (normally the one
+-- seeded from ADMIN_USERNAME) becomes the first admin.
+UPDATE users SET role = 'admin'
+    WHERE id = (SELECT id FROM users ORDER BY created_at, rowid LIMIT 1);
@@ -0,0 +1,7 @@
+-- Per-user queue visibility and per-user download defaults. NULL defaults
+-- fall back to the global default_quality / subtitles settings.
+
+ALTER TABLE users ADD COLUMN default_quality TEXT;
+ALTER TABLE users ADD COLUMN default_subtitles INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_queue_user ON queue_items(user_id);
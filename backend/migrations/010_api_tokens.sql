@@ -0,0 +1,17 @@
+-- Long-lived personal API tokens for scripts. As with sessions, only an HMAC
+-- of the token is stored; `prefix` is the first few characters, kept so a
+-- token can be recognised in the list. `scopes` is a JSON array; an empty
+-- array gives the token its owner's ordinary (non-admin) access.
+
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id            TEXT PRIMARY KEY,
+    user_id       TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name          TEXT NOT NULL,
+    token_hash    TEXT NOT NULL UNIQUE,
+    prefix        TEXT NOT NULL,
+    scopes        TEXT NOT NULL DEFAULT '[]',
+    created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+    last_used_at  TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens(user_id);
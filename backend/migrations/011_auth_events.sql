@@ -0,0 +1,15 @@
+-- Audit trail of logins and password changes. `username` is what was typed,
+-- so failed attempts against unknown accounts are kept too; `user_id` is set
+-- when it matched a real user.
+
+CREATE TABLE IF NOT EXISTS auth_events (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind        TEXT NOT NULL,  -- login_success | login_failure | login_locked | password_changed
+    username    TEXT NOT NULL,
+    user_id     TEXT REFERENCES users(id) ON DELETE SET NULL,
+    ip          TEXT,
+    detail      TEXT,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_events_created ON auth_events(created_at);
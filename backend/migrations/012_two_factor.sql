@@ -0,0 +1,26 @@
+-- Optional TOTP two-factor authentication. `totp_secret` is base32 and is
+-- set by setup before it is verified; `totp_enabled` only flips once a code
+-- from it has been accepted. `totp_last_step` is the time step of the last
+-- accepted code, so a code can't be used twice.
+
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN totp_last_step INTEGER;
+
+-- One-time recovery codes, stored hashed like session tokens.
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    id          TEXT PRIMARY KEY,
+    user_id     TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash   TEXT NOT NULL,
+    used_at     TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_recovery_codes_user ON recovery_codes(user_id);
+
+-- Password checked, code still to come. Short-lived; only the hash is kept.
+CREATE TABLE IF NOT EXISTS login_challenges (
+    token_hash  TEXT PRIMARY KEY,
+    user_id     TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at  TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Why a download failed, classified from get_iplayer's output. NULL while
+-- no attempt has failed. Permanent kinds are not retried.
+
+ALTER TABLE queue_items ADD COLUMN error_kind TEXT;
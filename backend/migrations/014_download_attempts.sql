@@ -0,0 +1,18 @@
+-- One row per get_iplayer run of a queue item, so retries no longer erase
+-- what went wrong before. `ended_at` is NULL while the attempt is running.
+
+CREATE TABLE IF NOT EXISTS download_attempts (
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    queue_item_id  TEXT NOT NULL REFERENCES queue_items(id) ON DELETE CASCADE,
+    attempt        INTEGER NOT NULL,  -- 1 for the first run, 2 for the first retry…
+    started_at     TEXT NOT NULL,
+    ended_at       TEXT,
+    exit_code      INTEGER,
+    error_kind     TEXT,              -- NULL when the attempt succeeded
+    error          TEXT,
+    log_tail       TEXT,              -- last lines get_iplayer printed
+    bytes          INTEGER,           -- bytes transferred, as far as we could tell
+    quality        TEXT               -- recording mode actually obtained, e.g. hlshd1
+);
+
+CREATE INDEX IF NOT EXISTS idx_download_attempts_item ON download_attempts(queue_item_id, attempt);
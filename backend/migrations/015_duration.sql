@@ -0,0 +1,5 @@
+-- Programme running time in seconds, from the search result it was queued
+-- from or looked up before downloading. Lets ffmpeg progress (which only
+-- reports how far it has got) be turned into a percentage and ETA.
+
+ALTER TABLE queue_items ADD COLUMN duration_secs INTEGER;
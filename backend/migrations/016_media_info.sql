@@ -0,0 +1,12 @@
+-- What ffprobe found in a finished download. NULL until the download
+-- completes; `duration_secs` is overwritten with the recording's real length
+-- and `file_size` is filled in at the same time. The same details, plus the
+-- sidecar subtitle paths, are also kept under "media" in `metadata`.
+
+ALTER TABLE queue_items ADD COLUMN video_codec TEXT;
+ALTER TABLE queue_items ADD COLUMN audio_codec TEXT;
+ALTER TABLE queue_items ADD COLUMN width INTEGER;
+ALTER TABLE queue_items ADD COLUMN height INTEGER;
+ALTER TABLE queue_items ADD COLUMN audio_channels INTEGER;
+ALTER TABLE queue_items ADD COLUMN embedded_subtitles INTEGER;
+ALTER TABLE queue_items ADD COLUMN sidecar_subtitles INTEGER;
@@ -0,0 +1,23 @@
+-- Every file a download produced: the recording itself plus its subtitles,
+-- cover art and metadata XML. Deleting a queue item or library entry removes
+-- all of them, and integrity checks compare them against the disk.
+
+CREATE TABLE IF NOT EXISTS queue_item_files (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    queue_item_id TEXT NOT NULL REFERENCES queue_items(id) ON DELETE CASCADE,
+    path          TEXT NOT NULL,
+    -- media | subtitles | artwork | metadata | other
+    kind          TEXT NOT NULL,
+    -- Size when the download finished
+    size          INTEGER,
+    created_at    TEXT NOT NULL DEFAULT (datetime('now')),
+    UNIQUE (queue_item_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_queue_item_files_path ON queue_item_files(path);
+
+-- Downloads finished before this table existed only recorded their media file.
+INSERT OR IGNORE INTO queue_item_files (queue_item_id, path, kind, size)
+SELECT id, output_path, 'media', file_size
+FROM queue_items
+WHERE status = 'done' AND output_path IS NOT NULL AND output_path != '';
@@ -0,0 +1,17 @@
+-- The post-processing chain each finished download runs through before it is
+-- marked done (remux, embed subtitles, tag, normalise, transcode, move). The
+-- chain is copied from the `postprocess_steps` setting when the download
+-- finishes, so a later retry of one step runs the same chain.
+
+CREATE TABLE IF NOT EXISTS postprocess_steps (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    queue_item_id TEXT NOT NULL REFERENCES queue_items(id) ON DELETE CASCADE,
+    position      INTEGER NOT NULL,                 -- 1 for the first step…
+    step          TEXT NOT NULL,                    -- e.g. remux_mkv
+    status        TEXT NOT NULL DEFAULT 'pending',  -- pending | running | done | skipped | failed
+    runs          INTEGER NOT NULL DEFAULT 0,
+    started_at    TEXT,
+    ended_at      TEXT,
+    message       TEXT,                             -- why it was skipped or failed
+    UNIQUE (queue_item_id, position)
+);
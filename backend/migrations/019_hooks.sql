@@ -0,0 +1,8 @@
+-- Result of the last run of the `hook_command` setting for an item. In batch
+-- mode every item of the batch gets the same result. `hook_exit_code` is NULL
+-- when the command timed out, was killed or could not be started; the reason
+-- is then at the end of `hook_output`.
+
+ALTER TABLE queue_items ADD COLUMN hook_exit_code INTEGER;
+ALTER TABLE queue_items ADD COLUMN hook_output TEXT;
+ALTER TABLE queue_items ADD COLUMN hook_ran_at TEXT;
@@ -0,0 +1,34 @@
+-- Outgoing webhooks. Each one is sent the queue events listed in `events`
+-- (a JSON array) as JSON POSTs signed with HMAC-SHA256 under `secret`.
+-- Every event sent to a webhook is a row of `webhook_deliveries`, kept with
+-- the outcome of its latest attempt; `next_attempt_at` is set while a failed
+-- delivery is waiting to be retried, so retries survive a restart.
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id          TEXT PRIMARY KEY,
+    name        TEXT NOT NULL,
+    url         TEXT NOT NULL,
+    secret      TEXT NOT NULL,
+    events      TEXT NOT NULL DEFAULT '[]',
+    enabled     INTEGER NOT NULL DEFAULT 1,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id               INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id       TEXT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event            TEXT NOT NULL,
+    payload          TEXT NOT NULL,
+    status           TEXT NOT NULL DEFAULT 'pending',  -- pending | delivered | failed
+    attempts         INTEGER NOT NULL DEFAULT 0,
+    response_status  INTEGER,
+    error            TEXT,
+    created_at       TEXT NOT NULL DEFAULT (datetime('now')),
+    last_attempt_at  TEXT,
+    next_attempt_at  TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook
+    ON webhook_deliveries(webhook_id, id);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_pending
+    ON webhook_deliveries(status) WHERE status = 'pending';
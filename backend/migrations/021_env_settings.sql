@@ -0,0 +1,15 @@
+-- 001_initial.sql seeded rows for the settings that also have an environment
+-- variable (OUTPUT_DIR, MAX_CONCURRENT, MAX_DOWNLOAD_RETRIES, PROXY,
+-- GET_IPLAYER_PATH, FFMPEG_PATH), and a row always wins over the environment,
+-- so those variables had no effect. Drop the seeded rows nobody has changed
+-- since: the API writes `updated_at` as RFC 3339, so a row still carrying
+-- SQLite's `datetime('now')` format was never written through it.
+
+DELETE FROM settings
+WHERE updated_at NOT LIKE '%T%'
+  AND (   (key = 'output_dir'           AND value = '/downloads')
+       OR (key = 'max_concurrent'       AND value = '5')
+       OR (key = 'max_download_retries' AND value = '5')
+       OR (key = 'proxy'                AND value = '')
+       OR (key = 'get_iplayer_path'     AND value = 'get_iplayer')
+       OR (key = 'ffmpeg_path'          AND value = 'ffmpeg'));
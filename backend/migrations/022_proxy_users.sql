@@ -0,0 +1,10 @@
+-- Accounts created from the forward-auth proxy header are marked, and the
+-- header only logs in as those unless AUTH_PROXY_LOCAL_USERS is set, so a
+-- proxy user who happens to be called `admin` no longer becomes the local
+-- admin without its password or 2FA. Accounts provisioned before this are
+-- found in the audit trail.
+
+ALTER TABLE users ADD COLUMN proxy_provisioned INTEGER NOT NULL DEFAULT 0;
+
+UPDATE users SET proxy_provisioned = 1
+WHERE id IN (SELECT user_id FROM auth_events WHERE kind = 'user_provisioned');
@@ -0,0 +1,5 @@
+-- Items waiting for the next batch run of the hook command. Kept on the item
+-- rather than in memory so a batch left waiting when the service stops still
+-- runs after it starts again.
+
+ALTER TABLE queue_items ADD COLUMN hook_pending INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,191 @@
+/// Files produced by downloads.
+///
+/// get_iplayer writes more than the recording: subtitles (`.srt`, `.ttml`),
+/// cover art (`.jpg`) and programme metadata (`.xml`), all named after the
+/// recording. Once a download finishes, every one of those files is recorded
+/// in `queue_item_files`, so deleting the download removes all of them and
+/// integrity checks cover all of them.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use tracing::{info, warn};
+
+use crate::{
+    db::Db,
+    library,
+    models::{ArtefactKind, LibraryFile, QueueItem, QueueItemFile, QueueItemFileCheck},
+};
+
+const ARTWORK_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+const METADATA_EXTENSIONS: &[&str] = &["xml"];
+
+/// What the file at `path` is, judged by its extension.
+pub fn kind(path: &Path) -> ArtefactKind {
+    if library::media_type(path).is_some() {
+        return ArtefactKind::Media;
+    }
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    if library::SIDECAR_EXTENSIONS.contains(&ext.as_str()) {
+        ArtefactKind::Subtitles
+    } else if ARTWORK_EXTENSIONS.contains(&ext.as_str()) {
+        ArtefactKind::Artwork
+    } else if METADATA_EXTENSIONS.contains(&ext.as_str()) {
+        ArtefactKind::Metadata
+    } else {
+        ArtefactKind::Other
+    }
+}
+
+/// Every file next to `media` that shares its name, `media` included.
+pub async fn siblings(media: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let (Some(dir), Some(stem)) = (media.parent(), media.file_stem()) else {
+        return Ok(Vec::new());
+    };
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.file_stem() == Some(stem) && entry.file_type().await?.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Replace the files recorded for `item_id` with those of `paths` that
+/// exist.
+pub async fn record(db: &Db, item_id: &str, paths: &[String]) -> sqlx::Result<()> {
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM queue_item_files WHERE queue_item_id=?")
+        .bind(item_id)
+        .execute(&mut *tx)
+        .await?;
+    for path in paths {
+        let size = match tokio::fs::metadata(path).await {
+            Ok(m) => m.len() as i64,
+            Err(e) => {
+                warn!("Not recording {path} for {item_id}: {e}");
+                continue;
+            }
+        };
+        sqlx::query(
+            "INSERT OR IGNORE INTO queue_item_files (queue_item_id, path, kind, size) \
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(item_id)
+        .bind(path)
+        .bind(kind(Path::new(path)))
+        .bind(size)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// The files recorded for `item_id`, recording first.
+pub async fn list(db: &Db, item_id: &str) -> sqlx::Result<Vec<QueueItemFile>> {
+    sqlx::query_as(
+        "SELECT * FROM queue_item_files WHERE queue_item_id=? \
+         ORDER BY kind != 'media', path",
+    )
+    .bind(item_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Fill in `artefacts` on each library file that came from a download.
+pub async fn attach(db: &Db, files: &mut [LibraryFile]) -> sqlx::Result<()> {
+    let ids: Vec<&str> = files
+        .iter()
+        .filter_map(|f| f.queue_item_id.as_deref())
+        .collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<QueueItemFile> = sqlx::query_as(
+        "SELECT * FROM queue_item_files \
+         WHERE queue_item_id IN (SELECT value FROM json_each(?)) \
+         ORDER BY kind != 'media', path",
+    )
+    .bind(serde_json::to_string(&ids).unwrap_or_default())
+    .fetch_all(db)
+    .await?;
+
+    let mut by_item: HashMap<String, Vec<QueueItemFile>> = HashMap::new();
+    for row in rows {
+        by_item
+            .entry(row.queue_item_id.clone())
+            .or_default()
+            .push(row);
+    }
+    for file in files {
+        if let Some(id) = &file.queue_item_id {
+            file.artefacts = by_item.get(id).cloned().unwrap_or_default();
+        }
+    }
+    Ok(())
+}
+
+/// Compare each of `files` with what is on disk now.
+pub async fn check(files: Vec<QueueItemFile>) -> Vec<QueueItemFileCheck> {
+    let mut checks = Vec::with_capacity(files.len());
+    for file in files {
+        let size_on_disk = tokio::fs::metadata(&file.path)
+            .await
+            .ok()
+            .map(|m| m.len() as i64);
+        let intact = size_on_disk.is_some() && (file.size.is_none() || file.size == size_on_disk);
+        checks.push(QueueItemFileCheck {
+            file,
+            size_on_disk,
+            intact,
+        });
+    }
+    checks
+}
+
+/// How many recorded files, across all downloads, are missing or have
+/// changed size.
+pub async fn count_damaged(db: &Db) -> sqlx::Result<usize> {
+    let files: Vec<QueueItemFile> = sqlx::query_as("SELECT * FROM queue_item_files")
+        .fetch_all(db)
+        .await?;
+    Ok(check(files).await.iter().filter(|c| !c.intact).count())
+}
+
+/// Delete every file `item` produced from disk, along with their library
+/// entries. Downloads finished before files were tracked only know their
+/// `output_path`. Files that are already gone are not an error.
+pub async fn delete(db: &Db, item: &QueueItem) -> sqlx::Result<()> {
+    let mut paths: Vec<String> = list(db, &item.id)
+        .await?
+        .into_iter()
+        .map(|f| f.path)
+        .collect();
+    if paths.is_empty()
+        && let Some(path) = item.output_path.as_ref().filter(|p| !p.is_empty())
+    {
+        paths.push(path.clone());
+    }
+
+    for path in &paths {
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => info!("Deleted {path}"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            // Not fatal — the file may have been moved by hand
+            Err(e) => warn!("Could not delete {path}: {e}"),
+        }
+        sqlx::query("DELETE FROM library_files WHERE path=?")
+            .bind(path)
+            .execute(db)
+            .await?;
+    }
+    Ok(())
+}
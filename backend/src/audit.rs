@@ -0,0 +1,45 @@
+/// Authentication audit trail, stored in `auth_events`.
+///
+/// Writes never fail the request that triggered them; a lost audit row is
+/// logged instead.
+use std::net::IpAddr;
+
+use tracing::warn;
+
+use crate::{db::Db, models::AuthEventKind};
+
+/// Events older than this are dropped by `prune`.
+const RETENTION_DAYS: u32 = 90;
+
+/// Append one event.
+pub async fn record(
+    db: &Db,
+    kind: AuthEventKind,
+    username: &str,
+    user_id: Option<&str>,
+    ip: Option<IpAddr>,
+    detail: Option<&str>,
+) {
+    let result = sqlx::query(
+        "INSERT INTO auth_events (kind, username, user_id, ip, detail) VALUES (?,?,?,?,?)",
+    )
+    .bind(kind)
+    .bind(username)
+    .bind(user_id)
+    .bind(ip.map(|ip| ip.to_string()))
+    .bind(detail)
+    .execute(db)
+    .await;
+    if let Err(e) = result {
+        warn!("Could not record {kind:?} auth event for {username}: {e}");
+    }
+}
+
+/// Drop events past the retention period.
+pub async fn prune(db: &Db) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM auth_events WHERE created_at < datetime('now', ?)")
+        .bind(format!("-{RETENTION_DAYS} days"))
+        .execute(db)
+        .await?;
+    Ok(())
+}
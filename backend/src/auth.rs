@@ -1,17 +1,28 @@
+use std::net::{IpAddr, SocketAddr};
+
 use argon2::{
     Argon2,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
 };
 use axum::{
-    extract::{FromRequestParts, State},
-    http::{HeaderMap, StatusCode, request::Parts},
+    extract::{ConnectInfo, FromRequestParts, OriginalUri, Query, State},
+    http::{HeaderMap, Method, StatusCode, request::Parts},
     response::{IntoResponse, Response},
 };
-use base64::{Engine, engine::general_purpose::STANDARD};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
 
 use crate::{
-    models::{LoginRequest, LoginResponse, User},
+    audit,
+    error::AppError,
+    models::{
+        ApiScope, ApiToken, AuthEventKind, LoginOutcome, LoginRequest, LoginResponse, Role,
+        TwoFactorChallenge, TwoFactorLoginRequest, User,
+    },
     state::AppState,
+    two_factor,
 };
 
 // ── Password hashing ───────────────────────────────────────────────────────────
@@ -26,74 +37,551 @@ pub fn hash_password(password: &str) -> anyhow::Result<String> {
         .map_err(|e| anyhow::anyhow!("hash_password: {e}"))
 }
 
+/// Stored instead of a hash for accounts without a password of their own,
+/// which no password matches.
+pub const NO_PASSWORD: &str = "!";
+
 pub fn verify_password(password: &str, hash: &str) -> anyhow::Result<bool> {
+    if hash == NO_PASSWORD {
+        verify_dummy_password(password);
+        return Ok(false);
+    }
     let parsed = PasswordHash::new(hash).map_err(|e| anyhow::anyhow!("parse hash: {e}"))?;
     Ok(Argon2::default()
         .verify_password(password.as_bytes(), &parsed)
         .is_ok())
 }
 
-// ── Session token ──────────────────────────────────────────────────────────────
-// We use a simple HMAC-SHA256 token: base64(user_id + ":" + timestamp + ":" + hmac).
-// For production you'd swap this for JWT / sessions stored in DB.
+/// Spend as long as `verify_password` would, for a username that doesn't
+/// exist or has no password, so the response time doesn't tell.
+fn verify_dummy_password(password: &str) {
+    static DUMMY_HASH: once_cell::sync::Lazy<String> = once_cell::sync::Lazy::new(|| {
+        hash_password("tapedeck-dummy-password").expect("hashing a fixed password")
+    });
+    let _ = verify_password(password, &DUMMY_HASH);
+}
+
+// ── Sessions ───────────────────────────────────────────────────────────────────
+// A session token is 32 random bytes, base64url-encoded. The `sessions` table
+// only holds HMAC-SHA256(secret, token), so tokens can be expired and revoked
+// without the database ever containing one that could be replayed.
+
+/// HMAC-SHA256 of `token` under the server secret, hex-encoded.
+pub fn hash_token(token: &str, secret: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(token.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Start a session for `user_id`, returning the token and its expiry.
+pub async fn create_session(state: &AppState, user_id: &str) -> sqlx::Result<(String, String)> {
+    let token = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+    let expires_at: (String,) = sqlx::query_as(
+        "INSERT INTO sessions (id, user_id, token_hash, expires_at) \
+         VALUES (?, ?, ?, datetime('now', ?)) RETURNING expires_at",
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(user_id)
+    .bind(hash_token(&token, &state.config.secret))
+    .bind(format!("+{} hours", state.config.session_ttl_hours))
+    .fetch_one(&state.db)
+    .await?;
+    Ok((token, expires_at.0))
+}
+
+/// Revoke the session holding `token`.
+pub async fn end_session(state: &AppState, token: &str) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM sessions WHERE token_hash = ?")
+        .bind(hash_token(token, &state.config.secret))
+        .execute(&state.db)
+        .await?;
+    Ok(())
+}
+
+/// Revoke every session of `user_id` except the one holding `keep`.
+pub async fn revoke_sessions(
+    state: &AppState,
+    user_id: &str,
+    keep: Option<&str>,
+) -> sqlx::Result<u64> {
+    let keep = keep.map(|t| hash_token(t, &state.config.secret));
+    let result = sqlx::query("DELETE FROM sessions WHERE user_id = ? AND token_hash IS NOT ?")
+        .bind(user_id)
+        .bind(keep)
+        .execute(&state.db)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+// ── API tokens ─────────────────────────────────────────────────────────────────
+// Personal API tokens look like `tdk_<random>` so they can be told apart from
+// session tokens (and spotted by secret scanners). They are hashed the same
+// way and never expire; revoking one deletes its row.
+
+pub const API_TOKEN_PREFIX: &str = "tdk_";
+
+/// Characters of a new API token kept in `api_tokens.prefix` for display.
+const API_TOKEN_DISPLAY_LEN: usize = 12;
+
+/// A new API token and the part of it that may be shown later.
+pub fn generate_api_token() -> (String, String) {
+    let token = format!(
+        "{API_TOKEN_PREFIX}{}",
+        URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+    );
+    let prefix = token[..API_TOKEN_DISPLAY_LEN].to_string();
+    (token, prefix)
+}
+
+/// Who presented a token and, for API tokens, what it was granted.
+struct Credentials {
+    user: User,
+    scopes: Option<Vec<ApiScope>>,
+}
+
+impl Credentials {
+    /// Whether the token may make a `method` request to `path` (the full
+    /// `/api/...` path). Session tokens may do anything their user can.
+    fn permits(&self, method: &Method, path: &str) -> bool {
+        let Some(scopes) = &self.scopes else {
+            return true;
+        };
+        scopes.is_empty()
+            || scopes.iter().any(|scope| match scope {
+                ApiScope::Admin => true,
+                ApiScope::Read => method == Method::GET || method == Method::HEAD,
+                ApiScope::QueueAdd => method == Method::POST && path == "/api/queue",
+            })
+    }
+
+    /// The user to act as. An API token without the `admin` scope never has
+    /// admin rights, whatever its owner's role.
+    fn into_user(self) -> User {
+        let mut user = self.user;
+        if self
+            .scopes
+            .is_some_and(|scopes| !scopes.contains(&ApiScope::Admin))
+        {
+            user.role = Role::User;
+        }
+        user
+    }
+}
+
+async fn resolve(state: &AppState, token: &str) -> Option<Credentials> {
+    let hash = hash_token(token, &state.config.secret);
+    if !token.starts_with(API_TOKEN_PREFIX) {
+        let user = sqlx::query_as(
+            "SELECT u.* FROM sessions s JOIN users u ON u.id = s.user_id \
+             WHERE s.token_hash = ? AND s.expires_at > datetime('now')",
+        )
+        .bind(&hash)
+        .fetch_optional(&state.db)
+        .await
+        .ok()??;
+        return Some(Credentials { user, scopes: None });
+    }
+
+    let api_token: ApiToken = sqlx::query_as("SELECT * FROM api_tokens WHERE token_hash = ?")
+        .bind(&hash)
+        .fetch_optional(&state.db)
+        .await
+        .ok()??;
+    let user = sqlx::query_as("SELECT * FROM users WHERE id = ?")
+        .bind(&api_token.user_id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()??;
+    // Scripts may poll; a minute's precision saves a write per request.
+    let _ = sqlx::query(
+        "UPDATE api_tokens SET last_used_at = datetime('now') WHERE id = ? \
+         AND (last_used_at IS NULL OR last_used_at < datetime('now', '-1 minute'))",
+    )
+    .bind(&api_token.id)
+    .execute(&state.db)
+    .await;
+    Some(Credentials {
+        user,
+        scopes: Some(api_token.scopes),
+    })
+}
+
+// ── Media tokens ───────────────────────────────────────────────────────────────
+// `<video>`/`<audio>` elements and plain links can't send headers, so media
+// URLs carry a `?token=` instead. Rather than a session token, which would
+// then sit in browser history and proxy logs, it is a signed
+// `<user id>.<expiry>.<mac>` that only opens one path and expires soon.
 
-fn make_token(user_id: &str, secret: &str) -> String {
-    let payload = format!("{user_id}:{}", chrono::Utc::now().timestamp());
-    let mac = hmac_sha256(secret, &payload);
-    let token = format!("{payload}:{mac}");
-    STANDARD.encode(token)
+/// How long a media token works: long enough to watch (and seek through) a
+/// feature-length programme.
+const MEDIA_TOKEN_TTL_SECS: i64 = 4 * 3600;
+
+fn media_mac(state: &AppState, user_id: &str, expires: i64, path: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(state.config.secret.as_bytes())
+        .expect("HMAC accepts any key length");
+    mac.update(format!("media\n{user_id}\n{expires}\n{path}").as_bytes());
+    mac
 }
 
-fn hmac_sha256(secret: &str, data: &str) -> String {
-    use sha2::{Digest, Sha256};
-    // Simple keyed hash: SHA256(secret || data)
-    let mut h = Sha256::new();
-    h.update(secret.as_bytes());
-    h.update(b":");
-    h.update(data.as_bytes());
-    hex::encode(h.finalize())
+/// A token letting `user_id` GET `path` (e.g.
+/// `/api/library/files/<id>/stream`) for the next few hours, and its expiry.
+pub fn media_token(state: &AppState, user_id: &str, path: &str) -> (String, String) {
+    let expires = chrono::Utc::now() + chrono::Duration::seconds(MEDIA_TOKEN_TTL_SECS);
+    let mac = media_mac(state, user_id, expires.timestamp(), path);
+    let token = format!(
+        "{user_id}.{}.{}",
+        expires.timestamp(),
+        hex::encode(mac.finalize().into_bytes())
+    );
+    (token, expires.to_rfc3339())
 }
 
-pub fn verify_token(token: &str, secret: &str) -> Option<String> {
-    let decoded = STANDARD.decode(token).ok()?;
-    let s = String::from_utf8(decoded).ok()?;
-    let parts: Vec<&str> = s.splitn(3, ':').collect();
-    if parts.len() != 3 {
+/// The user a media token for `path` was issued to, if it is genuine and
+/// hasn't expired.
+async fn media_token_user(state: &AppState, token: &str, path: &str) -> Option<User> {
+    let mut parts = token.splitn(3, '.');
+    let (user_id, expires, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    let expires: i64 = expires.parse().ok()?;
+    if expires <= chrono::Utc::now().timestamp() {
         return None;
     }
-    let (user_id, ts, sig) = (parts[0], parts[1], parts[2]);
-    let payload = format!("{user_id}:{ts}");
-    let expected = hmac_sha256(secret, &payload);
-    if sig != expected {
+    media_mac(state, user_id, expires, path)
+        .verify_slice(&hex::decode(signature).ok()?)
+        .ok()?;
+    sqlx::query_as("SELECT * FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()?
+}
+
+// ── Trusted proxies ────────────────────────────────────────────────────────────
+
+/// The address a request really came from. Behind one of `TRUSTED_PROXIES`
+/// the TCP peer is the proxy, so the client is taken from
+/// `X-Forwarded-For`: the rightmost address that isn't itself a trusted
+/// proxy. Anyone else's `X-Forwarded-For` is ignored, since a client can
+/// send whatever it likes.
+pub fn client_ip(state: &AppState, headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+    if !state.config.is_trusted_proxy(peer) {
+        return peer;
+    }
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|a| a.trim().parse().ok())
+        .collect();
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !state.config.is_trusted_proxy(**ip))
+        .or(forwarded.first())
+        .copied()
+        .unwrap_or(peer)
+}
+
+// ── Trusted proxy header ───────────────────────────────────────────────────────
+// Behind a forward-auth proxy (Authelia, oauth2-proxy, …) the proxy has
+// already logged the user in and names them in a header such as
+// `Remote-User`. The header is only believed from `TRUSTED_PROXIES`; the
+// proxy must also strip it from client requests.
+
+/// The user named by `AUTH_PROXY_HEADER`, if header auth is on and `peer` is
+/// a trusted proxy. Users seen for the first time are created with
+/// `AUTH_PROXY_DEFAULT_ROLE` and no password, so they can only log in
+/// through the proxy until someone sets one. An account created locally is
+/// only matched with `AUTH_PROXY_LOCAL_USERS`: otherwise a proxy user who
+/// shares its name would get in without its password or 2FA.
+pub async fn proxy_user(
+    state: &AppState,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Option<User> {
+    let header = state.config.auth_proxy_header.as_deref()?;
+    let username = headers.get(header)?.to_str().ok()?.trim();
+    if username.is_empty() {
+        return None;
+    }
+    let peer = peer?;
+    if !state.config.is_trusted_proxy(peer) {
+        tracing::debug!("Ignoring {header} header from untrusted address {peer}");
         return None;
     }
-    Some(user_id.to_string())
+
+    let permitted = |user: User| {
+        if user.proxy_provisioned || state.config.auth_proxy_local_users {
+            return Some(user);
+        }
+        tracing::warn!(
+            "Ignoring {header} header for {username}: a local account has that name \
+             (set AUTH_PROXY_LOCAL_USERS to allow it)"
+        );
+        None
+    };
+
+    let existing: Option<User> = sqlx::query_as("SELECT * FROM users WHERE username = ?")
+        .bind(username)
+        .fetch_optional(&state.db)
+        .await
+        .ok()?;
+    if let Some(user) = existing {
+        return permitted(user);
+    }
+
+    let role = state.config.auth_proxy_default_role;
+    // Another request for the same new user may win the race; either way
+    // the row exists afterwards.
+    let created = sqlx::query(
+        "INSERT OR IGNORE INTO users (id, username, password, role, proxy_provisioned) \
+         VALUES (?, ?, ?, ?, 1)",
+    )
+    .bind(User::new_id())
+    .bind(username)
+    .bind(NO_PASSWORD)
+    .bind(role)
+    .execute(&state.db)
+    .await
+    .ok()?;
+    let user: User = sqlx::query_as("SELECT * FROM users WHERE username = ?")
+        .bind(username)
+        .fetch_one(&state.db)
+        .await
+        .ok()?;
+    if created.rows_affected() == 1 {
+        tracing::info!("Created {role:?} user {username} from {header} header");
+        audit::record(
+            &state.db,
+            AuthEventKind::UserProvisioned,
+            username,
+            Some(&user.id),
+            Some(peer),
+            Some(header),
+        )
+        .await;
+    }
+    permitted(user)
 }
 
-// ── Login handler ──────────────────────────────────────────────────────────────
+// ── Login / logout handlers ────────────────────────────────────────────────────
 
 pub async fn login_handler(
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     axum::Json(req): axum::Json<LoginRequest>,
-) -> crate::error::Result<axum::Json<LoginResponse>> {
+) -> crate::error::Result<axum::Json<LoginOutcome>> {
+    let ip = client_ip(&state, &headers, addr.ip());
+    let lockout = begin_attempt(&state, &req.username, ip)?;
+
     let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE username = ?")
         .bind(&req.username)
         .fetch_optional(&state.db)
         .await?;
 
-    let user = user.ok_or(crate::error::AppError::Unauthorized)?;
+    let valid = match &user {
+        Some(user) => verify_password(&req.password, &user.password)
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+        None => {
+            verify_dummy_password(&req.password);
+            false
+        }
+    };
+    let user_id = user.as_ref().map(|u| u.id.clone());
+    let Some(user) = user.filter(|_| valid) else {
+        login_failed(&state, &req.username, user_id.as_deref(), ip, None, lockout).await;
+        return Err(AppError::Unauthorized);
+    };
+
+    // The password alone is not enough: hand back a challenge for the code.
+    // The attempt is not a failure, but failure counts are only cleared once
+    // the whole login succeeds.
+    if user.totp_enabled {
+        state.login_limiter.undo(&user.username, ip);
+        let (challenge, expires_at) = two_factor::create_challenge(&state, &user.id).await?;
+        return Ok(axum::Json(LoginOutcome::TwoFactorRequired(
+            TwoFactorChallenge {
+                two_factor_required: true,
+                challenge,
+                expires_at,
+            },
+        )));
+    }
+
+    let response = complete_login(&state, user, ip, None).await?;
+    Ok(axum::Json(LoginOutcome::Session(response)))
+}
+
+/// POST /api/auth/login/2fa — second login step: trade the challenge from
+/// `/auth/login` and a TOTP or recovery code for a session
+pub async fn login_two_factor_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    axum::Json(req): axum::Json<TwoFactorLoginRequest>,
+) -> crate::error::Result<axum::Json<LoginResponse>> {
+    let ip = client_ip(&state, &headers, addr.ip());
+    let user = two_factor::challenge_user(&state, &req.challenge)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let lockout = begin_attempt(&state, &user.username, ip)?;
+
+    match two_factor::verify_code(&state, &user, &req.code).await? {
+        Some(method) => {
+            two_factor::end_challenge(&state, &req.challenge).await?;
+            let detail = (method == two_factor::CodeKind::Recovery).then_some("recovery code");
+            let response = complete_login(&state, user, ip, detail).await?;
+            Ok(axum::Json(response))
+        }
+        None => {
+            login_failed(
+                &state,
+                &user.username,
+                Some(&user.id),
+                ip,
+                Some("two-factor code"),
+                lockout,
+            )
+            .await;
+            Err(AppError::Unauthorized)
+        }
+    }
+}
+
+/// Count a login attempt, or 429 if `username` or `ip` is locked out by
+/// earlier failures. Returns the lockout the attempt triggers if it fails.
+fn begin_attempt(
+    state: &AppState,
+    username: &str,
+    ip: IpAddr,
+) -> crate::error::Result<Option<std::time::Duration>> {
+    state
+        .login_limiter
+        .begin(username, ip)
+        .map_err(|remaining| {
+            tracing::debug!("Login for {username} from {ip} refused: locked out");
+            AppError::TooManyRequests(remaining.as_millis().div_ceil(1000) as u64)
+        })
+}
+
+/// Record a failed login attempt, already counted by `begin_attempt`, and
+/// the `lockout` it triggered.
+async fn login_failed(
+    state: &AppState,
+    username: &str,
+    user_id: Option<&str>,
+    ip: IpAddr,
+    detail: Option<&str>,
+    lockout: Option<std::time::Duration>,
+) {
+    tracing::warn!("Failed login for {username} from {ip}");
+    audit::record(
+        &state.db,
+        AuthEventKind::LoginFailure,
+        username,
+        user_id,
+        Some(ip),
+        detail,
+    )
+    .await;
+    if let Some(lockout) = lockout {
+        tracing::warn!(
+            "Locking out logins for {username} / {ip} for {}s",
+            lockout.as_secs()
+        );
+        audit::record(
+            &state.db,
+            AuthEventKind::LoginLocked,
+            username,
+            user_id,
+            Some(ip),
+            Some(&format!("{}s", lockout.as_secs())),
+        )
+        .await;
+    }
+}
+
+/// Record a successful login and start its session.
+async fn complete_login(
+    state: &AppState,
+    user: User,
+    ip: IpAddr,
+    detail: Option<&str>,
+) -> crate::error::Result<LoginResponse> {
+    state.login_limiter.record_success(&user.username, ip);
+    tracing::info!("{} logged in from {ip}", user.username);
+    audit::record(
+        &state.db,
+        AuthEventKind::LoginSuccess,
+        &user.username,
+        Some(&user.id),
+        Some(ip),
+        detail,
+    )
+    .await;
+
+    // Logins are rare enough to double as the sweep for dead sessions,
+    // login challenges and old audit events.
+    sqlx::query("DELETE FROM sessions WHERE expires_at <= datetime('now')")
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM login_challenges WHERE expires_at <= datetime('now')")
+        .execute(&state.db)
+        .await?;
+    audit::prune(&state.db).await?;
+
+    let (token, expires_at) = create_session(state, &user.id).await?;
+    Ok(LoginResponse {
+        token,
+        expires_at,
+        user_id: user.id,
+        username: user.username,
+        role: user.role,
+    })
+}
 
-    let valid = verify_password(&req.password, &user.password)
-        .map_err(|e| crate::error::AppError::Internal(e.to_string()))?;
-    if !valid {
-        return Err(crate::error::AppError::Unauthorized);
+/// POST /api/auth/logout — end the current session
+pub async fn logout_handler(
+    AuthUser(_user): AuthUser,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> crate::error::Result<StatusCode> {
+    if let Some(token) = request_token(&headers) {
+        end_session(&state, &token).await?;
     }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/auth/logout-all — end every session of the current user,
+/// including this one
+pub async fn logout_all_handler(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+) -> crate::error::Result<StatusCode> {
+    let revoked = revoke_sessions(&state, &user.id, None).await?;
+    tracing::info!("Revoked {revoked} session(s) for {}", user.username);
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    let token = make_token(&user.id, &state.config.secret);
+/// POST /api/auth/refresh — swap the current session for a fresh one with
+/// a new expiry. The old token stops working immediately.
+pub async fn refresh_handler(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> crate::error::Result<axum::Json<LoginResponse>> {
+    if let Some(token) = request_token(&headers) {
+        end_session(&state, &token).await?;
+    }
+    let (token, expires_at) = create_session(&state, &user.id).await?;
     Ok(axum::Json(LoginResponse {
         token,
+        expires_at,
         user_id: user.id,
         username: user.username,
+        role: user.role,
     }))
 }
 
@@ -103,35 +591,147 @@ pub async fn login_handler(
 pub struct AuthUser(pub User);
 
 impl FromRequestParts<AppState> for AuthUser {
-    type Rejection = AuthRejection;
+    type Rejection = Response;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        // Accept token from Authorization: Bearer <token> or X-Auth-Token header.
-        let token = extract_bearer(&parts.headers)
-            .or_else(|| {
-                parts
-                    .headers
-                    .get("x-auth-token")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_string())
-            })
-            .ok_or(AuthRejection)?;
-
-        let user_id = verify_token(&token, &state.config.secret).ok_or(AuthRejection)?;
+        let credentials = request_credentials(parts, state).await?;
+        Ok(AuthUser(credentials.into_user()))
+    }
+}
 
-        let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = ?")
-            .bind(&user_id)
-            .fetch_optional(&state.db)
+/// The credentials a request carries, provided they allow it.
+async fn request_credentials(parts: &Parts, state: &AppState) -> Result<Credentials, Response> {
+    let Some(token) = request_token(&parts.headers) else {
+        // No token: the request may still carry a trusted proxy's user.
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        return proxy_user(state, &parts.headers, peer)
             .await
-            .map_err(|_| AuthRejection)?;
+            .map(|user| Credentials { user, scopes: None })
+            .ok_or_else(|| AuthRejection.into_response());
+    };
+    let credentials = resolve(state, &token)
+        .await
+        .ok_or_else(|| AuthRejection.into_response())?;
+
+    // Handlers sit under `/api`, which nesting strips from `parts.uri`.
+    let path = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(parts.uri.path(), |uri| uri.path());
+    if !credentials.permits(&parts.method, path) {
+        return Err(AppError::Forbidden.into_response());
+    }
+    Ok(credentials)
+}
+
+// ── Extractor: logged-in user ─────────────────────────────────────────────────
+
+/// A user who logged in, through the login form or a trusted proxy, rather
+/// than one presenting an API token, which gets 403. For managing the
+/// account itself: sessions, the password, two-factor and API tokens, so a
+/// script's token can never be turned into a session or a new token with
+/// more rights than it was given.
+#[derive(Debug, Clone)]
+pub struct SessionUser(pub User);
 
-        user.map(AuthUser).ok_or(AuthRejection)
+impl FromRequestParts<AppState> for SessionUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let credentials = request_credentials(parts, state).await?;
+        if credentials.scopes.is_some() {
+            return Err(AppError::Forbidden.into_response());
+        }
+        Ok(SessionUser(credentials.into_user()))
+    }
+}
+
+// ── Extractor: admin user ─────────────────────────────────────────────────────
+
+/// An authenticated user with the admin role. Other users get 403.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub User);
+
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+        if !user.is_admin() {
+            return Err(AppError::Forbidden.into_response());
+        }
+        Ok(AdminUser(user))
+    }
+}
+
+/// Like `AuthUser`, but also accepts a media token for the request's path
+/// (see [`media_token`]) as a `?token=` query parameter. For media streams
+/// and download links, which browsers fetch from `<video>`/`<audio>`
+/// elements and anchors that can't set headers.
+#[derive(Debug, Clone)]
+pub struct MediaUser(pub User);
+
+impl FromRequestParts<AppState> for MediaUser {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        #[derive(Deserialize)]
+        struct TokenQuery {
+            token: Option<String>,
+        }
+
+        if let Ok(AuthUser(user)) = AuthUser::from_request_parts(parts, state).await {
+            return Ok(MediaUser(user));
+        }
+        let token = Query::<TokenQuery>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|q| q.0.token)
+            .ok_or(AuthRejection)?;
+        let path = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map_or(parts.uri.path(), |uri| uri.path());
+        media_token_user(state, &token, path)
+            .await
+            .map(MediaUser)
+            .ok_or(AuthRejection)
     }
 }
 
+/// The user a session or API token belongs to, for read-only access such as
+/// the WebSocket event stream.
+pub async fn authenticate(state: &AppState, token: &str) -> Option<User> {
+    resolve(state, token)
+        .await
+        .filter(|c| c.permits(&Method::GET, ""))
+        .map(Credentials::into_user)
+}
+
+/// The token from `Authorization: Bearer <token>` or the `X-Auth-Token` header.
+pub fn request_token(headers: &HeaderMap) -> Option<String> {
+    extract_bearer(headers).or_else(|| {
+        headers
+            .get("x-auth-token")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    })
+}
+
 fn extract_bearer(headers: &HeaderMap) -> Option<String> {
     let v = headers.get("authorization")?.to_str().ok()?;
     v.strip_prefix("Bearer ").map(|s| s.to_string())
@@ -1,5 +1,10 @@
+use std::net::IpAddr;
+
 use anyhow::Context;
-use serde::Deserialize;
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer, de::Error};
+
+use crate::models::Role;
 
 /// Application configuration, loaded from environment variables / .env / config file.
 #[derive(Debug, Clone, Deserialize)]
@@ -38,10 +43,45 @@ pub struct AppConfig {
     #[serde(default = "default_iplayer_cache_dir")]
     pub iplayer_cache_dir: String,
 
-    /// Secret used for generating auth tokens / salts.
+    /// Directory holding each queue item's full get_iplayer log.
+    #[serde(default = "default_download_log_dir")]
+    pub download_log_dir: String,
+
+    /// Size at which an item's log file is rotated, in bytes.
+    #[serde(default = "default_download_log_max_bytes")]
+    pub download_log_max_bytes: u64,
+
+    /// Log files kept per item, counting the one being written.
+    #[serde(default = "default_download_log_files")]
+    pub download_log_files: usize,
+
+    /// Secret used as the HMAC key for stored session token hashes.
     #[serde(default = "default_secret")]
     pub secret: String,
 
+    /// How long a login session lasts before it must be refreshed, in hours.
+    #[serde(default = "default_session_ttl_hours")]
+    pub session_ttl_hours: u64,
+
+    /// Header carrying the username from a forward-auth proxy (e.g.
+    /// `Remote-User`). Unset disables header authentication.
+    #[serde(default)]
+    pub auth_proxy_header: Option<String>,
+
+    /// Comma-separated CIDRs (or bare IPs) of the proxies allowed to set
+    /// `auth_proxy_header`. The header is ignored from anywhere else.
+    #[serde(default, deserialize_with = "deserialize_nets")]
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// Role given to users created on first sight of their proxy header.
+    #[serde(default = "default_auth_proxy_role")]
+    pub auth_proxy_default_role: Role,
+
+    /// Let the proxy header log in as accounts created locally too, skipping
+    /// their password and 2FA. Off, it only matches accounts it created.
+    #[serde(default)]
+    pub auth_proxy_local_users: bool,
+
     /// Optional HTTP proxy to pass to get_iplayer.
     #[serde(default)]
     pub proxy: Option<String>,
@@ -79,9 +119,24 @@ fn default_ffmpeg_path() -> String {
 fn default_iplayer_cache_dir() -> String {
     "/data/iplayer-cache".to_string()
 }
+fn default_download_log_dir() -> String {
+    "/data/logs".to_string()
+}
+fn default_download_log_max_bytes() -> u64 {
+    1024 * 1024
+}
+fn default_download_log_files() -> usize {
+    3
+}
 fn default_secret() -> String {
     "change-me-in-production".to_string()
 }
+fn default_session_ttl_hours() -> u64 {
+    168
+}
+fn default_auth_proxy_role() -> Role {
+    Role::User
+}
 fn default_admin_user() -> String {
     "admin".to_string()
 }
@@ -89,11 +144,37 @@ fn default_admin_pass() -> String {
     "changeme".to_string()
 }
 
+/// `10.0.0.0/8, 192.168.1.10` → networks; a bare address is a single host.
+fn deserialize_nets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<IpNet>, D::Error> {
+    String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<IpNet>()
+                .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| D::Error::custom(format!("invalid CIDR or IP address {s:?}")))
+        })
+        .collect()
+}
+
 impl AppConfig {
     pub fn from_env() -> anyhow::Result<Self> {
         // Load .env if present (ignore errors — it may not exist)
         let _ = dotenvy::dotenv();
 
-        envy::from_env::<AppConfig>().context("Failed to load config from environment")
+        let config =
+            envy::from_env::<AppConfig>().context("Failed to load config from environment")?;
+        if config.auth_proxy_header.is_some() && config.trusted_proxies.is_empty() {
+            anyhow::bail!("AUTH_PROXY_HEADER is set but TRUSTED_PROXIES is empty");
+        }
+        Ok(config)
+    }
+
+    /// Whether `peer` may assert a user through `auth_proxy_header`.
+    pub fn is_trusted_proxy(&self, peer: IpAddr) -> bool {
+        // An IPv4 client on a dual-stack socket shows up as ::ffff:a.b.c.d.
+        let peer = peer.to_canonical();
+        self.trusted_proxies.iter().any(|net| net.contains(&peer))
     }
 }
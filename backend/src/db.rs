@@ -62,7 +62,7 @@ pub async fn seed_admin(pool: &Db, config: &AppConfig) -> anyhow::Result<()> {
         let id = User::new_id();
         let hash = crate::auth::hash_password(&config.admin_password)?;
         sqlx::query(
-            "INSERT INTO users (id, username, password) VALUES (?, ?, ?)",
+            "INSERT INTO users (id, username, password, role) VALUES (?, ?, ?, 'admin')",
         )
         .bind(&id)
         .bind(&config.admin_username)
@@ -0,0 +1,253 @@
+/// Per-item get_iplayer logs.
+///
+/// Everything get_iplayer prints on stdout or stderr while downloading an item
+/// is appended, timestamped, to `<download_log_dir>/<item id>.log`. Once that
+/// file reaches `download_log_max_bytes` it is rotated to `.log.1`, `.log.2`,
+/// …, keeping `download_log_files` files in all. Each line is also published
+/// on a broadcast channel for WebSocket clients tailing the item.
+use std::{collections::HashSet, path::PathBuf};
+
+use tokio::{
+    fs::{self, File},
+    io::AsyncWriteExt,
+    sync::{Mutex, broadcast},
+};
+use tracing::{info, warn};
+
+use crate::{config::AppConfig, db::Db};
+
+/// Where a log line came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+    /// ffmpeg, run by a post-processing step.
+    Ffmpeg,
+    /// Markers written by tapedeck itself, e.g. where each attempt starts.
+    Tapedeck,
+}
+
+impl Stream {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+            Self::Ffmpeg => "ffmpeg",
+            Self::Tapedeck => "tapedeck",
+        }
+    }
+}
+
+/// A line as written to an item's log, published to live tails.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub item_id: String,
+    pub line: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadLogs {
+    dir: PathBuf,
+    max_bytes: u64,
+    files: usize,
+    tail: broadcast::Sender<LogLine>,
+}
+
+impl DownloadLogs {
+    pub fn new(config: &AppConfig) -> Self {
+        let (tail, _) = broadcast::channel(1024);
+        DownloadLogs {
+            dir: PathBuf::from(&config.download_log_dir),
+            max_bytes: config.download_log_max_bytes.max(1),
+            files: config.download_log_files.max(1),
+            tail,
+        }
+    }
+
+    /// Receive every line written to any item's log from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<LogLine> {
+        self.tail.subscribe()
+    }
+
+    /// A writer for `item_id`'s log. Nothing touches the disk until the
+    /// first line is written.
+    pub fn writer(&self, item_id: &str) -> ItemLog {
+        ItemLog {
+            logs: self.clone(),
+            item_id: item_id.to_string(),
+            file: Mutex::new(LogFile::Closed),
+        }
+    }
+
+    /// The `index`th file of `item_id`'s log: 0 is the one being written,
+    /// higher numbers are older.
+    fn path(&self, item_id: &str, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join(format!("{item_id}.log")),
+            n => self.dir.join(format!("{item_id}.log.{n}")),
+        }
+    }
+
+    /// `item_id`'s log, oldest line first, across all rotated files.
+    /// Only the last `tail` lines are returned if given. An item that never
+    /// ran has an empty log.
+    pub async fn read(&self, item_id: &str, tail: Option<usize>) -> std::io::Result<String> {
+        let mut text = String::new();
+        for index in (0..self.files).rev() {
+            match fs::read(self.path(item_id, index)).await {
+                Ok(bytes) => text.push_str(&String::from_utf8_lossy(&bytes)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(n) = tail {
+            let lines: Vec<&str> = text.lines().collect();
+            let start = lines.len().saturating_sub(n);
+            text = lines[start..].iter().map(|l| format!("{l}\n")).collect();
+        }
+        Ok(text)
+    }
+
+    /// Delete every file of `item_id`'s log.
+    pub async fn remove(&self, item_id: &str) {
+        for index in 0..self.files {
+            let path = self.path(item_id, index);
+            match fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Could not delete log file {}: {e}", path.display()),
+            }
+        }
+    }
+
+    /// Delete logs whose queue item no longer exists, e.g. because its owner
+    /// was deleted, and rotated files beyond the configured count.
+    pub async fn prune(&self, db: &Db) {
+        let ids: Vec<(String,)> = match sqlx::query_as("SELECT id FROM queue_items")
+            .fetch_all(db)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("Could not list queue items to prune logs: {e}");
+                return;
+            }
+        };
+        let ids: HashSet<String> = ids.into_iter().map(|(id,)| id).collect();
+
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(e) => e,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("Could not scan {} for logs: {e}", self.dir.display());
+                return;
+            }
+        };
+        let mut removed = 0;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some((id, index)) = parse_file_name(&name) else {
+                continue;
+            };
+            if ids.contains(id) && index < self.files {
+                continue;
+            }
+            match fs::remove_file(entry.path()).await {
+                Ok(()) => removed += 1,
+                Err(e) => warn!("Could not delete log file {name}: {e}"),
+            }
+        }
+        if removed > 0 {
+            info!("Removed {removed} stale download log file(s)");
+        }
+    }
+
+    /// Shift `.log` → `.log.1` → `.log.2` …, dropping the oldest.
+    async fn rotate(&self, item_id: &str) {
+        let _ = fs::remove_file(self.path(item_id, self.files - 1)).await;
+        for index in (0..self.files - 1).rev() {
+            let from = self.path(item_id, index);
+            if let Err(e) = fs::rename(&from, self.path(item_id, index + 1)).await
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                warn!("Could not rotate log file {}: {e}", from.display());
+            }
+        }
+    }
+}
+
+/// `<id>.log` → `(id, 0)`, `<id>.log.<n>` → `(id, n)`.
+fn parse_file_name(name: &str) -> Option<(&str, usize)> {
+    if let Some(id) = name.strip_suffix(".log") {
+        return Some((id, 0));
+    }
+    let (rest, n) = name.rsplit_once('.')?;
+    Some((rest.strip_suffix(".log")?, n.parse().ok()?))
+}
+
+/// Appends to one item's log, rotating it as it grows.
+pub struct ItemLog {
+    logs: DownloadLogs,
+    item_id: String,
+    file: Mutex<LogFile>,
+}
+
+enum LogFile {
+    /// Not opened yet, or just rotated.
+    Closed,
+    Open {
+        file: File,
+        size: u64,
+    },
+    /// Opening failed; lines are still published but no longer written.
+    Unwritable,
+}
+
+impl ItemLog {
+    pub async fn write(&self, stream: Stream, line: &str) {
+        let line = format!(
+            "{} {:<8} {line}",
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            stream.as_str(),
+        );
+
+        let mut state = self.file.lock().await;
+        if matches!(*state, LogFile::Closed) {
+            *state = self.open().await;
+        }
+        if let LogFile::Open { file, size } = &mut *state {
+            match file.write_all(format!("{line}\n").as_bytes()).await {
+                Ok(()) => *size += line.len() as u64 + 1,
+                Err(e) => warn!("Could not write to the log of {}: {e}", self.item_id),
+            }
+            if *size >= self.logs.max_bytes {
+                *state = LogFile::Closed;
+                self.logs.rotate(&self.item_id).await;
+            }
+        }
+        drop(state);
+
+        let _ = self.logs.tail.send(LogLine {
+            item_id: self.item_id.clone(),
+            line,
+        });
+    }
+
+    async fn open(&self) -> LogFile {
+        let path = self.logs.path(&self.item_id, 0);
+        let opened = async {
+            fs::create_dir_all(&self.logs.dir).await?;
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await?;
+            let size = file.metadata().await?.len();
+            std::io::Result::Ok(LogFile::Open { file, size })
+        };
+        opened.await.unwrap_or_else(|e| {
+            warn!("Could not open log file {}: {e}", path.display());
+            LogFile::Unwritable
+        })
+    }
+}
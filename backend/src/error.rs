@@ -1,9 +1,10 @@
 use axum::{
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
     Json,
 };
 use serde_json::json;
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -23,6 +24,14 @@ pub enum AppError {
     #[error("bad request: {0}")]
     BadRequest(String),
 
+    /// Per-field validation failures, keyed by field name.
+    #[error("validation failed")]
+    Validation(BTreeMap<String, String>),
+
+    /// Locked out after too many failed attempts; retry after this many seconds.
+    #[error("too many failed attempts; retry after {0}s")]
+    TooManyRequests(u64),
+
     #[error("conflict: {0}")]
     Conflict(String),
 
@@ -35,6 +44,23 @@ pub enum AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let AppError::Validation(fields) = &self {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": self.to_string(), "fields": fields })),
+            )
+                .into_response();
+        }
+
+        if let AppError::TooManyRequests(retry_after) = &self {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                Json(json!({ "error": self.to_string(), "retry_after": retry_after })),
+            )
+                .into_response();
+        }
+
         let (status, message) = match &self {
             AppError::Db(e) => {
                 tracing::error!("DB error: {e}");
@@ -47,6 +73,8 @@ impl IntoResponse for AppError {
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
             AppError::Forbidden => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::BadRequest(m) => (StatusCode::BAD_REQUEST, m.clone()),
+            AppError::Validation(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            AppError::TooManyRequests(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
             AppError::Conflict(m) => (StatusCode::CONFLICT, m.clone()),
             AppError::Internal(m) => {
                 tracing::error!("Internal error: {m}");
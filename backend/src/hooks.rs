@@ -0,0 +1,364 @@
+/// Hook commands run after downloads.
+///
+/// The admin-set `hook_command` is run with `sh -c` whenever a download is
+/// done or has failed for good (cancellations and failures that will be
+/// retried don't count). It gets the item's fields as `TAPEDECK_*`
+/// environment variables and the whole item as JSON on stdin. Its exit code
+/// and output are stored on the item and noted in the item's log.
+///
+/// In `batch` mode finished items are marked `hook_pending` instead, and the
+/// command runs once for all of them when nothing else is downloading,
+/// processing or due to start. It then gets `TAPEDECK_COUNT` and
+/// `TAPEDECK_IDS` and a JSON array of the items, and every item of the batch
+/// stores the same result.
+use std::{process::Stdio, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, Command},
+};
+use tracing::{info, warn};
+
+use crate::{
+    db::Db,
+    download_log::{DownloadLogs, Stream},
+    models::QueueItem,
+    settings::{RuntimeSettings, SettingsService},
+};
+
+/// Values of the `hook_mode` setting.
+pub const MODES: &[&str] = &["item", "batch"];
+
+/// Lines of output kept on the item; earlier ones are dropped.
+const MAX_OUTPUT_LINES: usize = 200;
+
+/// When the hook command runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Once for every finished item.
+    Item,
+    /// Once for everything that finished, when the queue goes idle.
+    Batch,
+}
+
+impl std::str::FromStr for Mode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "item" => Ok(Mode::Item),
+            "batch" => Ok(Mode::Batch),
+            other => Err(anyhow::anyhow!("unknown hook mode: {other}")),
+        }
+    }
+}
+
+// ── Service ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct Hooks {
+    db: Db,
+    settings: SettingsService,
+    logs: DownloadLogs,
+}
+
+impl Hooks {
+    pub fn new(db: Db, settings: SettingsService, logs: DownloadLogs) -> Self {
+        Hooks { db, settings, logs }
+    }
+
+    /// `id` is done or has failed for good. Runs the hook for it, or for the
+    /// batch once the queue is idle, in the background.
+    pub fn finished(&self, id: &str) {
+        let settings = self.settings.current();
+        if settings.hook_command.is_empty() {
+            return;
+        }
+        let hooks = self.clone();
+        let id = id.to_string();
+        tokio::spawn(async move {
+            match settings.hook_mode {
+                Mode::Item => hooks.run(&settings, Mode::Item, &[id]).await,
+                Mode::Batch => {
+                    if let Err(e) = sqlx::query("UPDATE queue_items SET hook_pending=1 WHERE id=?")
+                        .bind(&id)
+                        .execute(&hooks.db)
+                        .await
+                    {
+                        warn!("Could not add {id} to the hook batch: {e}");
+                    }
+                    hooks.run_batch(&settings).await;
+                }
+            }
+        });
+    }
+
+    /// Run the hook for the waiting batch, if there is one and the queue is
+    /// idle, in the background. Called whenever the queue may have gone idle
+    /// without anything finishing, e.g. after a cancellation or at startup.
+    pub fn flush(&self) {
+        let settings = self.settings.current();
+        if settings.hook_command.is_empty() {
+            return;
+        }
+        let hooks = self.clone();
+        tokio::spawn(async move { hooks.run_batch(&settings).await });
+    }
+
+    async fn run_batch(&self, settings: &RuntimeSettings) {
+        match self.take_batch().await {
+            Ok(ids) if ids.is_empty() => {}
+            Ok(ids) => self.run(settings, Mode::Batch, &ids).await,
+            Err(e) => warn!("Could not take the hook batch: {e}"),
+        }
+    }
+
+    /// If nothing is left to do, take the whole batch. Of two items finishing
+    /// together, only one takes it.
+    async fn take_batch(&self) -> sqlx::Result<Vec<String>> {
+        if busy(&self.db).await? {
+            return Ok(Vec::new());
+        }
+        let ids: Vec<(String,)> =
+            sqlx::query_as("UPDATE queue_items SET hook_pending=0 WHERE hook_pending RETURNING id")
+                .fetch_all(&self.db)
+                .await?;
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Run the hook for `ids` and store the result on each of them.
+    async fn run(&self, settings: &RuntimeSettings, mode: Mode, ids: &[String]) {
+        let items: Vec<QueueItem> = match sqlx::query_as(
+            "SELECT * FROM queue_items WHERE id IN (SELECT value FROM json_each(?)) \
+             ORDER BY completed_at",
+        )
+        .bind(serde_json::to_string(ids).unwrap_or_default())
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(items) => items,
+            Err(e) => {
+                warn!("Could not load the items for the hook: {e}");
+                return;
+            }
+        };
+        // Items removed in the meantime are left out.
+        let (env, stdin) = match (mode, items.as_slice()) {
+            (_, []) => return,
+            (Mode::Item, [item, ..]) => (item_env(item), serde_json::to_vec(item)),
+            (Mode::Batch, items) => (batch_env(items), serde_json::to_vec(items)),
+        };
+
+        let ran_at = chrono::Utc::now().to_rfc3339();
+        let timeout = Duration::from_secs(settings.hook_timeout_secs);
+        let result = execute(
+            &settings.hook_command,
+            env,
+            stdin.unwrap_or_default(),
+            timeout,
+        )
+        .await;
+        info!(
+            "Hook for {} item(s) {}",
+            items.len(),
+            result.describe(timeout)
+        );
+
+        let ids: Vec<&str> = items.iter().map(|i| i.id.as_str()).collect();
+        if let Err(e) = sqlx::query(
+            "UPDATE queue_items SET hook_exit_code=?, hook_output=?, hook_ran_at=? \
+             WHERE id IN (SELECT value FROM json_each(?))",
+        )
+        .bind(result.exit_code)
+        .bind(result.output(timeout))
+        .bind(&ran_at)
+        .bind(serde_json::to_string(&ids).unwrap_or_default())
+        .execute(&self.db)
+        .await
+        {
+            warn!("Could not store the hook result: {e}");
+        }
+        for id in ids {
+            self.logs
+                .writer(id)
+                .write(
+                    Stream::Tapedeck,
+                    &format!("Hook {}", result.describe(timeout)),
+                )
+                .await;
+        }
+    }
+}
+
+/// Whether anything is downloading, processing or queued and due.
+async fn busy(db: &Db) -> sqlx::Result<bool> {
+    let (busy,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM queue_items \
+         WHERE status IN ('downloading', 'processing') \
+            OR (status = 'queued' AND (scheduled_at IS NULL OR scheduled_at <= ?)))",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_one(db)
+    .await?;
+    Ok(busy)
+}
+
+fn item_env(item: &QueueItem) -> Vec<(&'static str, String)> {
+    let or_blank = |v: &Option<String>| v.clone().unwrap_or_default();
+    vec![
+        ("TAPEDECK_MODE", "item".to_string()),
+        ("TAPEDECK_ID", item.id.clone()),
+        ("TAPEDECK_PID", item.pid.clone()),
+        ("TAPEDECK_TITLE", item.title.clone()),
+        ("TAPEDECK_SERIES", or_blank(&item.series)),
+        ("TAPEDECK_EPISODE", or_blank(&item.episode)),
+        ("TAPEDECK_CHANNEL", or_blank(&item.channel)),
+        ("TAPEDECK_OUTPUT_PATH", or_blank(&item.output_path)),
+        ("TAPEDECK_STATUS", item.status.clone()),
+        ("TAPEDECK_ERROR", or_blank(&item.error)),
+    ]
+}
+
+fn batch_env(items: &[QueueItem]) -> Vec<(&'static str, String)> {
+    let ids: Vec<&str> = items.iter().map(|i| i.id.as_str()).collect();
+    vec![
+        ("TAPEDECK_MODE", "batch".to_string()),
+        ("TAPEDECK_COUNT", items.len().to_string()),
+        ("TAPEDECK_IDS", ids.join(",")),
+    ]
+}
+
+// ── Running the command ────────────────────────────────────────────────────────
+
+/// How a run of the hook command ended.
+struct Run {
+    /// `None` if it timed out, was killed or never started.
+    exit_code: Option<i32>,
+    /// stdout and stderr, interleaved as they arrived.
+    lines: Vec<String>,
+    timed_out: bool,
+    /// Why it couldn't be started.
+    error: Option<String>,
+}
+
+impl Run {
+    fn describe(&self, timeout: Duration) -> String {
+        match (&self.error, self.exit_code) {
+            (Some(e), _) => format!("could not be run: {e}"),
+            _ if self.timed_out => format!("timed out after {}s", timeout.as_secs()),
+            (None, Some(code)) => format!("exited with {code}"),
+            (None, None) => "was killed by a signal".to_string(),
+        }
+    }
+
+    /// The output to store, with the reason appended when there is no exit
+    /// code.
+    fn output(&self, timeout: Duration) -> String {
+        let mut lines = self.lines.clone();
+        if self.exit_code.is_none() {
+            lines.push(format!("[hook {}]", self.describe(timeout)));
+        }
+        lines.join("\n")
+    }
+}
+
+async fn execute(
+    command: &str,
+    env: Vec<(&'static str, String)>,
+    stdin: Vec<u8>,
+    timeout: Duration,
+) -> Run {
+    let mut run = Run {
+        exit_code: None,
+        lines: Vec::new(),
+        timed_out: false,
+        error: None,
+    };
+    // In a process group of its own, so a timeout kills whatever it started
+    // as well as the shell.
+    let mut child = match Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .process_group(0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            run.error = Some(e.to_string());
+            return run;
+        }
+    };
+
+    // Written from a task so a command that never reads its stdin can't
+    // hold up reading its output.
+    let mut input = child.stdin.take().expect("stdin piped");
+    tokio::spawn(async move {
+        let _ = input.write_all(&stdin).await;
+    });
+
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout piped")).lines();
+    let mut stderr = BufReader::new(child.stderr.take().expect("stderr piped")).lines();
+    let (mut out_open, mut err_open) = (true, true);
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    while out_open || err_open {
+        tokio::select! {
+            line = stdout.next_line(), if out_open => match line {
+                Ok(Some(line)) => keep(&mut run.lines, line),
+                _ => out_open = false,
+            },
+            line = stderr.next_line(), if err_open => match line {
+                Ok(Some(line)) => keep(&mut run.lines, line),
+                _ => err_open = false,
+            },
+            _ = &mut deadline => {
+                run.timed_out = true;
+                break;
+            }
+        }
+    }
+
+    if !run.timed_out {
+        tokio::select! {
+            status = child.wait() => match status {
+                Ok(status) => run.exit_code = status.code(),
+                Err(e) => run.error = Some(e.to_string()),
+            },
+            _ = &mut deadline => run.timed_out = true,
+        }
+    }
+    if run.timed_out
+        && let Err(e) = kill_group(&mut child).await
+    {
+        warn!("Could not kill the hook's process group: {e}");
+    }
+    run
+}
+
+/// Kill `child`, started with `process_group(0)`, and everything else in
+/// its process group.
+pub async fn kill_group(child: &mut Child) -> std::io::Result<()> {
+    let group = match child.id() {
+        Some(pid) => Command::new("kill")
+            .args(["-KILL", "--", &format!("-{pid}")])
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map(drop),
+        None => Ok(()),
+    };
+    let _ = child.kill().await;
+    group
+}
+
+/// Add `line` to `lines`, dropping the oldest past `MAX_OUTPUT_LINES`.
+fn keep(lines: &mut Vec<String>, line: String) {
+    lines.push(line);
+    if lines.len() > MAX_OUTPUT_LINES {
+        lines.remove(0);
+    }
+}
@@ -5,8 +5,14 @@
 use anyhow::{Context, bail};
 use regex::Regex;
 use tokio::{io::AsyncReadExt, process::Command, sync::mpsc as tmpsc};
+use tokio_util::sync::CancellationToken;
 
-use crate::models::SearchResult;
+use crate::{
+    artefacts,
+    download_log::{ItemLog, Stream},
+    hooks,
+    models::{ArtefactKind, DownloadErrorKind, SearchResult},
+};
 
 // ── Progress parsing ───────────────────────────────────────────────────────────
 
@@ -14,9 +20,38 @@ use crate::models::SearchResult;
 pub struct ProgressUpdate {
     pub percent: f64,
     pub speed: Option<String>,
+    /// Time left, as `HH:MM:SS`.
     pub eta: Option<String>,
-    #[allow(dead_code)]
-    pub size: Option<String>,
+    /// How far into the programme ffmpeg has got, as `HH:MM:SS`.
+    pub elapsed: Option<String>,
+    pub bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    /// `elapsed` in seconds and ffmpeg's speed as a multiple of real time,
+    /// for working out percent and ETA once the duration is known.
+    position_secs: Option<f64>,
+    speed_factor: Option<f64>,
+}
+
+impl ProgressUpdate {
+    /// ffmpeg only says how far it has got; with the programme's running
+    /// time that gives a real percentage and ETA.
+    fn apply_duration(&mut self, duration_secs: f64) {
+        let Some(position) = self.position_secs else {
+            return;
+        };
+        if self.percent > 0.0 || duration_secs <= 0.0 {
+            return;
+        }
+        // One decimal place, like get_iplayer's own HLS percentages.
+        self.percent = ((position / duration_secs * 1000.0).round() / 10.0).clamp(0.0, 100.0);
+        if let Some(factor) = self.speed_factor.filter(|f| *f > 0.0) {
+            let remaining = (duration_secs - position).max(0.0) / factor;
+            self.eta = Some(format_hms(remaining));
+        }
+        if let Some(bytes) = self.bytes.filter(|_| position > 0.0) {
+            self.total_bytes = Some((bytes as f64 * duration_secs / position) as u64);
+        }
+    }
 }
 
 fn parse_progress_line(line: &str) -> Option<ProgressUpdate> {
@@ -35,48 +70,155 @@ fn parse_progress_line(line: &str) -> Option<ProgressUpdate> {
         once_cell::sync::Lazy::new(|| Regex::new(r"ETA:?\s+([\d:]+)").unwrap());
     // Size: matches "~2442.31 MB", "12.34 MiB", "512 KB", etc.
     static RE_SIZE_BW: once_cell::sync::Lazy<Regex> =
-        once_cell::sync::Lazy::new(|| Regex::new(r"~?([\d.]+\s*(?:[KMGT]i?[Bb]))\b").unwrap());
-
-    if let Some(pct) = RE_PERCENT.captures(line) {
-        if let Ok(percent) = pct[1].parse::<f64>() {
-            return Some(ProgressUpdate {
-                percent,
-                speed: RE_SPEED_BW.captures(line).map(|c| c[1].to_string()),
-                eta: RE_ETA.captures(line).map(|c| c[1].to_string()),
-                size: RE_SIZE_BW.captures(line).map(|c| c[1].to_string()),
-            });
-        }
+        once_cell::sync::Lazy::new(|| Regex::new(r"~?([\d.]+)\s*([KMGT]?)i?[Bb]\b").unwrap());
+
+    if let Some(pct) = RE_PERCENT.captures(line)
+        && let Ok(percent) = pct[1].parse::<f64>()
+    {
+        // The size is the expected total; get_iplayer doesn't print the
+        // amount transferred.
+        let total_bytes = RE_SIZE_BW
+            .captures(line)
+            .and_then(|c| Some(c[1].parse::<f64>().ok()? * unit_multiplier(&c[2])));
+        return Some(ProgressUpdate {
+            percent,
+            speed: RE_SPEED_BW.captures(line).map(|c| c[1].to_string()),
+            eta: RE_ETA.captures(line).map(|c| c[1].to_string()),
+            bytes: total_bytes.map(|t| (t * percent / 100.0) as u64),
+            total_bytes: total_bytes.map(|t| t as u64),
+            ..Default::default()
+        });
     }
 
     // ── Format 2: ffmpeg stats line (DASH downloads) ──────────────────────
     //   frame=  123 fps= 25 q=28.0 size=    512kB time=00:00:12.00 bitrate= 350kbps speed=1.2x
+    // ffmpeg doesn't know the total, so percent stays 0 until
+    // `apply_duration` is given the programme's running time.
     static RE_FFMPEG: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
-        Regex::new(r"frame=\s*\d+.*?size=\s*(\d+)kB\s+time=([\d:\.]+).*?speed=\s*([\d.Na/]+)x?")
-            .unwrap()
+        Regex::new(
+            r"frame=\s*\d+.*?size=\s*(\d+)(?:kB|KiB)\s+time=([\d:\.]+).*?speed=\s*([\d.Na/]+)x?",
+        )
+        .unwrap()
     });
 
     if let Some(caps) = RE_FFMPEG.captures(line) {
-        let size_kb: f64 = caps[1].parse().unwrap_or(0.0);
-        let size_str = if size_kb >= 1024.0 * 1024.0 {
-            format!("{:.2} GB", size_kb / 1_048_576.0)
-        } else if size_kb >= 1024.0 {
-            format!("{:.1} MB", size_kb / 1024.0)
-        } else {
-            format!("{:.0} kB", size_kb)
-        };
-        let elapsed = caps[2].to_string();
+        let size_kb: u64 = caps[1].parse().unwrap_or(0);
+        let time = &caps[2];
         let speed = caps[3].to_string();
         return Some(ProgressUpdate {
             percent: 0.0,
+            speed_factor: speed.parse().ok(),
             speed: Some(format!("{speed}x")),
-            eta: Some(elapsed),
-            size: Some(size_str),
+            elapsed: Some(time.split('.').next().unwrap_or(time).to_string()),
+            bytes: Some(size_kb * 1024),
+            position_secs: parse_clock(time),
+            ..Default::default()
         });
     }
 
     None
 }
 
+fn unit_multiplier(prefix: &str) -> f64 {
+    match prefix {
+        "K" | "k" => 1024.0,
+        "M" => 1024.0 * 1024.0,
+        "G" => 1024.0 * 1024.0 * 1024.0,
+        "T" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => 1.0,
+    }
+}
+
+/// `HH:MM:SS(.ss)`, `MM:SS` or plain seconds → seconds.
+fn parse_clock(s: &str) -> Option<f64> {
+    s.trim().split(':').try_fold(0.0, |acc, part| {
+        Some(acc * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+pub fn format_hms(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+/// A programme running time as shown by the BBC or get_iplayer — `1:28:00`,
+/// `58:30`, `3480` (seconds) or `1 hour 28 mins` — in seconds.
+pub fn parse_duration(s: &str) -> Option<i64> {
+    static RE_WORDS: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
+        Regex::new(r"(?i)(\d+)\s*(h(?:ours?|rs?)?|m(?:ins?|inutes?)?|s(?:ecs?|econds?)?)\b")
+            .unwrap()
+    });
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    if let Some(secs) = parse_clock(s) {
+        return Some(secs as i64).filter(|s| *s > 0);
+    }
+    let secs: i64 = RE_WORDS
+        .captures_iter(s)
+        .map(|c| {
+            let n: i64 = c[1].parse().unwrap_or(0);
+            match c[2].chars().next().map(|c| c.to_ascii_lowercase()) {
+                Some('h') => n * 3600,
+                Some('m') => n * 60,
+                _ => n,
+            }
+        })
+        .sum();
+    Some(secs).filter(|s| *s > 0)
+}
+
+/// The running time ffmpeg prints for its input, e.g.
+/// `  Duration: 00:28:45.12, start: 0.000000, bitrate: N/A`.
+fn ffmpeg_duration(line: &str) -> Option<f64> {
+    static RE: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
+        Regex::new(r"^Duration:\s*(\d+:\d{2}:\d{2}(?:\.\d+)?)").unwrap()
+    });
+    parse_clock(&RE.captures(line.trim())?[1]).filter(|d| *d > 0.0)
+}
+
+/// A BBC lookup of the programme's running time, made during a download.
+enum DurationLookup {
+    NotStarted,
+    Running(tokio::task::JoinHandle<Option<i64>>),
+    Done,
+}
+
+/// ffmpeg only says how far it has got. If neither the item nor ffmpeg said
+/// how long the programme is, look it up once, in the background so the
+/// download carries on meanwhile, and use it once it arrives.
+async fn resolve_duration(
+    opts: &DownloadOptions<'_>,
+    progress: &ProgressUpdate,
+    duration: &mut Option<f64>,
+    lookup: &mut DurationLookup,
+) {
+    if duration.is_some() || progress.position_secs.is_none() || progress.percent > 0.0 {
+        return;
+    }
+    match lookup {
+        DurationLookup::NotStarted => {
+            let pid = opts.pid.to_string();
+            let media_type = opts.media_type.to_string();
+            let proxy = opts.proxy.map(str::to_string);
+            *lookup = DurationLookup::Running(tokio::spawn(async move {
+                lookup_duration(&pid, &media_type, proxy.as_deref()).await
+            }));
+        }
+        DurationLookup::Running(task) if task.is_finished() => {
+            *duration = task.await.ok().flatten().map(|d| d as f64);
+            *lookup = DurationLookup::Done;
+        }
+        DurationLookup::Running(_) | DurationLookup::Done => {}
+    }
+}
+
 // ── Download ───────────────────────────────────────────────────────────────────
 
 pub struct DownloadOptions<'a> {
@@ -89,11 +231,148 @@ pub struct DownloadOptions<'a> {
     pub ffmpeg_path: &'a str,
     pub cache_dir: &'a str,
     pub proxy: Option<&'a str>,
+    /// Programme running time, used to turn ffmpeg's position into a
+    /// percentage.
+    pub duration_secs: Option<f64>,
+    /// Receives every line get_iplayer prints.
+    pub log: &'a ItemLog,
+    /// Cancelling this token kills the get_iplayer process and removes any
+    /// partially written files.
+    pub cancel: &'a CancellationToken,
+}
+
+/// How many of get_iplayer's last output lines an [`AttemptReport`] keeps.
+const LOG_TAIL_LINES: usize = 20;
+
+/// How often a progress line is written to the item's log. get_iplayer
+/// prints several a second; the rest only go to the UI.
+const PROGRESS_LOG_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Lines at the end of a failed run searched for the reason it failed.
+const FAILURE_LINES: usize = 10;
+
+/// What one get_iplayer run left behind, whether or not it succeeded.
+#[derive(Debug, Clone, Default)]
+pub struct AttemptReport {
+    /// `None` if the process never started, was killed, or died by signal.
+    pub exit_code: Option<i32>,
+    /// The last non-progress lines get_iplayer printed.
+    pub log_tail: Vec<String>,
+    /// The size of the recorded file, or the last progress reading if the
+    /// run did not finish.
+    pub bytes: Option<i64>,
+    /// The recording mode get_iplayer used, e.g. `hlshd1` or `dashhd2`.
+    pub quality: Option<String>,
+    /// The programme's running time as known by the end of the run: given,
+    /// reported by ffmpeg or looked up.
+    pub duration_secs: Option<f64>,
+}
+
+/// A successful download.
+#[derive(Debug)]
+pub struct Downloaded {
+    /// Where get_iplayer says it wrote the programme; empty if it never said.
+    pub output_path: String,
+    /// Every file get_iplayer said it wrote — the programme, subtitles,
+    /// cover art, metadata — in the order it said so.
+    pub files: Vec<String>,
+    pub report: AttemptReport,
+}
+
+/// A failed download: the kind of failure, classified from get_iplayer's
+/// output, and the lines that explain it.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct DownloadError {
+    pub kind: DownloadErrorKind,
+    pub message: String,
+    pub report: AttemptReport,
+}
+
+impl DownloadError {
+    fn new(kind: DownloadErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            report: AttemptReport::default(),
+        }
+    }
+
+    fn with_report(mut self, report: AttemptReport) -> Self {
+        self.report = report;
+        self
+    }
+}
+
+/// Pick out the recording mode (`hlshd1`, `dashfhd2`, `hlsaachigh1`, …)
+/// get_iplayer reports in its INFO and progress lines.
+fn recording_mode(line: &str) -> Option<String> {
+    static RE: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
+        Regex::new(r"\b((?:hls|dash)(?:aac)?(?:x?fhd|x?hd|x?sd|web|mobile|high|std|med|low)\d*)\b")
+            .unwrap()
+    });
+    RE.captures(line).map(|c| c[1].to_string())
+}
+
+/// Recognise a get_iplayer or ffmpeg output line that explains a failure.
+fn classify_line(line: &str) -> Option<DownloadErrorKind> {
+    static PATTERNS: once_cell::sync::Lazy<Vec<(DownloadErrorKind, Regex)>> =
+        once_cell::sync::Lazy::new(|| {
+            [
+                (
+                    DownloadErrorKind::DiskFull,
+                    r"no space left on device|disk (is )?full|disk quota exceeded",
+                ),
+                (
+                    DownloadErrorKind::FfmpegMissing,
+                    r"ffmpeg.*(not found|does not exist|cannot be found|not installed|is required)|(requires|install) ffmpeg",
+                ),
+                (
+                    DownloadErrorKind::GeoBlocked,
+                    r"geo-?block|outside (of )?the uk|not available in your (area|location|country)",
+                ),
+                (
+                    DownloadErrorKind::NoQuality,
+                    r"no (specified )?recording quality",
+                ),
+                (
+                    DownloadErrorKind::Unavailable,
+                    r"no media streams|no longer available|no programmes are available|(programme|episode) (is )?not (yet|currently) available|has expired|failed to find programme|no matching programmes|programme not found",
+                ),
+                (
+                    DownloadErrorKind::Network,
+                    r"timed? ?out|connection (refused|reset|closed)|could not resolve|name or service not known|temporary failure in name resolution|network is unreachable|ssl connect|\b5\d\d (internal|bad gateway|service unavailable|gateway timeout)",
+                ),
+            ]
+            .into_iter()
+            .map(|(kind, re)| (kind, Regex::new(&format!("(?i){re}")).unwrap()))
+            .collect()
+        });
+    PATTERNS
+        .iter()
+        .find(|(_, re)| re.is_match(line))
+        .map(|(kind, _)| *kind)
+}
+
+/// Decide why a run failed from the last `FAILURE_LINES` lines it printed
+/// (progress aside), where get_iplayer and ffmpeg report what finally went
+/// wrong. The last of those that explains a failure decides. Anything
+/// earlier, such as a programme description or a warning the run got past,
+/// is ignored, and a failure nothing explains stays `Unknown` (retryable).
+fn classify_failure(lines: &[String]) -> DownloadErrorKind {
+    lines[lines.len().saturating_sub(FAILURE_LINES)..]
+        .iter()
+        .rev()
+        .find_map(|l| classify_line(l))
+        .unwrap_or(DownloadErrorKind::Unknown)
 }
 
 /// Runs `get_iplayer` to download a single PID. Calls `on_progress` with each
 /// progress update parsed from stdout/stderr.
-pub async fn download<F>(opts: DownloadOptions<'_>, mut on_progress: F) -> anyhow::Result<String>
+pub async fn download<F>(
+    opts: DownloadOptions<'_>,
+    mut on_progress: F,
+) -> Result<Downloaded, DownloadError>
 where
     F: FnMut(ProgressUpdate) + Send,
 {
@@ -154,17 +433,27 @@ where
         cmd.arg("--subtitles");
     }
 
-    if let Some(proxy) = opts.proxy {
-        if !proxy.is_empty() {
-            cmd.arg("--proxy").arg(proxy);
-        }
+    if let Some(proxy) = opts.proxy
+        && !proxy.is_empty()
+    {
+        cmd.arg("--proxy").arg(proxy);
     }
 
+    // In a process group of its own, so cancelling also stops the ffmpeg it
+    // runs for HLS and DASH streams.
     cmd.stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
+        .process_group(0)
         .kill_on_drop(true);
 
-    let mut child = cmd.spawn().context("spawn get_iplayer")?;
+    let mut child = cmd.spawn().map_err(|e| {
+        let kind = if e.kind() == std::io::ErrorKind::NotFound {
+            DownloadErrorKind::GetIplayerMissing
+        } else {
+            DownloadErrorKind::Unknown
+        };
+        DownloadError::new(kind, format!("spawn get_iplayer: {e}"))
+    })?;
 
     // get_iplayer writes everything (INFO lines, progress lines) to stdout.
     // Progress lines use \r (not \n) for in-place updates, so BufReader::lines()
@@ -215,9 +504,16 @@ where
     let mut stderr_rx = spawn_line_reader(stderr);
 
     let mut output_path = String::new();
+    let mut files: Vec<String> = Vec::new();
     let mut stderr_buf: Vec<String> = Vec::new();
+    let mut quality = None;
+    let mut bytes = None;
+    // Falls back to what ffmpeg reports for its input.
+    let mut duration = opts.duration_secs;
     let mut stderr_done = false;
     let mut stdout_done = false;
+    let mut progress_logged = None;
+    let mut duration_lookup = DurationLookup::NotStarted;
 
     loop {
         if stderr_done && stdout_done {
@@ -227,20 +523,28 @@ where
             msg = stdout_rx.recv(), if !stdout_done => {
                 match msg {
                     Some(l) => {
-                        if let Some(progress) = parse_progress_line(&l) {
+                        let progress = parse_progress_line(&l);
+                        if progress.is_none() || progress_due(&mut progress_logged) {
+                            opts.log.write(Stream::Stdout, &l).await;
+                        }
+                        quality = recording_mode(&l).or(quality);
+                        if let Some(mut progress) = progress {
+                            resolve_duration(&opts, &progress, &mut duration, &mut duration_lookup).await;
+                            if let Some(d) = duration {
+                                progress.apply_duration(d);
+                            }
                             tracing::info!(
                                 "[get_iplayer] progress: {:.1}% speed={} eta={}",
                                 progress.percent,
                                 progress.speed.as_deref().unwrap_or("-"),
                                 progress.eta.as_deref().unwrap_or("-"),
                             );
+                            bytes = progress.bytes.map(|b| b as i64).or(bytes);
                             on_progress(progress);
                         } else {
                             tracing::info!("[get_iplayer] {l}");
-                            // get_iplayer prints e.g. "INFO: Recorded /downloads/Episode.mp4"
-                            if let Some(path) = extract_output_path(&l) {
-                                output_path = path;
-                            }
+                            duration = duration.or_else(|| ffmpeg_duration(&l));
+                            note_artefact(&l, &mut output_path, &mut files);
                             stderr_buf.push(l);
                             if stderr_buf.len() > 50 {
                                 stderr_buf.remove(0);
@@ -253,16 +557,28 @@ where
             msg = stderr_rx.recv(), if !stderr_done => {
                 match msg {
                     Some(l) => {
-                        if let Some(progress) = parse_progress_line(&l) {
+                        let progress = parse_progress_line(&l);
+                        if progress.is_none() || progress_due(&mut progress_logged) {
+                            opts.log.write(Stream::Stderr, &l).await;
+                        }
+                        quality = recording_mode(&l).or(quality);
+                        if let Some(mut progress) = progress {
+                            resolve_duration(&opts, &progress, &mut duration, &mut duration_lookup).await;
+                            if let Some(d) = duration {
+                                progress.apply_duration(d);
+                            }
                             tracing::info!(
                                 "[get_iplayer] progress: {:.1}% speed={} eta={}",
                                 progress.percent,
                                 progress.speed.as_deref().unwrap_or("-"),
                                 progress.eta.as_deref().unwrap_or("-"),
                             );
+                            bytes = progress.bytes.map(|b| b as i64).or(bytes);
                             on_progress(progress);
                         } else {
                             tracing::info!("[get_iplayer stderr] {l}");
+                            duration = duration.or_else(|| ffmpeg_duration(&l));
+                            note_artefact(&l, &mut output_path, &mut files);
                             stderr_buf.push(l);
                             if stderr_buf.len() > 50 {
                                 stderr_buf.remove(0);
@@ -272,10 +588,46 @@ where
                     None => { stderr_done = true; }
                 }
             }
+            _ = opts.cancel.cancelled() => {
+                tracing::info!("Cancelling get_iplayer for PID {}", opts.pid);
+                if let Err(e) = hooks::kill_group(&mut child).await {
+                    tracing::warn!("Failed to kill get_iplayer for PID {}: {e}", opts.pid);
+                }
+                remove_partial_files(opts.output_dir, opts.pid).await;
+                return Err(DownloadError::new(
+                    DownloadErrorKind::Cancelled,
+                    format!("download of PID {} was cancelled", opts.pid),
+                )
+                .with_report(AttemptReport {
+                    exit_code: None,
+                    log_tail: tail(&stderr_buf),
+                    bytes,
+                    quality,
+                    duration_secs: duration,
+                }));
+            }
         }
     }
 
-    let status = child.wait().await.context("wait for get_iplayer")?;
+    let status = child.wait().await.map_err(|e| {
+        DownloadError::new(
+            DownloadErrorKind::Unknown,
+            format!("wait for get_iplayer: {e}"),
+        )
+    })?;
+    if duration.is_none()
+        && let DurationLookup::Running(task) = duration_lookup
+        && task.is_finished()
+    {
+        duration = task.await.ok().flatten().map(|d| d as f64);
+    }
+    let mut report = AttemptReport {
+        exit_code: status.code(),
+        log_tail: tail(&stderr_buf),
+        bytes,
+        quality,
+        duration_secs: duration,
+    };
     if !status.success() {
         let detail = stderr_buf
             .iter()
@@ -306,22 +658,100 @@ where
         } else {
             detail.join("\n")
         };
-        bail!(
-            "get_iplayer exited with status {} for PID {}\n{}",
-            status.code().unwrap_or(-1),
-            opts.pid,
-            detail_str,
-        );
+        return Err(DownloadError::new(
+            classify_failure(&stderr_buf),
+            format!(
+                "get_iplayer exited with status {} for PID {}\n{}",
+                status.code().unwrap_or(-1),
+                opts.pid,
+                detail_str,
+            ),
+        )
+        .with_report(report));
     }
 
-    Ok(output_path)
+    if !output_path.is_empty()
+        && let Ok(meta) = tokio::fs::metadata(&output_path).await
+    {
+        report.bytes = Some(meta.len() as i64);
+    }
+    Ok(Downloaded {
+        output_path,
+        files,
+        report,
+    })
 }
 
-fn extract_output_path(line: &str) -> Option<String> {
-    static RE: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
-        Regex::new(r"(?:INFO:|Recorded)\s+(.+\.(?:mp4|m4v|mp3|m4a|aac|ts))").unwrap()
+/// Whether a progress line is due to be logged, given when the last one was.
+fn progress_due(last: &mut Option<std::time::Instant>) -> bool {
+    let now = std::time::Instant::now();
+    if last.is_some_and(|t| now.duration_since(t) < PROGRESS_LOG_INTERVAL) {
+        return false;
+    }
+    *last = Some(now);
+    true
+}
+
+fn tail(lines: &[String]) -> Vec<String> {
+    lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].to_vec()
+}
+
+/// Delete get_iplayer's `.partial.*` working files for `pid` in `output_dir`.
+///
+/// get_iplayer's default file prefix always contains the PID. Only working
+/// files are touched: a finished recording of the same programme, or any
+/// other file that happens to mention the PID, is left alone.
+async fn remove_partial_files(output_dir: &str, pid: &str) {
+    let mut entries = match tokio::fs::read_dir(output_dir).await {
+        Ok(e) => e,
+        Err(e) => {
+            tracing::warn!("Could not scan {output_dir} for partial files: {e}");
+            return;
+        }
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !name.contains(pid) || !name.contains(".partial.") {
+            continue;
+        }
+        let path = entry.path();
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => tracing::info!("Removed partial file {}", path.display()),
+            Err(e) => tracing::warn!("Could not remove partial file {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Remember a file get_iplayer says it wrote, e.g.
+/// `INFO: Recorded /downloads/Episode.mp4` for the programme itself or
+/// `INFO: Downloaded Thumbnail to '/downloads/Episode.jpg'` for an artefact.
+/// The last recording mentioned, or failing that the first media file,
+/// becomes `output_path`.
+fn note_artefact(line: &str, output_path: &mut String, files: &mut Vec<String>) {
+    static RECORDED: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
+        Regex::new(r"^(?:INFO:\s+)?Recorded\s+'?(/.+?)'?\s*$").unwrap()
     });
-    RE.captures(line).map(|c| c[1].trim().to_string())
+    static WROTE: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
+        Regex::new(r"^INFO:\s+(?:Recorded|Downloaded)\s+[\w ]+?\s+to\s+'?(/.+?)'?\s*$").unwrap()
+    });
+
+    let (path, recorded) = if let Some(c) = RECORDED.captures(line) {
+        (c[1].to_string(), true)
+    } else if let Some(c) = WROTE.captures(line) {
+        (c[1].to_string(), false)
+    } else {
+        return;
+    };
+    if recorded
+        || (output_path.is_empty()
+            && artefacts::kind(std::path::Path::new(&path)) == ArtefactKind::Media)
+    {
+        *output_path = path.clone();
+    }
+    if !files.contains(&path) {
+        files.push(path);
+    }
 }
 
 // ── Search ────────────────────────────────────────────────────────────────────
@@ -360,10 +790,10 @@ pub async fn list_episodes(opts: EpisodesOptions<'_>) -> anyhow::Result<Vec<Sear
         .stderr(std::process::Stdio::piped())
         .kill_on_drop(true);
 
-    if let Some(p) = opts.proxy {
-        if !p.is_empty() {
-            cmd.arg("--proxy").arg(p);
-        }
+    if let Some(p) = opts.proxy
+        && !p.is_empty()
+    {
+        cmd.arg("--proxy").arg(p);
     }
 
     let out = cmd
@@ -486,7 +916,7 @@ fn parse_pid_recursive_output(
 }
 
 /// BBC PIDs are 8 chars: one letter (usually b or p) followed by 7 lowercase alphanumerics.
-fn extract_pid(input: &str) -> Option<String> {
+pub fn extract_pid(input: &str) -> Option<String> {
     static RE_PID: once_cell::sync::Lazy<Regex> =
         once_cell::sync::Lazy::new(|| Regex::new(r"(?:/|^)([bpm][0-9a-z]{7})(?:[/?#]|$)").unwrap());
     RE_PID.captures(input).map(|c| c[1].to_string())
@@ -566,17 +996,17 @@ fn collect_redux_images(
 ) {
     match v {
         serde_json::Value::Object(obj) => {
-            if let (Some(id_val), Some(images_val)) = (obj.get("id"), obj.get("images")) {
-                if let (Some(pid), Some(imgs)) = (id_val.as_str(), images_val.as_object()) {
-                    // Prefer "standard"; fall back to any image field
-                    let url = imgs
-                        .get("standard")
-                        .or_else(|| imgs.get("promotional_with_logo"))
-                        .or_else(|| imgs.values().next())
-                        .and_then(|u| u.as_str());
-                    if let Some(raw) = url {
-                        map.insert(pid.to_string(), raw.replace("{recipe}", "480x270"));
-                    }
+            if let (Some(id_val), Some(images_val)) = (obj.get("id"), obj.get("images"))
+                && let (Some(pid), Some(imgs)) = (id_val.as_str(), images_val.as_object())
+            {
+                // Prefer "standard"; fall back to any image field
+                let url = imgs
+                    .get("standard")
+                    .or_else(|| imgs.get("promotional_with_logo"))
+                    .or_else(|| imgs.values().next())
+                    .and_then(|u| u.as_str());
+                if let Some(raw) = url {
+                    map.insert(pid.to_string(), raw.replace("{recipe}", "480x270"));
                 }
             }
             for val in obj.values() {
@@ -673,6 +1103,7 @@ fn parse_bbc_search_html(html: &str, media_type: &str) -> Vec<SearchResult> {
             series: None,
             channel: None,
             thumbnail_url,
+            duration_secs: duration.as_deref().and_then(parse_duration),
             duration,
             description,
             media_type: media_type.to_string(),
@@ -702,10 +1133,10 @@ async fn bbc_web_search(
              Chrome/124.0.0.0 Safari/537.36",
         );
 
-    if let Some(p) = proxy {
-        if !p.is_empty() {
-            builder = builder.proxy(reqwest::Proxy::all(p)?);
-        }
+    if let Some(p) = proxy
+        && !p.is_empty()
+    {
+        builder = builder.proxy(reqwest::Proxy::all(p)?);
     }
 
     let client = builder.build()?;
@@ -866,10 +1297,10 @@ async fn get_series_label(pid: &str, proxy: Option<&str>) -> SeriesInfo {
                  AppleWebKit/537.36 (KHTML, like Gecko) \
                  Chrome/124.0.0.0 Safari/537.36",
             );
-        if let Some(p) = proxy {
-            if !p.is_empty() {
-                builder = builder.proxy(reqwest::Proxy::all(p).ok()?);
-            }
+        if let Some(p) = proxy
+            && !p.is_empty()
+        {
+            builder = builder.proxy(reqwest::Proxy::all(p).ok()?);
         }
         let client = builder.build().ok()?;
         let url = format!("https://www.bbc.co.uk/programmes/{pid}.json");
@@ -922,10 +1353,10 @@ async fn lookup_pid_api(
              AppleWebKit/537.36 (KHTML, like Gecko) \
              Chrome/124.0.0.0 Safari/537.36",
         );
-    if let Some(p) = proxy {
-        if !p.is_empty() {
-            builder = builder.proxy(reqwest::Proxy::all(p)?);
-        }
+    if let Some(p) = proxy
+        && !p.is_empty()
+    {
+        builder = builder.proxy(reqwest::Proxy::all(p)?);
     }
     let client = builder.build()?;
 
@@ -1001,12 +1432,24 @@ async fn lookup_pid_api(
         channel,
         thumbnail_url,
         duration,
+        duration_secs: prog["duration"].as_i64(),
         description,
         media_type: media_type.to_string(),
         ..Default::default()
     }])
 }
 
+/// The running time of episode `pid` according to the BBC Programmes API.
+async fn lookup_duration(pid: &str, media_type: &str, proxy: Option<&str>) -> Option<i64> {
+    match lookup_pid_api(pid, media_type, proxy).await {
+        Ok(results) => results.first()?.duration_secs,
+        Err(e) => {
+            tracing::debug!("Could not look up the duration of {pid}: {e:#}");
+            None
+        }
+    }
+}
+
 async fn search_by_pid(
     pid: &str,
     media_type: &str,
@@ -1026,16 +1469,18 @@ async fn search_by_pid(
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .kill_on_drop(true);
-    if let Some(p) = proxy {
-        if !p.is_empty() {
-            cmd.arg("--proxy").arg(p);
-        }
+    if let Some(p) = proxy
+        && !p.is_empty()
+    {
+        cmd.arg("--proxy").arg(p);
     }
     let out = cmd.output().await.context("spawn get_iplayer --pid")?;
     parse_get_iplayer_output(&out.stdout, &out.stderr, media_type)
 }
 
-async fn search_local_cache(opts: SearchOptions<'_>) -> anyhow::Result<Vec<SearchResult>> {
+/// Search get_iplayer's local programme cache. `query` is a get_iplayer
+/// (Perl) regex matched against names, episodes and descriptions.
+pub async fn search_local_cache(opts: SearchOptions<'_>) -> anyhow::Result<Vec<SearchResult>> {
     let mut cmd = Command::new(opts.get_iplayer_path);
     cmd.arg("--profile-dir")
         .arg(opts.cache_dir)
@@ -1049,10 +1494,10 @@ async fn search_local_cache(opts: SearchOptions<'_>) -> anyhow::Result<Vec<Searc
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .kill_on_drop(true);
-    if let Some(p) = opts.proxy {
-        if !p.is_empty() {
-            cmd.arg("--proxy").arg(p);
-        }
+    if let Some(p) = opts.proxy
+        && !p.is_empty()
+    {
+        cmd.arg("--proxy").arg(p);
     }
     let out = cmd.output().await.context("spawn get_iplayer search")?;
     parse_get_iplayer_output(&out.stdout, &out.stderr, opts.media_type)
@@ -1106,6 +1551,7 @@ fn parse_get_iplayer_output(
                 .get(6)
                 .filter(|s| !s.is_empty())
                 .map(|s| s.trim().to_string()),
+            duration_secs: parts.get(6).and_then(|s| parse_duration(s)),
             description: parts
                 .get(7)
                 .filter(|s| !s.is_empty())
@@ -1122,9 +1568,10 @@ pub async fn refresh_cache(
     get_iplayer_path: &str,
     media_type: &str,
     cache_dir: &str,
+    proxy: Option<&str>,
 ) -> anyhow::Result<()> {
-    let status = Command::new(get_iplayer_path)
-        .arg("--profile-dir")
+    let mut cmd = Command::new(get_iplayer_path);
+    cmd.arg("--profile-dir")
         .arg(cache_dir)
         .arg("--refresh")
         .arg("--type")
@@ -1132,10 +1579,13 @@ pub async fn refresh_cache(
         // Include upcoming/future schedule feeds (many more programmes)
         .arg("--refresh-future")
         // Re-fetch even recently-cached feeds
-        .arg("--force")
-        .status()
-        .await
-        .context("run get_iplayer --refresh")?;
+        .arg("--force");
+    if let Some(p) = proxy
+        && !p.is_empty()
+    {
+        cmd.arg("--proxy").arg(p);
+    }
+    let status = cmd.status().await.context("run get_iplayer --refresh")?;
 
     if !status.success() {
         bail!(
@@ -1145,3 +1595,113 @@ pub async fn refresh_cache(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(ls: &[&str]) -> Vec<String> {
+        ls.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn classifies_known_failures() {
+        let cases = [
+            (
+                "av_interleaved_write_frame(): No space left on device",
+                DownloadErrorKind::DiskFull,
+            ),
+            (
+                "WARNING: Required ffmpeg utility not found",
+                DownloadErrorKind::FfmpegMissing,
+            ),
+            (
+                "ERROR: This programme is not available in your location",
+                DownloadErrorKind::GeoBlocked,
+            ),
+            (
+                "WARNING: No specified recording quality available",
+                DownloadErrorKind::NoQuality,
+            ),
+            (
+                "ERROR: Failed to find programme b0123456",
+                DownloadErrorKind::Unavailable,
+            ),
+            (
+                "WARNING: Programme is not yet available",
+                DownloadErrorKind::Unavailable,
+            ),
+            ("ERROR: Connection timed out", DownloadErrorKind::Network),
+            (
+                "ERROR: HTTP 503 Service Unavailable",
+                DownloadErrorKind::Network,
+            ),
+        ];
+        for (line, kind) in cases {
+            assert_eq!(classify_line(line), Some(kind), "{line}");
+        }
+    }
+
+    #[test]
+    fn ignores_ordinary_output() {
+        for line in [
+            "INFO: Recorded /downloads/Show_-_Episode_b0123456_original.mp4",
+            "desc: The best drama not yet available on DVD, in full HD quality",
+            "INFO: Audio quality is not available in 5.1 for this programme",
+            "INFO: 1 matching programmes",
+        ] {
+            assert_eq!(classify_line(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn failure_is_classified_from_the_final_lines() {
+        let mut output = lines(&["ERROR: This programme is not available in your location"]);
+        output.extend((0..FAILURE_LINES).map(|i| format!("INFO: line {i}")));
+        assert_eq!(classify_failure(&output), DownloadErrorKind::Unknown);
+
+        output.push("ERROR: Connection reset by peer".to_string());
+        assert_eq!(classify_failure(&output), DownloadErrorKind::Network);
+
+        let output = lines(&[
+            "WARNING: Connection timed out, retrying",
+            "ERROR: Failed to find programme b0123456",
+            "INFO: See the log for details",
+        ]);
+        assert_eq!(classify_failure(&output), DownloadErrorKind::Unavailable);
+    }
+
+    #[test]
+    fn notes_only_the_files_get_iplayer_wrote() {
+        let (mut output_path, mut files) = (String::new(), Vec::new());
+        for line in [
+            "INFO: Downloaded Subtitles to '/downloads/Show.srt'",
+            "INFO: Downloaded Thumbnail to '/downloads/Show.jpg'",
+            "INFO: Recorded /downloads/Show.mp4",
+            "INFO: Recorded metadata to '/downloads/Show.xml'",
+            "INFO: Removed existing file '/downloads/Other.mp4'",
+            "INFO: Downloading tv: 'Show (b0123456)' (hlshd1/cf)",
+            "INFO: Using cached copy of /downloads/Old.mp4",
+        ] {
+            note_artefact(line, &mut output_path, &mut files);
+        }
+        assert_eq!(output_path, "/downloads/Show.mp4");
+        assert_eq!(
+            files,
+            [
+                "/downloads/Show.srt",
+                "/downloads/Show.jpg",
+                "/downloads/Show.mp4",
+                "/downloads/Show.xml",
+            ]
+        );
+    }
+
+    #[test]
+    fn unexplained_failures_are_retried() {
+        assert!(!DownloadErrorKind::Unknown.is_permanent());
+        assert!(!DownloadErrorKind::Network.is_permanent());
+        assert!(DownloadErrorKind::Unavailable.is_permanent());
+        assert_eq!(classify_failure(&[]), DownloadErrorKind::Unknown);
+    }
+}
@@ -0,0 +1,360 @@
+/// Downloaded-library index.
+///
+/// `library_files` mirrors the recordings under `output_dir`. A scan walks
+/// the directory tree, probes new or changed media files with ffprobe and
+/// drops entries whose file has gone. Each finished download is indexed on
+/// its own as soon as it completes, so a full scan is only needed at startup
+/// or after files are changed by hand.
+///
+/// Programme, series and episode come from the queue item that produced the
+/// file when there is one, and otherwise from get_iplayer's default
+/// `<name>_-_<episode>_<pid>_<version>` file name.
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tracing::{info, warn};
+
+use crate::{
+    artefacts,
+    db::Db,
+    media,
+    models::{LibraryFile, LibraryScanSummary, QueueItem},
+    settings::RuntimeSettings,
+};
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mkv", "ts"];
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac"];
+pub(crate) const SIDECAR_EXTENSIONS: &[&str] = &["srt", "ttml", "vtt", "ass"];
+
+/// Only one scan walks the disk at a time.
+static SCAN_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+// ── Scanning ───────────────────────────────────────────────────────────────────
+
+/// Bring the index in line with the files under `output_dir`.
+pub async fn scan(db: &Db, settings: &RuntimeSettings) -> anyhow::Result<LibraryScanSummary> {
+    let _guard = SCAN_LOCK.lock().await;
+    let root = Path::new(&settings.output_dir);
+    let files = walk(root).await?;
+
+    let known: HashMap<String, (i64, String)> = sqlx::query_as::<_, (String, i64, String)>(
+        "SELECT path, size, modified_at FROM library_files",
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|(path, size, modified)| (path, (size, modified)))
+    .collect();
+
+    let mut summary = LibraryScanSummary::default();
+    let mut present = HashSet::new();
+    for path in files.iter().filter(|p| media_type(p).is_some()) {
+        let key = path.to_string_lossy().into_owned();
+        let sidecars = sidecars_for(path, &files);
+        match index(db, settings, path, sidecars, known.get(&key)).await {
+            Ok(changed) => {
+                summary.indexed += usize::from(changed);
+                summary.total += 1;
+            }
+            Err(e) => warn!("Library: could not index {key}: {e:#}"),
+        }
+        present.insert(key);
+    }
+
+    for path in known.keys().filter(|p| !present.contains(*p)) {
+        sqlx::query("DELETE FROM library_files WHERE path=?")
+            .bind(path)
+            .execute(db)
+            .await?;
+        summary.removed += 1;
+    }
+
+    summary.damaged_artefacts = artefacts::count_damaged(db).await?;
+    if summary.damaged_artefacts > 0 {
+        warn!(
+            "Library: {} downloaded file(s) are missing or have changed size",
+            summary.damaged_artefacts
+        );
+    }
+
+    info!(
+        "Library scan of {}: {} file(s), {} indexed, {} removed",
+        root.display(),
+        summary.total,
+        summary.indexed,
+        summary.removed
+    );
+    Ok(summary)
+}
+
+/// Index a single media file, e.g. one that a download just produced.
+pub async fn index_file(db: &Db, settings: &RuntimeSettings, path: &Path) -> anyhow::Result<()> {
+    if media_type(path).is_none() {
+        return Ok(());
+    }
+    let sidecars = subtitle_sidecars(path).await?;
+    index(db, settings, path, sidecars, None).await?;
+    Ok(())
+}
+
+/// The subtitle files saved next to the recording at `path`.
+pub async fn subtitle_sidecars(path: &Path) -> std::io::Result<Vec<String>> {
+    let mut siblings = Vec::new();
+    if let Some(dir) = path.parent() {
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            siblings.push(entry.path());
+        }
+    }
+    Ok(sidecars_for(path, &siblings))
+}
+
+/// Insert or refresh the entry for `path`. The file is only probed when it
+/// is new or its size or modification time differ from `known`. Returns
+/// whether it was probed.
+async fn index(
+    db: &Db,
+    settings: &RuntimeSettings,
+    path: &Path,
+    sidecars: Vec<String>,
+    known: Option<&(i64, String)>,
+) -> anyhow::Result<bool> {
+    let meta = tokio::fs::metadata(path).await?;
+    let size = meta.len() as i64;
+    let modified = meta
+        .modified()
+        .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339())
+        .unwrap_or_default();
+    let key = path.to_string_lossy().into_owned();
+    let sidecars = serde_json::to_string(&sidecars)?;
+
+    if known.is_some_and(|(s, m)| *s == size && *m == modified) {
+        sqlx::query("UPDATE library_files SET sidecars=? WHERE path=?")
+            .bind(&sidecars)
+            .bind(&key)
+            .execute(db)
+            .await?;
+        return Ok(false);
+    }
+
+    let info = media::probe(&settings.ffmpeg_path, path)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Library: ffprobe failed for {key}: {e:#}");
+            media::MediaInfo::default()
+        });
+
+    let source: Option<QueueItem> = sqlx::query_as(
+        "SELECT * FROM queue_items WHERE output_path=? ORDER BY completed_at DESC LIMIT 1",
+    )
+    .bind(&key)
+    .fetch_optional(db)
+    .await?;
+    let (queue_item_id, programme, series, episode, pid) = match source {
+        Some(item) => (
+            Some(item.id),
+            item.title,
+            item.series,
+            item.episode,
+            Some(item.pid),
+        ),
+        None => {
+            let parsed = parse_file_name(path);
+            (
+                None,
+                parsed.programme,
+                parsed.series,
+                parsed.episode,
+                parsed.pid,
+            )
+        }
+    };
+
+    sqlx::query(
+        "INSERT INTO library_files \
+         (id, path, programme, series, episode, pid, media_type, size, modified_at, \
+          duration_secs, video_codec, audio_codec, width, height, audio_channels, \
+          embedded_subtitles, sidecars, queue_item_id, indexed_at) \
+         VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,datetime('now')) \
+         ON CONFLICT(path) DO UPDATE SET \
+          programme=excluded.programme, series=excluded.series, episode=excluded.episode, \
+          pid=excluded.pid, media_type=excluded.media_type, size=excluded.size, \
+          modified_at=excluded.modified_at, duration_secs=excluded.duration_secs, \
+          video_codec=excluded.video_codec, audio_codec=excluded.audio_codec, \
+          width=excluded.width, height=excluded.height, \
+          audio_channels=excluded.audio_channels, \
+          embedded_subtitles=excluded.embedded_subtitles, sidecars=excluded.sidecars, \
+          queue_item_id=excluded.queue_item_id, indexed_at=excluded.indexed_at",
+    )
+    .bind(LibraryFile::new_id())
+    .bind(&key)
+    .bind(&programme)
+    .bind(&series)
+    .bind(&episode)
+    .bind(&pid)
+    .bind(media_type(path).unwrap_or("tv"))
+    .bind(size)
+    .bind(&modified)
+    .bind(info.duration_secs)
+    .bind(&info.video_codec)
+    .bind(&info.audio_codec)
+    .bind(info.width)
+    .bind(info.height)
+    .bind(info.audio_channels)
+    .bind(info.embedded_subtitles)
+    .bind(&sidecars)
+    .bind(&queue_item_id)
+    .execute(db)
+    .await?;
+    Ok(true)
+}
+
+/// Delete a recording, its subtitle sidecars and every other file the
+/// download left with it (`file.artefacts`) from disk and the index. Files
+/// that are already gone are not an error.
+pub async fn delete_file(db: &Db, file: &LibraryFile) -> anyhow::Result<()> {
+    let mut paths = vec![&file.path];
+    paths.extend(&file.sidecars);
+    paths.extend(file.artefacts.iter().map(|a| &a.path));
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => info!("Deleted {path}"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("delete {path}")),
+        }
+        sqlx::query("DELETE FROM queue_item_files WHERE path=?")
+            .bind(path)
+            .execute(db)
+            .await?;
+    }
+    sqlx::query("DELETE FROM library_files WHERE id=?")
+        .bind(&file.id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+// ── Helpers ────────────────────────────────────────────────────────────────────
+
+/// Every regular file under `root`. Hidden files and directories are skipped.
+async fn walk(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(e) => e,
+            Err(e) if dir == root => {
+                return Err(e).with_context(|| format!("read {}", root.display()));
+            }
+            Err(e) => {
+                warn!("Library: skipping {}: {e}", dir.display());
+                continue;
+            }
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// `tv` for video files, `radio` for audio files, `None` for anything else.
+/// get_iplayer's `.partial.` working files are never media.
+pub(crate) fn media_type(path: &Path) -> Option<&'static str> {
+    if path.to_string_lossy().contains(".partial.") {
+        return None;
+    }
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    if VIDEO_EXTENSIONS.contains(&ext.as_str()) {
+        Some("tv")
+    } else if AUDIO_EXTENSIONS.contains(&ext.as_str()) {
+        Some("radio")
+    } else {
+        None
+    }
+}
+
+/// Subtitle files in `candidates` that share `media`'s directory and stem.
+fn sidecars_for(media: &Path, candidates: &[PathBuf]) -> Vec<String> {
+    let Some(stem) = media.file_stem() else {
+        return Vec::new();
+    };
+    let mut sidecars: Vec<String> = candidates
+        .iter()
+        .filter(|c| c.parent() == media.parent() && c.file_stem() == Some(stem))
+        .filter(|c| {
+            c.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| SIDECAR_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        })
+        .map(|c| c.to_string_lossy().into_owned())
+        .collect();
+    sidecars.sort();
+    sidecars
+}
+
+struct ParsedName {
+    programme: String,
+    series: Option<String>,
+    episode: Option<String>,
+    pid: Option<String>,
+}
+
+/// Recover programme details from a get_iplayer default file name such as
+/// `Doctor_Who_-_Series_1_-_1._Rose_b0074dlc_original.mp4`.
+fn parse_file_name(path: &Path) -> ParsedName {
+    static DEFAULT_NAME: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(
+            r"^(?P<name>.+?)(?:_-_(?P<episode>.+?))?_(?P<pid>[b-df-hj-np-tv-z0-9]{8})_[a-z]+$",
+        )
+        .unwrap()
+    });
+    static SERIES: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^(?P<series>Series \d+)(?::| -) (?P<episode>.+)$").unwrap());
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let Some(caps) = DEFAULT_NAME.captures(&stem) else {
+        return ParsedName {
+            programme: stem.replace('_', " "),
+            series: None,
+            episode: None,
+            pid: None,
+        };
+    };
+
+    let programme = caps["name"].replace('_', " ");
+    let pid = Some(caps["pid"].to_string());
+    let episode = caps.name("episode").map(|e| e.as_str().replace('_', " "));
+    match episode.as_deref().and_then(|e| SERIES.captures(e)) {
+        Some(parts) => ParsedName {
+            programme,
+            series: Some(parts["series"].to_string()),
+            episode: Some(parts["episode"].to_string()),
+            pid,
+        },
+        None => ParsedName {
+            programme,
+            series: None,
+            episode,
+            pid,
+        },
+    }
+}
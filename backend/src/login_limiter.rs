@@ -0,0 +1,110 @@
+/// Brute-force protection for `POST /api/auth/login`.
+///
+/// Failed logins are counted per username and per client IP. Once either
+/// count passes its allowance, every further failure locks that key out for
+/// twice as long as the last, up to `MAX_LOCKOUT`. A successful login clears
+/// the username's count; the IP's only decays, so one known password can't
+/// be used to keep guessing at others. Counts live in memory and reset on
+/// restart.
+///
+/// An attempt is counted as a failure when it starts, in the same step as
+/// the lockout check, and handed back if it succeeds. Checking first and
+/// counting once the password hash has been verified would let a burst of
+/// parallel guesses all pass the check before any of them was counted.
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Failures allowed per username before lockouts start.
+const USERNAME_ALLOWANCE: u32 = 5;
+/// Failures allowed per IP; higher, since a household or office shares one.
+const IP_ALLOWANCE: u32 = 20;
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// A key with no failures for this long starts again from zero.
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+struct Entry {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct LoginLimiter {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl LoginLimiter {
+    /// Start a login attempt. `Err(remaining)` if the username or IP is
+    /// locked out right now. Otherwise the attempt is counted as a failure
+    /// until `record_success` says otherwise, and the lockout that failure
+    /// triggers, if any, is returned.
+    pub fn begin(&self, username: &str, ip: IpAddr) -> Result<Option<Duration>, Duration> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let remaining = keys(username, ip)
+            .iter()
+            .filter_map(|(key, _)| entries.get(key)?.locked_until)
+            .filter_map(|until| until.checked_duration_since(now))
+            .max();
+        match remaining {
+            Some(d) if !d.is_zero() => return Err(d),
+            _ => {}
+        }
+        entries.retain(|_, e| now.duration_since(e.last_failure) < FORGET_AFTER);
+
+        let mut lockout = None;
+        for (key, allowance) in keys(username, ip) {
+            let entry = entries.entry(key).or_insert(Entry {
+                failures: 0,
+                last_failure: now,
+                locked_until: None,
+            });
+            entry.failures += 1;
+            entry.last_failure = now;
+            if entry.failures > allowance {
+                let excess = (entry.failures - allowance).min(16);
+                let duration = Duration::from_secs(1 << excess).min(MAX_LOCKOUT);
+                entry.locked_until = Some(now + duration);
+                lockout = lockout.max(Some(duration));
+            }
+        }
+        Ok(lockout)
+    }
+
+    /// Take back the failure `begin` counted, e.g. for a correct password
+    /// that still needs a two-factor code.
+    pub fn undo(&self, username: &str, ip: IpAddr) {
+        let mut entries = self.entries.lock().unwrap();
+        for (key, allowance) in keys(username, ip) {
+            if let Some(entry) = entries.get_mut(&key) {
+                entry.failures = entry.failures.saturating_sub(1);
+                if entry.failures <= allowance {
+                    entry.locked_until = None;
+                }
+            }
+        }
+    }
+
+    /// The login succeeded: take back its attempt and forget the username's
+    /// failures.
+    pub fn record_success(&self, username: &str, ip: IpAddr) {
+        self.undo(username, ip);
+        self.entries.lock().unwrap().remove(&username_key(username));
+    }
+}
+
+fn username_key(username: &str) -> String {
+    format!("user:{}", username.to_lowercase())
+}
+
+fn keys(username: &str, ip: IpAddr) -> [(String, u32); 2] {
+    [
+        (username_key(username), USERNAME_ALLOWANCE),
+        (format!("ip:{ip}"), IP_ALLOWANCE),
+    ]
+}
@@ -1,14 +1,28 @@
+mod artefacts;
+mod audit;
 mod auth;
 mod config;
 mod db;
+mod download_log;
 mod error;
+mod hooks;
 mod iplayer;
+mod library;
+mod login_limiter;
+mod media;
 mod models;
+mod naming;
+mod postprocess;
 mod queue;
 mod routes;
+mod settings;
 mod state;
+mod subscriptions;
+mod two_factor;
+mod watch_rules;
+mod webhooks;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use tokio::sync::broadcast;
 use tracing::info;
@@ -32,29 +46,50 @@ async fn main() -> anyhow::Result<()> {
     let config = Arc::new(config);
     info!("Starting tapedeck, binding to {}", config.bind);
 
-    // Ensure output directory exists
-    tokio::fs::create_dir_all(&config.output_dir).await?;
-
     // ── Database ─────────────────────────────────────────────────────────────
     let db = db::connect(&config).await?;
     db::seed_admin(&db, &config).await?;
 
+    // ── Runtime settings (DB overrides env) ───────────────────────────────────
+    let settings = settings::SettingsService::load(db.clone(), Arc::clone(&config)).await?;
+
+    // Ensure output directory exists
+    tokio::fs::create_dir_all(&settings.current().output_dir).await?;
+
     // ── WebSocket broadcast channel ───────────────────────────────────────────
     let (events_tx, _) = broadcast::channel::<WsEvent>(256);
 
+    // ── Per-item download logs ────────────────────────────────────────────────
+    let download_logs = download_log::DownloadLogs::new(&config);
+    download_logs.prune(&db).await;
+
+    // ── Outgoing webhooks ─────────────────────────────────────────────────────
+    let webhooks = webhooks::Webhooks::start(db.clone());
+
     // ── Download worker pool ──────────────────────────────────────────────────
-    let queue = queue::start_worker_pool(db.clone(), Arc::clone(&config), events_tx.clone());
+    let queue = queue::start_worker_pool(
+        db.clone(),
+        Arc::clone(&config),
+        settings.clone(),
+        events_tx.clone(),
+        download_logs.clone(),
+        webhooks.clone(),
+    );
 
     // ── Application state ─────────────────────────────────────────────────────
     let state = AppState {
         db,
         config: Arc::clone(&config),
+        settings: settings.clone(),
         queue,
         events: events_tx,
+        download_logs,
+        login_limiter: Arc::default(),
+        webhooks,
     };
 
     // ── Scheduled-item watcher ────────────────────────────────────────────────
-    // Every minute, check for items whose scheduled_at has passed and enqueue them.
+    // Every minute, check for items whose scheduled_at has passed and wake the scheduler.
     {
         let state_clone = state.clone();
         tokio::spawn(async move {
@@ -66,11 +101,28 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
+    // ── Library index ─────────────────────────────────────────────────────────
+    // Pick up files added, changed or removed while the server was down.
+    {
+        let db = state.db.clone();
+        let settings = state.settings.clone();
+        tokio::spawn(async move {
+            if let Err(e) = library::scan(&db, &settings.current()).await {
+                tracing::warn!("Library scan failed: {e:#}");
+            }
+        });
+    }
+
+    // ── Subscription checker ──────────────────────────────────────────────────
+    subscriptions::spawn_checker(state.clone());
+
     // ── Cache refresh (every hour, not on startup) ────────────────────────────
     // The local cache is only a fallback when BBC web search is unavailable;
     // refreshing it at startup delays the server for no practical benefit.
+    // Watch rules are evaluated against the fresh cache after each refresh.
     {
-        let iplayer_path = config.get_iplayer_path.clone();
+        let state = state.clone();
+        let settings = settings.clone();
         let cache_dir = config.iplayer_cache_dir.clone();
         tokio::spawn(async move {
             let start = tokio::time::Instant::now() + tokio::time::Duration::from_secs(3600);
@@ -78,18 +130,25 @@ async fn main() -> anyhow::Result<()> {
                 tokio::time::interval_at(start, tokio::time::Duration::from_secs(3600));
             loop {
                 interval.tick().await;
+                let current = settings.current();
+                let iplayer_path = &current.get_iplayer_path;
+                let proxy = current.proxy.as_deref();
                 info!("Refreshing get_iplayer TV cache…");
-                if let Err(e) = iplayer::refresh_cache(&iplayer_path, "tv", &cache_dir).await {
+                if let Err(e) = iplayer::refresh_cache(iplayer_path, "tv", &cache_dir, proxy).await
+                {
                     tracing::warn!("TV cache refresh failed: {e:#}");
                 } else {
                     info!("TV cache refresh complete");
                 }
                 info!("Refreshing get_iplayer radio cache…");
-                if let Err(e) = iplayer::refresh_cache(&iplayer_path, "radio", &cache_dir).await {
+                if let Err(e) =
+                    iplayer::refresh_cache(iplayer_path, "radio", &cache_dir, proxy).await
+                {
                     tracing::warn!("Radio cache refresh failed: {e:#}");
                 } else {
                     info!("Radio cache refresh complete");
                 }
+                watch_rules::evaluate_all(&state).await;
             }
         });
     }
@@ -101,27 +160,32 @@ async fn main() -> anyhow::Result<()> {
     let listener = tokio::net::TcpListener::bind(&config.bind).await?;
     info!("Listening on http://{}", config.bind);
 
-    axum::serve(listener, router).await?;
+    // Client addresses are needed for per-IP login limits and the audit trail.
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
 
-/// Enqueue any queue items whose `scheduled_at` is in the past and whose status
-/// is still `queued`.
+/// Wake the download scheduler if any `queued` items have a `scheduled_at` in
+/// the past. The scheduler itself decides what to start, in priority order.
 async fn enqueue_scheduled(state: &AppState) {
     let now = chrono::Utc::now().to_rfc3339();
 
-    let rows: Vec<(String,)> = sqlx::query_as(
-        "SELECT id FROM queue_items \
+    let (due,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM queue_items \
          WHERE status='queued' AND scheduled_at IS NOT NULL AND scheduled_at <= ?",
     )
     .bind(&now)
-    .fetch_all(&state.db)
+    .fetch_one(&state.db)
     .await
-    .unwrap_or_default();
+    .unwrap_or((0,));
 
-    for (id,) in rows {
-        tracing::info!("Enqueuing scheduled item {id}");
-        state.queue.enqueue(id);
+    if due > 0 {
+        tracing::info!("{due} scheduled item(s) now due");
+        state.queue.wake();
     }
 }
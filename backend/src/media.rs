@@ -0,0 +1,105 @@
+/// Media inspection via ffprobe.
+///
+/// ffprobe ships alongside ffmpeg, so its path is derived from the
+/// `ffmpeg_path` setting rather than configured separately.
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use serde::Serialize;
+use tokio::process::Command;
+
+/// Upper bound on one ffprobe run; it only reads container headers.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What ffprobe reports about a recording.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MediaInfo {
+    pub duration_secs: Option<f64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub audio_channels: Option<i64>,
+    /// The container carries at least one subtitle stream.
+    pub embedded_subtitles: bool,
+}
+
+/// The ffprobe binary next to `ffmpeg_path` (or `ffprobe` on `$PATH`).
+pub fn ffprobe_path(ffmpeg_path: &str) -> String {
+    let path = Path::new(ffmpeg_path);
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) if path.parent().is_some_and(|p| !p.as_os_str().is_empty()) => path
+            .with_file_name(name.replacen("ffmpeg", "ffprobe", 1))
+            .to_string_lossy()
+            .into_owned(),
+        _ => "ffprobe".to_string(),
+    }
+}
+
+/// Run ffprobe on `file` and pick out the first video and audio streams.
+pub async fn probe(ffmpeg_path: &str, file: &Path) -> anyhow::Result<MediaInfo> {
+    let mut cmd = Command::new(ffprobe_path(ffmpeg_path));
+    cmd.args([
+        "-v",
+        "error",
+        "-print_format",
+        "json",
+        "-show_format",
+        "-show_streams",
+    ])
+    .arg(file)
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::piped())
+    .kill_on_drop(true);
+
+    let out = tokio::time::timeout(PROBE_TIMEOUT, cmd.output())
+        .await
+        .context("ffprobe timed out")?
+        .context("spawn ffprobe")?;
+    if !out.status.success() {
+        anyhow::bail!(
+            "ffprobe exited with {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&out.stdout).context("parse ffprobe output")?;
+    Ok(parse_probe(&json))
+}
+
+fn parse_probe(json: &serde_json::Value) -> MediaInfo {
+    let mut info = MediaInfo {
+        duration_secs: json["format"]["duration"]
+            .as_str()
+            .and_then(|d| d.parse().ok()),
+        ..MediaInfo::default()
+    };
+
+    let streams = json["streams"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for stream in streams {
+        let codec = stream["codec_name"].as_str().map(str::to_string);
+        match stream["codec_type"].as_str() {
+            // Cover art is reported as a single-frame video stream.
+            Some("video")
+                if info.video_codec.is_none()
+                    && stream["disposition"]["attached_pic"].as_i64() != Some(1) =>
+            {
+                info.video_codec = codec;
+                info.width = stream["width"].as_i64();
+                info.height = stream["height"].as_i64();
+            }
+            Some("audio") if info.audio_codec.is_none() => {
+                info.audio_codec = codec;
+                info.audio_channels = stream["channels"].as_i64();
+            }
+            Some("subtitle") => info.embedded_subtitles = true,
+            _ => {}
+        }
+    }
+    info
+}
@@ -5,6 +5,14 @@ use uuid::Uuid;
 
 // ── User ─────────────────────────────────────────────────────────────────────
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    User,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: String,
@@ -13,12 +21,37 @@ pub struct User {
     pub password: String,
     pub created_at: String,
     pub updated_at: String,
+    pub role: Role,
+    /// Overrides the `default_quality` setting for this user's new items.
+    pub default_quality: Option<String>,
+    /// Overrides the `subtitles` setting for this user's new items.
+    pub default_subtitles: Option<bool>,
+    /// Base32 TOTP secret; set during 2FA setup, before it is enabled.
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    /// Created from the forward-auth proxy header rather than locally.
+    pub proxy_provisioned: bool,
 }
 
 impl User {
     pub fn new_id() -> String {
         Uuid::new_v4().to_string()
     }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// Admins manage everything; other users only what they own.
+    pub fn can_manage(&self, owner_id: &str) -> bool {
+        self.is_admin() || self.id == owner_id
+    }
+
+    /// The owner to filter listings by: `None` (everyone) for admins.
+    pub fn owner_filter(&self) -> Option<&str> {
+        (!self.is_admin()).then_some(self.id.as_str())
+    }
 }
 
 // ── Download queue item ───────────────────────────────────────────────────────
@@ -29,6 +62,8 @@ impl User {
 pub enum DownloadStatus {
     Queued,
     Downloading,
+    /// Downloaded; running the post-processing steps.
+    Processing,
     Done,
     Failed,
     Cancelled,
@@ -39,6 +74,7 @@ impl std::fmt::Display for DownloadStatus {
         let s = match self {
             DownloadStatus::Queued => "queued",
             DownloadStatus::Downloading => "downloading",
+            DownloadStatus::Processing => "processing",
             DownloadStatus::Done => "done",
             DownloadStatus::Failed => "failed",
             DownloadStatus::Cancelled => "cancelled",
@@ -53,6 +89,7 @@ impl std::str::FromStr for DownloadStatus {
         match s {
             "queued" => Ok(DownloadStatus::Queued),
             "downloading" => Ok(DownloadStatus::Downloading),
+            "processing" => Ok(DownloadStatus::Processing),
             "done" => Ok(DownloadStatus::Done),
             "failed" => Ok(DownloadStatus::Failed),
             "cancelled" => Ok(DownloadStatus::Cancelled),
@@ -61,6 +98,53 @@ impl std::str::FromStr for DownloadStatus {
     }
 }
 
+/// Why a download attempt failed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadErrorKind {
+    /// The BBC refused the stream because the request came from outside the UK.
+    GeoBlocked,
+    /// The programme has expired, isn't available yet, or doesn't exist.
+    Unavailable,
+    /// None of the requested quality levels is offered.
+    NoQuality,
+    FfmpegMissing,
+    GetIplayerMissing,
+    /// A timeout, refused connection, DNS failure or server error.
+    Network,
+    DiskFull,
+    Cancelled,
+    /// The download worked but a post-processing step did not.
+    PostProcessing,
+    /// Nothing in the output matched a known failure.
+    Unknown,
+}
+
+impl DownloadErrorKind {
+    /// Failures that will happen again however often the download is
+    /// retried; only a change of settings, environment or PID will help.
+    pub fn is_permanent(self) -> bool {
+        !matches!(self, Self::Network | Self::Unknown)
+    }
+
+    /// A short reason for the UI.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::GeoBlocked => "Geo-blocked: only available from the UK (try a proxy)",
+            Self::Unavailable => "Programme unavailable or expired",
+            Self::NoQuality => "None of the requested qualities is available",
+            Self::FfmpegMissing => "ffmpeg not found (check the ffmpeg_path setting)",
+            Self::GetIplayerMissing => "get_iplayer not found (check the get_iplayer_path setting)",
+            Self::Network => "Network error",
+            Self::DiskFull => "Output disk is full",
+            Self::Cancelled => "Cancelled",
+            Self::PostProcessing => "Post-processing failed",
+            Self::Unknown => "Download failed",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct QueueItem {
     pub id: String,
@@ -87,6 +171,26 @@ pub struct QueueItem {
     pub subtitles: bool,
     pub metadata: String, // JSON blob
     pub user_id: String,
+    /// Overrides the `filename_template` setting for this item.
+    pub filename_template: Option<String>,
+    /// Why the last attempt failed; cleared when a new attempt starts.
+    pub error_kind: Option<DownloadErrorKind>,
+    /// Programme running time, when known; replaced by the recording's
+    /// own length once it has been probed.
+    pub duration_secs: Option<i64>,
+    // What ffprobe found in the finished recording; `None` until then.
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub audio_channels: Option<i64>,
+    pub embedded_subtitles: Option<bool>,
+    /// Subtitles were saved as separate files next to the recording.
+    pub sidecar_subtitles: Option<bool>,
+    // What the last run of the hook command made of this item; see `hooks`.
+    pub hook_exit_code: Option<i64>,
+    pub hook_output: Option<String>,
+    pub hook_ran_at: Option<String>,
 }
 
 impl QueueItem {
@@ -95,6 +199,95 @@ impl QueueItem {
     }
 }
 
+/// One get_iplayer run of a queue item. Every retry adds a row.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DownloadAttempt {
+    pub id: i64,
+    pub queue_item_id: String,
+    pub attempt: i64,
+    pub started_at: String,
+    /// `None` while the attempt is still running.
+    pub ended_at: Option<String>,
+    pub exit_code: Option<i64>,
+    /// `None` if the attempt succeeded (or is still running).
+    pub error_kind: Option<DownloadErrorKind>,
+    pub error: Option<String>,
+    /// The last lines get_iplayer printed, newline-separated.
+    pub log_tail: Option<String>,
+    pub bytes: Option<i64>,
+    /// The recording mode get_iplayer settled on, e.g. `hlshd1`.
+    pub quality: Option<String>,
+}
+
+/// What a file produced by a download is.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ArtefactKind {
+    /// The recording itself.
+    Media,
+    Subtitles,
+    /// Cover art / thumbnail.
+    Artwork,
+    /// get_iplayer's programme metadata XML.
+    Metadata,
+    Other,
+}
+
+/// One file a download produced.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct QueueItemFile {
+    pub id: i64,
+    pub queue_item_id: String,
+    pub path: String,
+    pub kind: ArtefactKind,
+    /// Size when the download finished.
+    pub size: Option<i64>,
+    pub created_at: String,
+}
+
+/// A downloaded file compared against what is on disk now.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueItemFileCheck {
+    #[serde(flatten)]
+    pub file: QueueItemFile,
+    /// `None` if the file is gone.
+    pub size_on_disk: Option<i64>,
+    /// Present, and the same size as when the download finished.
+    pub intact: bool,
+}
+
+/// Where a post-processing step has got to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Done,
+    /// Nothing to do for this recording, e.g. no subtitles to embed.
+    Skipped,
+    Failed,
+}
+
+/// One step of an item's post-processing chain.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PostprocessStep {
+    pub id: i64,
+    pub queue_item_id: String,
+    /// 1 for the first step of the chain.
+    pub position: i64,
+    /// e.g. `remux_mkv`; see `postprocess::STEPS`.
+    pub step: String,
+    pub status: StepStatus,
+    /// How many times the step has been started.
+    pub runs: i64,
+    pub started_at: Option<String>,
+    pub ended_at: Option<String>,
+    /// Why the step was skipped or failed.
+    pub message: Option<String>,
+}
+
 // ── Request / Response DTOs ───────────────────────────────────────────────────
 
 #[derive(Debug, Deserialize)]
@@ -110,10 +303,37 @@ pub struct AddQueueItemRequest {
     pub scheduled_at: Option<DateTime<Utc>>,
     #[serde(default = "default_priority")]
     pub priority: i64,
-    #[serde(default = "default_quality")]
-    pub quality: String,
-    #[serde(default = "default_subtitles")]
-    pub subtitles: bool,
+    /// Defaults to the `default_quality` setting.
+    pub quality: Option<String>,
+    /// Defaults to the `subtitles` setting.
+    pub subtitles: Option<bool>,
+    /// Naming template or preset for this item; defaults to the
+    /// `filename_template` setting when the download finishes.
+    pub filename_template: Option<String>,
+    /// Programme running time; looked up before downloading if not given.
+    pub duration_secs: Option<i64>,
+}
+
+impl AddQueueItemRequest {
+    /// A request to queue a programme found by search or episode listing,
+    /// using the default priority and settings-derived quality/subtitles.
+    pub fn from_search_result(r: &SearchResult) -> Self {
+        AddQueueItemRequest {
+            pid: r.pid.clone(),
+            title: r.title.clone(),
+            series: r.series.clone(),
+            episode: r.episode.clone(),
+            channel: r.channel.clone(),
+            media_type: r.media_type.clone(),
+            thumbnail_url: r.thumbnail_url.clone(),
+            scheduled_at: None,
+            priority: default_priority(),
+            quality: None,
+            subtitles: None,
+            filename_template: None,
+            duration_secs: r.duration_secs,
+        }
+    }
 }
 
 fn default_media_type() -> String {
@@ -122,12 +342,6 @@ fn default_media_type() -> String {
 fn default_priority() -> i64 {
     5
 }
-fn default_quality() -> String {
-    "best".to_string()
-}
-fn default_subtitles() -> bool {
-    true
-}
 
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
@@ -138,14 +352,172 @@ pub struct LoginRequest {
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
     pub token: String,
+    pub expires_at: String,
     pub user_id: String,
     pub username: String,
+    pub role: Role,
+}
+
+/// Response to `POST /api/auth/login`: a session, or a challenge to finish
+/// with `POST /api/auth/login/2fa` when the user has 2FA enabled.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginOutcome {
+    Session(LoginResponse),
+    TwoFactorRequired(TwoFactorChallenge),
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallenge {
+    /// Always `true`; lets clients tell this apart from a session.
+    pub two_factor_required: bool,
+    pub challenge: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge: String,
+    /// A 6-digit TOTP code or an unused recovery code.
+    pub code: String,
+}
+
+/// Body of `GET`/`PUT /api/users/me/preferences`. `null` means "use the
+/// global setting".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserPreferences {
+    pub default_quality: Option<String>,
+    pub default_subtitles: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateUserRequest {
     pub username: String,
     pub password: String,
+    #[serde(default = "default_role")]
+    pub role: Role,
+}
+
+fn default_role() -> Role {
+    Role::User
+}
+
+// ── Auth audit trail ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventKind {
+    LoginSuccess,
+    LoginFailure,
+    /// Too many failures; the username or IP is now locked out.
+    LoginLocked,
+    PasswordChanged,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    /// Account created on first sight of a trusted proxy's user header.
+    UserProvisioned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuthEvent {
+    pub id: i64,
+    pub kind: AuthEventKind,
+    pub username: String,
+    pub user_id: Option<String>,
+    pub ip: Option<String>,
+    pub detail: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct AuthEventQuery {
+    pub username: Option<String>,
+    pub kind: Option<AuthEventKind>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+// ── Two-factor authentication ─────────────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+/// Response to `POST /api/auth/2fa/setup`.
+#[derive(Debug, Serialize)]
+pub struct TwoFactorSetup {
+    /// Base32, for authenticator apps that take the key by hand.
+    pub secret: String,
+    /// `otpauth://` URI, usually shown as a QR code.
+    pub otpauth_uri: String,
+}
+
+/// Fresh recovery codes. Only shown once.
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordConfirmation {
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+// ── API tokens ────────────────────────────────────────────────────────────────
+
+/// What a personal API token may do. A token with no scopes has its owner's
+/// ordinary access; admin endpoints always need `admin`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiScope {
+    /// `GET` requests only.
+    Read,
+    /// `POST /api/queue`.
+    QueueAdd,
+    /// Everything, including admin endpoints. Only admins can grant it.
+    Admin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    /// The first characters of the token, to tell tokens apart.
+    pub prefix: String,
+    #[sqlx(json)]
+    pub scopes: Vec<ApiScope>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+impl ApiToken {
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<ApiScope>,
+}
+
+/// Response to `POST /api/tokens` — the only time the token itself is shown.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -163,6 +535,12 @@ pub struct QueueQuery {
     pub per_page: Option<i64>,
 }
 
+#[derive(Debug, Deserialize, Default)]
+pub struct LogQuery {
+    /// Only return this many of the most recent lines.
+    pub tail: Option<usize>,
+}
+
 /// Live progress update broadcast via WebSocket.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -171,21 +549,52 @@ pub enum WsEvent {
         id: String,
         progress: f64,
         speed: Option<String>,
+        /// Time left, as `HH:MM:SS`.
         eta: Option<String>,
+        /// How far ffmpeg has got into the programme, or how long the
+        /// download has been running before any progress is reported.
+        elapsed: Option<String>,
+        bytes: Option<u64>,
+        total_bytes: Option<u64>,
     },
     StatusChange {
         id: String,
         status: String,
     },
     ItemAdded {
-        item: QueueItem,
+        item: Box<QueueItem>,
     },
     ItemRemoved {
         id: String,
+        /// The item's owner, for filtering: by now the row is usually gone.
+        user_id: String,
     },
     Error {
         id: String,
         message: String,
+        kind: Option<DownloadErrorKind>,
+    },
+    /// A subscription check queued one or more new episodes.
+    SubscriptionNewEpisodes {
+        subscription_id: String,
+        title: String,
+        pids: Vec<String>,
+    },
+    /// A watch rule matched and queued one or more programmes.
+    WatchRuleMatched {
+        rule_id: String,
+        name: String,
+        pids: Vec<String>,
+    },
+    /// A line of get_iplayer output, sent only to clients tailing the item.
+    LogLine {
+        id: String,
+        line: String,
+    },
+    /// A post-processing step started, finished, was skipped or failed.
+    StepChange {
+        id: String,
+        step: Box<PostprocessStep>,
     },
 }
 
@@ -200,10 +609,306 @@ pub struct SearchResult {
     pub media_type: String,
     pub thumbnail_url: Option<String>,
     pub available_until: Option<String>,
+    /// Running time as shown by the source, e.g. `1:28:00` or `58 mins`.
     pub duration: Option<String>,
+    /// `duration` in seconds, where it could be understood.
+    pub duration_secs: Option<i64>,
     pub description: Option<String>,
 }
 
+// ── Subscriptions ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Subscription {
+    pub id: String,
+    pub pid: String,
+    pub title: String,
+    pub media_type: String,
+    pub quality: Option<String>,
+    pub subtitles: Option<bool>,
+    pub enabled: bool,
+    pub created_at: String,
+    pub last_checked_at: Option<String>,
+    pub last_error: Option<String>,
+    pub user_id: String,
+}
+
+impl Subscription {
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSubscriptionRequest {
+    /// Brand or series PID, or a BBC programmes/iPlayer URL containing one.
+    pub pid: String,
+    pub title: String,
+    #[serde(default = "default_media_type")]
+    pub media_type: String,
+    pub quality: Option<String>,
+    pub subtitles: Option<bool>,
+    /// Queue the episodes available right now. When false they are recorded
+    /// as seen and only episodes published later are queued.
+    #[serde(default = "default_backfill")]
+    pub backfill: bool,
+}
+
+fn default_backfill() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSubscriptionRequest {
+    pub title: Option<String>,
+    pub quality: Option<String>,
+    pub subtitles: Option<bool>,
+    pub enabled: Option<bool>,
+}
+
+// ── Watch rules ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WatchRule {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    pub is_regex: bool,
+    pub media_type: String,
+    pub channel: Option<String>,
+    pub exclude: Option<String>,
+    pub quality: Option<String>,
+    pub subtitles: Option<bool>,
+    pub enabled: bool,
+    pub created_at: String,
+    pub last_run_at: Option<String>,
+    pub last_error: Option<String>,
+    pub user_id: String,
+}
+
+impl WatchRule {
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// Body of `POST /api/watch-rules` and `POST /api/watch-rules/preview`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateWatchRuleRequest {
+    #[serde(default)]
+    pub name: String,
+    /// Matched against title, series, episode and description.
+    pub query: String,
+    #[serde(default)]
+    pub is_regex: bool,
+    #[serde(default = "default_media_type")]
+    pub media_type: String,
+    pub channel: Option<String>,
+    pub exclude: Option<String>,
+    pub quality: Option<String>,
+    pub subtitles: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWatchRuleRequest {
+    pub name: Option<String>,
+    pub query: Option<String>,
+    pub is_regex: Option<bool>,
+    pub media_type: Option<String>,
+    /// An empty string clears the filter.
+    pub channel: Option<String>,
+    /// An empty string clears the filter.
+    pub exclude: Option<String>,
+    pub quality: Option<String>,
+    pub subtitles: Option<bool>,
+    pub enabled: Option<bool>,
+}
+
+/// A programme a watch rule matches, as returned by the preview endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchRuleMatch {
+    #[serde(flatten)]
+    pub programme: SearchResult,
+    /// Already queued or downloaded, so the rule would skip it.
+    pub seen: bool,
+}
+
+// ── Webhooks ──────────────────────────────────────────────────────────────────
+
+/// A queue event a webhook can be sent.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    ItemAdded,
+    /// Sent once per download, not for its automatic retries.
+    DownloadStarted,
+    DownloadDone,
+    /// Sent once retries are exhausted, or when post-processing fails.
+    DownloadFailed,
+    /// Sent by the test endpoint, whatever the webhook subscribes to.
+    Ping,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Webhook {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    /// Only shown when the webhook is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    #[sqlx(json)]
+    pub events: Vec<WebhookEvent>,
+    pub enabled: bool,
+    pub created_at: String,
+}
+
+impl Webhook {
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    /// Generated when left out.
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    /// Replaces the signing secret.
+    pub secret: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Response to `POST /api/webhooks` — the only time the secret is shown.
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not sent yet, or waiting to be retried.
+    Pending,
+    Delivered,
+    /// Every attempt failed.
+    Failed,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct WebhookDeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+/// One event sent, or being sent, to a webhook.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    /// The JSON body, exactly as signed and sent.
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    /// HTTP status of the latest attempt, if it got a response.
+    pub response_status: Option<i64>,
+    /// Why the latest attempt failed.
+    pub error: Option<String>,
+    pub created_at: String,
+    pub last_attempt_at: Option<String>,
+    pub next_attempt_at: Option<String>,
+}
+
+// ── Library ───────────────────────────────────────────────────────────────────
+
+/// One indexed recording under `output_dir`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LibraryFile {
+    pub id: String,
+    pub path: String,
+    pub programme: String,
+    pub series: Option<String>,
+    pub episode: Option<String>,
+    pub pid: Option<String>,
+    pub media_type: String,
+    pub size: i64,
+    pub modified_at: String,
+    pub duration_secs: Option<f64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub audio_channels: Option<i64>,
+    pub embedded_subtitles: bool,
+    /// Subtitle files next to the recording.
+    #[sqlx(json)]
+    pub sidecars: Vec<String>,
+    pub queue_item_id: Option<String>,
+    pub indexed_at: String,
+    /// Every file the download that produced this recording left behind,
+    /// including the recording itself.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub artefacts: Vec<QueueItemFile>,
+}
+
+impl LibraryFile {
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// Library files grouped by programme, then series.
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryProgramme {
+    pub programme: String,
+    pub file_count: usize,
+    pub total_size: i64,
+    pub series: Vec<LibrarySeries>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LibrarySeries {
+    pub series: Option<String>,
+    pub files: Vec<LibraryFile>,
+}
+
+/// A link to a library file that works without an `Authorization` header,
+/// for `<video>`/`<audio>` sources and downloads.
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaLink {
+    /// Relative to the server root; add `&download=true` to save the file.
+    pub url: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LibraryScanSummary {
+    /// Files added or re-probed because they changed.
+    pub indexed: usize,
+    /// Index entries dropped because their file is gone.
+    pub removed: usize,
+    pub total: usize,
+    /// Files recorded for finished downloads that are missing or have
+    /// changed size since.
+    pub damaged_artefacts: usize,
+}
+
 /// Key/value settings pair
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Setting {
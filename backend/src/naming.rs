@@ -0,0 +1,314 @@
+/// Output file naming.
+///
+/// get_iplayer always writes its default `<name>_-_<episode>_<pid>_<version>`
+/// file names flat into `output_dir`; partial-file cleanup on cancel relies on
+/// the PID being in those names. Once a download has finished, its files are
+/// moved to the path rendered from a naming template, so libraries such as
+/// Plex, Jellyfin and Kodi can pick them up.
+///
+/// A template is a `/`-separated relative path without an extension, made of
+/// literal text and `{token}`s. The media file keeps its extension and every
+/// sidecar (subtitles, artwork) keeps its own.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tracing::{info, warn};
+
+use crate::models::QueueItem;
+
+/// Tokens a template may use. `{date}` is the day the download finished, not
+/// the broadcast date, which queue items don't record.
+pub const TOKENS: &[&str] = &[
+    "title",
+    "series",
+    "episode",
+    "episode_title",
+    "season",
+    "number",
+    "channel",
+    "pid",
+    "date",
+];
+
+/// Named layouts accepted wherever a template is.
+pub const PRESETS: &[(&str, &str)] = &[
+    (
+        "plex",
+        "{title}/Season {season}/{title} - S{season}E{number} - {episode_title}",
+    ),
+    (
+        "jellyfin",
+        "{title}/Season {season}/{title} S{season}E{number} - {episode_title}",
+    ),
+    ("kodi", "{title}/Season {season}/{title} S{season}E{number}"),
+    ("by_date", "{title}/{date} - {episode}"),
+];
+
+/// The names of `PRESETS`.
+pub const PRESET_NAMES: [&str; PRESETS.len()] = {
+    let mut names = [""; PRESETS.len()];
+    let mut i = 0;
+    while i < PRESETS.len() {
+        names[i] = PRESETS[i].0;
+        i += 1;
+    }
+    names
+};
+
+static TOKEN_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([a-z_]+)\}").unwrap());
+
+/// Expand a preset name; anything else is returned as-is.
+pub fn resolve(template: &str) -> &str {
+    PRESETS
+        .iter()
+        .find(|(name, _)| *name == template)
+        .map(|(_, t)| *t)
+        .unwrap_or(template)
+}
+
+/// Check a template (or preset name), returning the normalised value or a
+/// message explaining the problem. Blank means "keep get_iplayer's names".
+pub fn validate(template: &str) -> Result<String, String> {
+    let template = template.trim();
+    if template.is_empty() || PRESET_NAMES.contains(&template) {
+        return Ok(template.to_string());
+    }
+    if let Some(unknown) = TOKEN_RE
+        .captures_iter(template)
+        .map(|c| c[1].to_string())
+        .find(|t| !TOKENS.contains(&t.as_str()))
+    {
+        return Err(format!(
+            "unknown token {{{unknown}}}; use {}",
+            TOKENS
+                .iter()
+                .map(|t| format!("{{{t}}}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if template.starts_with('/') || template.split('/').any(|s| s.trim() == "..") {
+        return Err("must be a relative path inside the output directory".to_string());
+    }
+    if !template.contains('{') {
+        return Err(format!(
+            "must contain at least one token, or be one of {}",
+            PRESET_NAMES.join(", ")
+        ));
+    }
+    Ok(template.to_string())
+}
+
+// ── Rendering ──────────────────────────────────────────────────────────────────
+
+/// Render `template` for `item` into a relative path without extension.
+/// `date` fills `{date}`: the download date.
+pub fn render(template: &str, item: &QueueItem, date: chrono::NaiveDate) -> PathBuf {
+    static SERIES_NUM: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)series\s+(\d+)").unwrap());
+    static EPISODE_NUM: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^(?:(\d+)\.\s*|(?i:episode)\s+(\d+)\b[:.\s-]*)").unwrap());
+
+    let series = item.series.as_deref().unwrap_or_default();
+    let episode = item.episode.as_deref().unwrap_or_default();
+    let season = SERIES_NUM
+        .captures(series)
+        .or_else(|| SERIES_NUM.captures(episode))
+        .and_then(|c| c[1].parse::<u32>().ok())
+        .unwrap_or(0);
+    let (number, episode_title) = match EPISODE_NUM.captures(episode) {
+        Some(c) => {
+            let n = c
+                .get(1)
+                .or(c.get(2))
+                .and_then(|m| m.as_str().parse::<u32>().ok());
+            let rest = episode[c.get(0).map_or(0, |m| m.end())..].trim();
+            (n.unwrap_or(0), if rest.is_empty() { episode } else { rest })
+        }
+        None => (0, episode),
+    };
+
+    let expanded = TOKEN_RE.replace_all(resolve(template), |c: &regex::Captures| {
+        let value = match &c[1] {
+            "title" => item.title.clone(),
+            "series" => series.to_string(),
+            "episode" => episode.to_string(),
+            "episode_title" => episode_title.to_string(),
+            "season" => format!("{season:02}"),
+            "number" => format!("{number:02}"),
+            "channel" => item.channel.clone().unwrap_or_default(),
+            "pid" => item.pid.clone(),
+            "date" => date.format("%Y-%m-%d").to_string(),
+            _ => String::new(),
+        };
+        // A token's value must never introduce extra path segments.
+        value.replace(['/', '\\'], "-")
+    });
+
+    expanded
+        .split('/')
+        .map(sanitise_segment)
+        .filter(|s| !s.is_empty() && s != "." && s != "..")
+        .collect()
+}
+
+/// Strip characters that are invalid in file names on common filesystems and
+/// tidy the separators left behind by empty tokens.
+fn sanitise_segment(segment: &str) -> String {
+    static SPACES: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+    static DANGLING: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:\s*-\s*){2,}").unwrap());
+
+    let cleaned: String = segment
+        .chars()
+        .filter(|c| !matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|') && !c.is_control())
+        .collect();
+    let cleaned = SPACES.replace_all(&cleaned, " ");
+    let cleaned = DANGLING.replace_all(&cleaned, " - ");
+    cleaned
+        .trim_matches(|c: char| c.is_whitespace() || c == '-' || c == '.')
+        .to_string()
+}
+
+// ── Moving files ───────────────────────────────────────────────────────────────
+
+/// Where a finished download's files ended up.
+pub struct Placed {
+    /// The recording's path.
+    pub media: String,
+    /// Every file passed to [`place`], at its new path.
+    pub files: Vec<String>,
+}
+
+/// Move a finished download to the path `template` gives under
+/// `output_dir`. `files` are all the files the download produced, `media`
+/// among them; those named after `media` (subtitles, artwork) move with it,
+/// anything else stays put. On any error the files are left where
+/// get_iplayer wrote them.
+pub async fn place(
+    output_dir: &str,
+    template: &str,
+    item: &QueueItem,
+    media: &str,
+    files: &[String],
+) -> Placed {
+    let unmoved = || Placed {
+        media: media.to_string(),
+        files: files.to_vec(),
+    };
+    if template.trim().is_empty() {
+        return unmoved();
+    }
+    let date = chrono::Local::now().date_naive();
+    let target = render(template, item, date);
+    if target.as_os_str().is_empty() {
+        warn!(
+            "Naming template {template:?} rendered nothing for {}",
+            item.id
+        );
+        return unmoved();
+    }
+    match move_files(Path::new(output_dir), &target, Path::new(media), files).await {
+        Ok(placed) => {
+            info!("Moved {media} to {}", placed.media);
+            placed
+        }
+        Err(e) => {
+            warn!("Could not apply naming template for {}: {e:#}", item.id);
+            unmoved()
+        }
+    }
+}
+
+async fn move_files(
+    output_dir: &Path,
+    target: &Path,
+    media: &Path,
+    files: &[String],
+) -> anyhow::Result<Placed> {
+    let stem = media
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .context("output path has no file name")?;
+
+    // The media file and every sidecar named after it, each with what follows
+    // the name: `.mp4`, `.srt`, `.en.srt`…
+    let movable: Vec<(PathBuf, String)> = files
+        .iter()
+        .map(PathBuf::from)
+        .filter(|f| f.parent() == media.parent())
+        .filter_map(|f| name_suffix(stem, &f).map(|suffix| (f, suffix)))
+        .collect();
+
+    let base = unused_base(output_dir, target, &movable).await;
+    if let Some(parent) = base.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("create {}", parent.display()))?;
+    }
+
+    let mut moved = HashMap::new();
+    for (file, suffix) in movable {
+        let dest = with_suffix(&base, &suffix);
+        tokio::fs::rename(&file, &dest)
+            .await
+            .with_context(|| format!("move {} to {}", file.display(), dest.display()))?;
+        moved.insert(file, dest);
+    }
+    let new_path = |path: &Path| {
+        moved
+            .get(path)
+            .map_or(path, PathBuf::as_path)
+            .to_string_lossy()
+            .into_owned()
+    };
+    Ok(Placed {
+        media: new_path(media),
+        files: files.iter().map(|f| new_path(Path::new(f))).collect(),
+    })
+}
+
+/// `output_dir/target`, or `target (2)`, `target (3)`… if any of the files
+/// would overwrite something already there.
+async fn unused_base(output_dir: &Path, target: &Path, files: &[(PathBuf, String)]) -> PathBuf {
+    let mut n = 1;
+    loop {
+        let mut base = output_dir.join(target);
+        if n > 1 {
+            let name = base
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            base.set_file_name(format!("{name} ({n})"));
+        }
+        let mut clash = false;
+        for (file, suffix) in files {
+            let dest = with_suffix(&base, suffix);
+            if dest != *file && tokio::fs::try_exists(&dest).await.unwrap_or(false) {
+                clash = true;
+                break;
+            }
+        }
+        if !clash {
+            return base;
+        }
+        n += 1;
+    }
+}
+
+/// What follows `stem` in the name of `file`, if it is named after it:
+/// `.en.srt` for `stem.en.srt`, but nothing for `stem2.srt`.
+fn name_suffix(stem: &str, file: &Path) -> Option<String> {
+    let rest = file.file_name()?.to_str()?.strip_prefix(stem)?;
+    rest.starts_with('.').then(|| rest.to_string())
+}
+
+/// `base` plus `suffix`, e.g. `.en.srt`.
+fn with_suffix(base: &Path, suffix: &str) -> PathBuf {
+    let mut name = base.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
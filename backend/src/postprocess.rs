@@ -0,0 +1,593 @@
+/// Post-processing of finished downloads.
+///
+/// The `postprocess_steps` setting names a chain of steps that every finished
+/// download runs through, in order, before it is marked done. When the
+/// download finishes, the chain is copied into the item's `postprocess_steps`
+/// rows, so each step's progress can be followed and a failed step can be run
+/// again on its own with the same chain. The item is `processing` meanwhile.
+///
+/// Every step except `move` rewrites the recording with ffmpeg into a
+/// `.partial.` file next to it and only then swaps it in, so a step that
+/// fails or is cancelled leaves the previous file as it was.
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    sync::broadcast,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::{
+    artefacts,
+    db::Db,
+    download_log::{ItemLog, Stream},
+    library,
+    models::{ArtefactKind, PostprocessStep, QueueItem, StepStatus, WsEvent},
+    settings::RuntimeSettings,
+};
+
+/// Every step a chain may use.
+pub const STEPS: &[&str] = &[
+    "remux_mkv",
+    "embed_subtitles",
+    "tag",
+    "normalise_audio",
+    "transcode",
+    "move",
+];
+
+/// Lines of ffmpeg output quoted when a step fails.
+const ERROR_LINES: usize = 5;
+
+/// Check a comma-separated chain, returning it normalised or a message
+/// naming the step that doesn't exist.
+pub fn validate(value: &str) -> Result<String, String> {
+    let steps: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    match steps.iter().find(|s| !STEPS.contains(s)) {
+        Some(unknown) => Err(format!(
+            "unknown step \"{unknown}\"; steps are {}",
+            STEPS.join(", ")
+        )),
+        None => Ok(steps.join(",")),
+    }
+}
+
+// ── Step rows ──────────────────────────────────────────────────────────────────
+
+/// Replace `item_id`'s steps with `steps`, all pending. Returns how many
+/// there are.
+pub async fn plan(db: &Db, item_id: &str, steps: &[String]) -> sqlx::Result<usize> {
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM postprocess_steps WHERE queue_item_id=?")
+        .bind(item_id)
+        .execute(&mut *tx)
+        .await?;
+    for (position, step) in steps.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO postprocess_steps (queue_item_id, position, step) VALUES (?, ?, ?)",
+        )
+        .bind(item_id)
+        .bind(position as i64 + 1)
+        .bind(step)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(steps.len())
+}
+
+/// `item_id`'s steps in chain order.
+pub async fn list(db: &Db, item_id: &str) -> sqlx::Result<Vec<PostprocessStep>> {
+    sqlx::query_as("SELECT * FROM postprocess_steps WHERE queue_item_id=? ORDER BY position")
+        .bind(item_id)
+        .fetch_all(db)
+        .await
+}
+
+/// Make `step` and every step after it pending again.
+pub async fn reset_from(db: &Db, step: &PostprocessStep) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE postprocess_steps SET status='pending', message=NULL \
+         WHERE queue_item_id=? AND position>=?",
+    )
+    .bind(&step.queue_item_id)
+    .bind(step.position)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// On startup: steps that were running when the service stopped failed.
+pub async fn fail_interrupted(db: &Db) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE postprocess_steps \
+         SET status='failed', ended_at=?, message='Interrupted by a restart' \
+         WHERE status='running'",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+// ── Running a chain ────────────────────────────────────────────────────────────
+
+/// The files a chain works on.
+#[derive(Debug, Clone)]
+pub struct Files {
+    /// The recording.
+    pub media: String,
+    /// Every file of the download, `media` included.
+    pub files: Vec<String>,
+}
+
+impl Files {
+    /// Swap `from` for `to` wherever it appears.
+    fn renamed(&self, from: &str, to: &str) -> Files {
+        let rename = |p: &String| if p == from { to.to_string() } else { p.clone() };
+        Files {
+            media: rename(&self.media),
+            files: self.files.iter().map(rename).collect(),
+        }
+    }
+
+    fn of_kind(&self, kind: ArtefactKind) -> impl Iterator<Item = &String> {
+        self.files
+            .iter()
+            .filter(move |f| artefacts::kind(Path::new(f)) == kind)
+    }
+}
+
+/// What a step did.
+enum Outcome {
+    /// The files after the step.
+    Done(Files),
+    /// Why there was nothing to do.
+    Skipped(String),
+}
+
+/// One item's trip through its chain.
+pub struct Pipeline<'a> {
+    pub db: &'a Db,
+    pub settings: &'a RuntimeSettings,
+    pub item: &'a QueueItem,
+    pub events: &'a broadcast::Sender<WsEvent>,
+    pub log: &'a ItemLog,
+    pub cancel: &'a CancellationToken,
+}
+
+impl Pipeline<'_> {
+    /// Run every step that hasn't completed yet, in order, stopping at the
+    /// first that fails. The item's `output_path` and files are updated after
+    /// each step, so a retry carries on from where the chain got to.
+    pub async fn run(&self, mut files: Files) -> anyhow::Result<Files> {
+        let steps = list(self.db, &self.item.id).await?;
+        let todo = steps
+            .into_iter()
+            .filter(|s| !matches!(s.status, StepStatus::Done | StepStatus::Skipped));
+        for step in todo {
+            self.mark(step.id, StepStatus::Running, None).await;
+            self.note(&format!("Post-processing: {} started", step.step))
+                .await;
+
+            match self.apply(&step.step, &files).await {
+                Ok(Outcome::Done(after)) => {
+                    files = after;
+                    self.save(&files).await?;
+                    self.mark(step.id, StepStatus::Done, None).await;
+                    self.note(&format!("Post-processing: {} done", step.step))
+                        .await;
+                }
+                Ok(Outcome::Skipped(reason)) => {
+                    self.mark(step.id, StepStatus::Skipped, Some(&reason)).await;
+                    self.note(&format!("Post-processing: {} skipped: {reason}", step.step))
+                        .await;
+                }
+                Err(e) => {
+                    let message = format!("{e:#}");
+                    self.mark(step.id, StepStatus::Failed, Some(&message)).await;
+                    self.note(&format!("Post-processing: {} failed: {message}", step.step))
+                        .await;
+                    bail!("post-processing step {} failed: {message}", step.step);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    async fn apply(&self, step: &str, files: &Files) -> anyhow::Result<Outcome> {
+        match step {
+            "remux_mkv" => self.remux_mkv(files).await,
+            "embed_subtitles" => self.embed_subtitles(files).await,
+            "tag" => self.tag(files).await,
+            "normalise_audio" => self.normalise_audio(files).await,
+            "transcode" => self.transcode(files).await,
+            "move" => self.move_files(files).await,
+            other => bail!("unknown step {other}"),
+        }
+    }
+
+    // ── Steps ──────────────────────────────────────────────────────────────────
+
+    /// Copy every stream into a Matroska container, converting subtitles to
+    /// SRT on the way.
+    async fn remux_mkv(&self, files: &Files) -> anyhow::Result<Outcome> {
+        let media = Path::new(&files.media);
+        if library::media_type(media) != Some("tv") {
+            return Ok(Outcome::Skipped("audio-only recording".into()));
+        }
+        if extension(media) == "mkv" {
+            return Ok(Outcome::Skipped("already Matroska".into()));
+        }
+        let target = media.with_extension("mkv");
+        let args = args(["-i".as_ref(), media.as_os_str()])
+            .chain(args(["-map", "0", "-c", "copy", "-c:s", "srt"]))
+            .collect();
+        self.rewrite(files, args, &target).await
+    }
+
+    /// Mux the SRT, WebVTT and ASS sidecars into the recording. TTML can't be
+    /// read by ffmpeg and is left as a sidecar.
+    async fn embed_subtitles(&self, files: &Files) -> anyhow::Result<Outcome> {
+        let media = Path::new(&files.media);
+        let ext = extension(media);
+        let codec = match ext.as_str() {
+            "mkv" => "srt",
+            "mp4" | "m4v" => "mov_text",
+            _ => {
+                return Ok(Outcome::Skipped(format!(
+                    ".{ext} files can't carry subtitles"
+                )));
+            }
+        };
+        let subtitles: Vec<&String> = files
+            .of_kind(ArtefactKind::Subtitles)
+            .filter(|s| matches!(extension(Path::new(s)).as_str(), "srt" | "vtt" | "ass"))
+            .collect();
+        if subtitles.is_empty() {
+            return Ok(Outcome::Skipped("no subtitle files to embed".into()));
+        }
+
+        let mut cmd = vec!["-i".into(), media.into()];
+        for sub in &subtitles {
+            cmd.extend(["-i".into(), sub.into()]);
+        }
+        for input in 0..=subtitles.len() {
+            cmd.extend(["-map".into(), input.to_string().into()]);
+        }
+        cmd.extend(args(["-c", "copy", "-c:s", codec]));
+        self.rewrite(files, cmd, media).await
+    }
+
+    /// Write programme details into the container's tags and attach the
+    /// cover art, if get_iplayer saved any.
+    async fn tag(&self, files: &Files) -> anyhow::Result<Outcome> {
+        let media = Path::new(&files.media);
+        let ext = extension(media);
+        if !matches!(ext.as_str(), "mp4" | "m4v" | "m4a" | "mkv" | "mp3") {
+            return Ok(Outcome::Skipped(format!(".{ext} files can't carry tags")));
+        }
+        let item = self.item;
+        let mut tags = vec![
+            (
+                "title",
+                item.episode.clone().unwrap_or_else(|| item.title.clone()),
+            ),
+            ("show", item.title.clone()),
+            (
+                "album",
+                item.series.clone().unwrap_or_else(|| item.title.clone()),
+            ),
+            ("episode_id", item.pid.clone()),
+        ];
+        if let Some(channel) = &item.channel {
+            tags.push(("artist", channel.clone()));
+            tags.push(("network", channel.clone()));
+        }
+
+        let art = files.of_kind(ArtefactKind::Artwork).next();
+        let mut cmd = vec!["-i".into(), media.into()];
+        match art {
+            Some(art) if ext == "mkv" => {
+                let (mime, name) = match extension(Path::new(art)).as_str() {
+                    "png" => ("image/png", "cover.png"),
+                    _ => ("image/jpeg", "cover.jpg"),
+                };
+                cmd.extend(args(["-map", "0", "-c", "copy", "-attach"]));
+                cmd.push(art.into());
+                cmd.extend(args(["-metadata:s:t", &format!("mimetype={mime}")]));
+                cmd.extend(args(["-metadata:s:t", &format!("filename={name}")]));
+            }
+            // Mapped first so `v:0` is the picture whatever else is there.
+            Some(art) => {
+                cmd.extend(["-i".into(), art.into()]);
+                cmd.extend(args(["-map", "1", "-map", "0", "-c", "copy"]));
+                cmd.extend(args(["-disposition:v:0", "attached_pic"]));
+            }
+            None => cmd.extend(args(["-map", "0", "-c", "copy"])),
+        }
+        if ext == "mp3" {
+            cmd.extend(args(["-id3v2_version", "3"]));
+        }
+        for (key, value) in tags {
+            cmd.extend(args(["-metadata", &format!("{key}={value}")]));
+        }
+        self.rewrite(files, cmd, media).await
+    }
+
+    /// Re-encode the audio to `loudness_target` LUFS with ffmpeg's single-pass
+    /// `loudnorm` filter. Video is copied as it is.
+    async fn normalise_audio(&self, files: &Files) -> anyhow::Result<Outcome> {
+        let media = Path::new(&files.media);
+        let codec = match extension(media).as_str() {
+            "mp3" => "libmp3lame",
+            _ => "aac",
+        };
+        let bitrate = match self.settings.transcode_audio_bitrate.as_str() {
+            "" => "192k",
+            b => b,
+        };
+        let filter = format!(
+            "loudnorm=I={}:TP=-1.5:LRA=11",
+            self.settings.loudness_target
+        );
+        let cmd = args(["-i".as_ref(), media.as_os_str()])
+            .chain(args(["-map", "0", "-c", "copy", "-c:a", codec]))
+            .chain(args(["-b:a", bitrate, "-af", &filter]))
+            .collect();
+        self.rewrite(files, cmd, media).await
+    }
+
+    /// Re-encode to the `transcode_*` codecs and bitrates. A stream with only
+    /// a bitrate set keeps to H.264 or AAC; one with neither is copied.
+    async fn transcode(&self, files: &Files) -> anyhow::Result<Outcome> {
+        let s = self.settings;
+        // `V` leaves attached cover art alone.
+        let streams = [
+            (
+                "V",
+                &s.transcode_video_codec,
+                &s.transcode_video_bitrate,
+                "libx264",
+            ),
+            (
+                "a",
+                &s.transcode_audio_codec,
+                &s.transcode_audio_bitrate,
+                "aac",
+            ),
+        ];
+        if streams
+            .iter()
+            .all(|(_, c, b, _)| c.is_empty() && b.is_empty())
+        {
+            return Ok(Outcome::Skipped(
+                "no transcode codec or bitrate is set".into(),
+            ));
+        }
+
+        let media = Path::new(&files.media);
+        let mut cmd: Vec<OsString> = args(["-i".as_ref(), media.as_os_str()])
+            .chain(args(["-map", "0", "-c", "copy"]))
+            .collect();
+        for (spec, codec, bitrate, default) in streams {
+            if codec.is_empty() && bitrate.is_empty() {
+                continue;
+            }
+            let codec = if codec.is_empty() { default } else { codec };
+            cmd.extend(args([&format!("-c:{spec}"), codec]));
+            if !bitrate.is_empty() {
+                cmd.extend(args([&format!("-b:{spec}"), bitrate.as_str()]));
+            }
+        }
+        self.rewrite(files, cmd, media).await
+    }
+
+    /// Move every file to `final_dir`, keeping its path under the output
+    /// directory. Nothing moves if any destination is already taken. Files
+    /// already there, moved by an earlier run that failed part-way, stay put.
+    async fn move_files(&self, files: &Files) -> anyhow::Result<Outcome> {
+        let final_dir = self.settings.final_dir.as_str();
+        if final_dir.is_empty() {
+            bail!("no final location is set (final_dir)");
+        }
+        let root = Path::new(&self.settings.output_dir);
+        let destination = |path: &Path| -> PathBuf {
+            match path.strip_prefix(root) {
+                Ok(relative) => Path::new(final_dir).join(relative),
+                Err(_) => Path::new(final_dir).join(path.file_name().unwrap_or_default()),
+            }
+        };
+        // `final_dir` may hold the output directory, or sit inside it.
+        let moved_already = |path: &Path| {
+            path.starts_with(final_dir) && !(root.starts_with(final_dir) && path.starts_with(root))
+        };
+
+        let mut moves = Vec::new();
+        for file in &files.files {
+            let from = PathBuf::from(file);
+            if moved_already(&from) {
+                continue;
+            }
+            let to = destination(&from);
+            if tokio::fs::try_exists(&to).await.unwrap_or(false) {
+                bail!("{} already exists", to.display());
+            }
+            moves.push((from, to));
+        }
+
+        let mut moved = files.clone();
+        for (from, to) in moves {
+            if let Some(parent) = to.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .with_context(|| format!("create {}", parent.display()))?;
+            }
+            move_file(&from, &to).await?;
+            moved = moved.renamed(&from.to_string_lossy(), &to.to_string_lossy());
+            // Save as we go so a failure part-way doesn't lose track of what
+            // has already moved.
+            self.save(&moved).await?;
+        }
+        Ok(Outcome::Done(moved))
+    }
+
+    // ── Helpers ────────────────────────────────────────────────────────────────
+
+    /// Run ffmpeg with `cmd` into a `.partial.` file, then put that in place
+    /// of the recording as `target`.
+    async fn rewrite(
+        &self,
+        files: &Files,
+        cmd: Vec<OsString>,
+        target: &Path,
+    ) -> anyhow::Result<Outcome> {
+        let media = Path::new(&files.media);
+        let partial = partial_path(target);
+        self.ffmpeg(cmd, &partial).await?;
+
+        tokio::fs::rename(&partial, target)
+            .await
+            .with_context(|| format!("move {} to {}", partial.display(), target.display()))?;
+        if target != media
+            && let Err(e) = tokio::fs::remove_file(media).await
+        {
+            warn!("Could not delete {}: {e}", media.display());
+        }
+        Ok(Outcome::Done(
+            files.renamed(&files.media, &target.to_string_lossy()),
+        ))
+    }
+
+    /// Run ffmpeg, writing to `output`, with its output in the item's log.
+    /// `output` is removed if ffmpeg fails or the item is cancelled.
+    async fn ffmpeg(&self, cmd: Vec<OsString>, output: &Path) -> anyhow::Result<()> {
+        let mut child = Command::new(&self.settings.ffmpeg_path)
+            .args(["-hide_banner", "-nostdin", "-y", "-loglevel", "warning"])
+            .args(&cmd)
+            .arg(output)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("could not run ffmpeg ({})", self.settings.ffmpeg_path))?;
+
+        let mut lines = BufReader::new(child.stderr.take().expect("stderr piped")).lines();
+        let mut last = Vec::new();
+        loop {
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        self.log.write(Stream::Ffmpeg, &line).await;
+                        last.push(line);
+                        if last.len() > ERROR_LINES {
+                            last.remove(0);
+                        }
+                    }
+                    _ => break,
+                },
+                _ = self.cancel.cancelled() => {
+                    let _ = child.kill().await;
+                    let _ = tokio::fs::remove_file(output).await;
+                    bail!("cancelled");
+                }
+            }
+        }
+
+        let status = child.wait().await.context("wait for ffmpeg")?;
+        if !status.success() {
+            let _ = tokio::fs::remove_file(output).await;
+            bail!("ffmpeg exited with {status}: {}", last.join(" / "));
+        }
+        Ok(())
+    }
+
+    /// Record the files as they are now.
+    async fn save(&self, files: &Files) -> sqlx::Result<()> {
+        artefacts::record(self.db, &self.item.id, &files.files).await?;
+        sqlx::query("UPDATE queue_items SET output_path=? WHERE id=?")
+            .bind(&files.media)
+            .bind(&self.item.id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Move a step to `status` and tell the item's watchers.
+    async fn mark(&self, step_id: i64, status: StepStatus, message: Option<&str>) {
+        let row: sqlx::Result<PostprocessStep> = sqlx::query_as(
+            "UPDATE postprocess_steps \
+             SET status=?1, message=?2, runs=runs + (?1 = 'running'), \
+                 started_at=CASE WHEN ?1 = 'running' THEN ?3 ELSE started_at END, \
+                 ended_at=CASE WHEN ?1 = 'running' THEN NULL ELSE ?3 END \
+             WHERE id=?4 \
+             RETURNING *",
+        )
+        .bind(status)
+        .bind(message)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(step_id)
+        .fetch_one(self.db)
+        .await;
+        match row {
+            Ok(step) => {
+                let _ = self.events.send(WsEvent::StepChange {
+                    id: self.item.id.clone(),
+                    step: Box::new(step),
+                });
+            }
+            Err(e) => warn!("Could not update post-processing step {step_id}: {e}"),
+        }
+    }
+
+    async fn note(&self, line: &str) {
+        self.log.write(Stream::Tapedeck, line).await;
+    }
+}
+
+/// `args([...])` as owned ffmpeg arguments.
+fn args<I, S>(items: I) -> impl Iterator<Item = OsString>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    items.into_iter().map(|s| s.as_ref().to_os_string())
+}
+
+/// Lower-case extension of `path`, or `""`.
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default()
+}
+
+/// `dir/stem.partial.ext` next to `target`. The library never indexes these.
+fn partial_path(target: &Path) -> PathBuf {
+    let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!("{stem}.partial.{}", extension(target)))
+}
+
+/// Rename, or copy and delete when `to` is on another filesystem.
+async fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if tokio::fs::rename(from, to).await.is_ok() {
+        return Ok(());
+    }
+    tokio::fs::copy(from, to)
+        .await
+        .with_context(|| format!("copy {} to {}", from.display(), to.display()))?;
+    tokio::fs::remove_file(from)
+        .await
+        .with_context(|| format!("delete {}", from.display()))
+}
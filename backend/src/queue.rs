@@ -1,31 +1,274 @@
 /// Background download queue.
 ///
-/// A `QueueHandle` is cloned into every Axum handler. Work items are sent over
-/// a Tokio channel. A pool of worker tasks drains the channel up to
-/// `max_concurrent` downloads at a time.
-use std::sync::Arc;
+/// A `QueueHandle` is cloned into every Axum handler. Handlers persist items
+/// and then wake the scheduler, which pulls the next eligible item from the
+/// database in `priority`, then `added_at` order whenever one of the
+/// `max_concurrent` worker slots is free.
+///
+/// An item only ever runs once at a time: the scheduler claims it with an
+/// atomic `queued → downloading` transition, passing over ids that are still
+/// in flight, and the in-flight set refuses a second worker for an id that is
+/// still running.
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
-use tokio::sync::{Semaphore, broadcast, mpsc};
+use sqlx::SqliteConnection;
+use tokio::sync::{Notify, Semaphore, broadcast};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 use crate::{
+    artefacts,
     config::AppConfig,
     db::Db,
-    iplayer::{self, DownloadOptions},
-    models::{DownloadStatus, QueueItem, WsEvent},
+    download_log::{DownloadLogs, ItemLog, Stream},
+    hooks::Hooks,
+    iplayer::{self, DownloadError, DownloadOptions, Downloaded},
+    library,
+    media::{self, MediaInfo},
+    models::{
+        AddQueueItemRequest, ArtefactKind, DownloadErrorKind, DownloadStatus, QueueItem,
+        StepStatus, WebhookEvent, WsEvent,
+    },
+    naming, postprocess,
+    settings::{RuntimeSettings, SettingsService},
+    webhooks::Webhooks,
 };
 
 // ── Public handle ──────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone)]
 pub struct QueueHandle {
-    tx: mpsc::UnboundedSender<String>, // sends item IDs to the worker pool
+    wake: Arc<Notify>, // nudges the scheduler to re-check the database
+    in_flight: InFlight,
+    worker: Worker,
+}
+
+/// What a task downloading or finishing off an item needs.
+#[derive(Debug, Clone)]
+struct Worker {
+    db: Db,
+    settings: SettingsService,
+    events: broadcast::Sender<WsEvent>,
+    logs: DownloadLogs,
+    hooks: Hooks,
+    webhooks: Webhooks,
+    /// One permit per download or post-processing run allowed at once.
+    slots: Arc<Semaphore>,
 }
 
 impl QueueHandle {
-    /// Enqueue an already-persisted item by ID.
-    pub fn enqueue(&self, id: String) {
-        let _ = self.tx.send(id);
+    /// Wake the scheduler so it picks up newly queued or newly due items.
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Cancel the in-flight download for `id`, killing its get_iplayer process.
+    /// Returns `false` if no worker is currently running that item.
+    pub fn cancel(&self, id: &str) -> bool {
+        self.in_flight.cancel(id)
+    }
+
+    /// Whether a worker is still running `id` (including a cancelled download
+    /// that is still cleaning up).
+    pub fn is_running(&self, id: &str) -> bool {
+        self.in_flight.contains(id)
+    }
+
+    /// Run `id`'s pending post-processing steps in the background, e.g. after
+    /// a failed step has been reset. It can be cancelled like a download.
+    /// Returns `false` if a worker is still running the item.
+    pub fn resume_processing(&self, id: &str) -> bool {
+        let Some(cancel) = self.in_flight.claim(id) else {
+            return false;
+        };
+        let worker = self.worker.clone();
+        let in_flight = self.in_flight.clone();
+        let wake = Arc::clone(&self.wake);
+        let id = id.to_string();
+        tokio::spawn(async move {
+            // ffmpeg is as heavy as a download, so wait for a slot like one.
+            let permit = tokio::select! {
+                permit = worker.slots.acquire() => permit.ok(),
+                _ = cancel.cancelled() => None,
+            };
+            if permit.is_some() {
+                resume_processing(&worker, &id, &cancel).await;
+            }
+            drop(permit);
+            in_flight.release(&id);
+            wake.notify_one();
+        });
+        true
+    }
+}
+
+// ── Persisting items ───────────────────────────────────────────────────────────
+
+/// Insert a new `queued` item owned by `user_id`. Quality and subtitles the
+/// request leaves out come from the user's defaults, then the runtime
+/// settings. The caller is responsible for waking the scheduler and
+/// announcing it.
+pub async fn insert_item(
+    db: &mut SqliteConnection,
+    settings: &RuntimeSettings,
+    req: &AddQueueItemRequest,
+    user_id: &str,
+) -> sqlx::Result<QueueItem> {
+    let id = QueueItem::new_id();
+    let now = chrono::Utc::now().to_rfc3339();
+    let scheduled = req.scheduled_at.map(|t| t.to_rfc3339());
+    let (user_quality, user_subtitles): (Option<String>, Option<bool>) =
+        sqlx::query_as("SELECT default_quality, default_subtitles FROM users WHERE id=?")
+            .bind(user_id)
+            .fetch_optional(&mut *db)
+            .await?
+            .unwrap_or_default();
+    let quality = req
+        .quality
+        .clone()
+        .or(user_quality)
+        .unwrap_or_else(|| settings.default_quality.clone());
+    let subtitles = req
+        .subtitles
+        .or(user_subtitles)
+        .unwrap_or(settings.subtitles);
+
+    sqlx::query(
+        "INSERT INTO queue_items \
+         (id, pid, title, series, episode, channel, media_type, thumbnail_url, \
+          added_at, scheduled_at, priority, status, quality, subtitles, metadata, user_id, \
+          filename_template, duration_secs) \
+         VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)",
+    )
+    .bind(&id)
+    .bind(&req.pid)
+    .bind(&req.title)
+    .bind(&req.series)
+    .bind(&req.episode)
+    .bind(&req.channel)
+    .bind(&req.media_type)
+    .bind(&req.thumbnail_url)
+    .bind(&now)
+    .bind(&scheduled)
+    .bind(req.priority)
+    .bind(DownloadStatus::Queued.to_string())
+    .bind(&quality)
+    .bind(subtitles)
+    .bind("{}")
+    .bind(user_id)
+    .bind(&req.filename_template)
+    .bind(req.duration_secs)
+    .execute(&mut *db)
+    .await?;
+
+    sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
+        .bind(&id)
+        .fetch_one(&mut *db)
+        .await
+}
+
+/// Queue `req` for an automatic source unless its PID has been seen before,
+/// recording it in `download_history` under `source` in the same
+/// transaction. The history row goes in first: its primary key makes a
+/// second check racing for the same PID (a manual check during a periodic
+/// one, say) find it taken and queue nothing. Returns `None` if the PID was
+/// already known. The caller wakes the scheduler and announces the item.
+pub async fn insert_new_item(
+    db: &Db,
+    settings: &RuntimeSettings,
+    req: &AddQueueItemRequest,
+    user_id: &str,
+    source: &str,
+) -> sqlx::Result<Option<QueueItem>> {
+    let mut tx = db.begin().await?;
+    let recorded =
+        sqlx::query("INSERT OR IGNORE INTO download_history (pid, source) VALUES (?, ?)")
+            .bind(&req.pid)
+            .bind(source)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    let (queued,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM queue_items WHERE pid=?)")
+            .bind(&req.pid)
+            .fetch_one(&mut *tx)
+            .await?;
+    if recorded == 0 || queued {
+        tx.commit().await?;
+        return Ok(None);
+    }
+    let item = insert_item(&mut tx, settings, req, user_id).await?;
+    tx.commit().await?;
+    Ok(Some(item))
+}
+
+/// Whether `pid` is already in the queue or has ever been downloaded or
+/// auto-queued. Automatic sources use this so a programme is fetched once.
+pub async fn is_known_pid(db: &Db, pid: &str) -> sqlx::Result<bool> {
+    let (known,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM queue_items WHERE pid=?) \
+             OR EXISTS (SELECT 1 FROM download_history WHERE pid=?)",
+    )
+    .bind(pid)
+    .bind(pid)
+    .fetch_one(db)
+    .await?;
+    Ok(known)
+}
+
+// ── In-flight set ──────────────────────────────────────────────────────────────
+
+/// Items currently owned by a worker, each with the token that cancels it.
+#[derive(Debug, Clone, Default)]
+struct InFlight(Arc<Mutex<HashMap<String, CancellationToken>>>);
+
+impl InFlight {
+    /// Take ownership of `id`. Returns `None` if another worker already has it.
+    fn claim(&self, id: &str) -> Option<CancellationToken> {
+        let mut map = self.0.lock().expect("in-flight set poisoned");
+        if map.contains_key(id) {
+            return None;
+        }
+        let token = CancellationToken::new();
+        map.insert(id.to_string(), token.clone());
+        Some(token)
+    }
+
+    fn release(&self, id: &str) {
+        self.0.lock().expect("in-flight set poisoned").remove(id);
+    }
+
+    /// The ids currently owned by a worker.
+    fn ids(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .expect("in-flight set poisoned")
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.0
+            .lock()
+            .expect("in-flight set poisoned")
+            .contains_key(id)
+    }
+
+    fn cancel(&self, id: &str) -> bool {
+        match self.0.lock().expect("in-flight set poisoned").get(id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
     }
 }
 
@@ -34,56 +277,200 @@ impl QueueHandle {
 pub fn start_worker_pool(
     db: Db,
     config: Arc<AppConfig>,
+    settings: SettingsService,
     events: broadcast::Sender<WsEvent>,
+    logs: DownloadLogs,
+    webhooks: Webhooks,
 ) -> QueueHandle {
-    let (tx, rx) = mpsc::unbounded_channel::<String>();
-    let max = config.max_concurrent;
+    let wake = Arc::new(Notify::new());
+    let in_flight = InFlight::default();
+    let max_concurrent = settings.current().max_concurrent.max(1);
+    let slots = Arc::new(Semaphore::new(max_concurrent));
+    tokio::spawn(resize_on_change(
+        Arc::clone(&slots),
+        max_concurrent,
+        settings.clone(),
+    ));
+    let worker = Worker {
+        hooks: Hooks::new(db.clone(), settings.clone(), logs.clone()),
+        db,
+        settings,
+        events,
+        logs,
+        webhooks,
+        slots,
+    };
 
-    tokio::spawn(run_pool(rx, tx.clone(), db, config, events, max));
+    tokio::spawn(run_pool(
+        Arc::clone(&wake),
+        in_flight.clone(),
+        worker.clone(),
+        config,
+    ));
 
-    QueueHandle { tx }
+    QueueHandle {
+        wake,
+        in_flight,
+        worker,
+    }
 }
 
-async fn run_pool(
-    mut rx: mpsc::UnboundedReceiver<String>,
-    tx: mpsc::UnboundedSender<String>,
-    db: Db,
-    config: Arc<AppConfig>,
-    events: broadcast::Sender<WsEvent>,
-    max_concurrent: usize,
-) {
-    let sem = Arc::new(Semaphore::new(max_concurrent));
+async fn run_pool(wake: Arc<Notify>, in_flight: InFlight, worker: Worker, config: Arc<AppConfig>) {
+    let Worker {
+        db,
+        events,
+        webhooks,
+        slots,
+        ..
+    } = worker.clone();
 
-    // On startup, resume any items that were mid-download or still queued when
-    // the service last stopped.
-    requeue_interrupted(&db, &events, &tx).await;
+    // On startup, put anything that was mid-download when the service last
+    // stopped back in the queue; the scheduler below then resumes it along
+    // with everything else that is still `queued`.
+    reset_interrupted(&db, &events, &webhooks).await;
 
-    while let Some(id) = rx.recv().await {
-        let permit = Arc::clone(&sem)
+    loop {
+        let permit = Arc::clone(&slots)
             .acquire_owned()
             .await
             .expect("semaphore closed");
 
-        let db = db.clone();
+        // Only choose the next item once a slot is free, so a reorder or a
+        // newly added high-priority item is honoured right up to the moment a
+        // download starts.
+        let id = loop {
+            match claim_next(&db, &in_flight.ids()).await {
+                Ok(Some(id)) => break id,
+                Ok(None) => {
+                    // Nothing is due, so the queue may have gone idle without
+                    // anything finishing (a cancellation, or a restart).
+                    worker.hooks.flush();
+                    wake.notified().await
+                }
+                Err(e) => {
+                    error!("Scheduler: failed to claim next queue item: {e}");
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                }
+            }
+        };
+
+        // `claim_next` skips ids that are in flight, but a worker may have
+        // taken this one since (e.g. post-processing resumed in between).
+        // Hand the row back rather than run it twice.
+        let Some(cancel) = in_flight.claim(&id) else {
+            warn!("Item {id} is still running in another worker, deferring");
+            if let Err(e) = unclaim(&db, &id).await {
+                error!("Failed to return {id} to the queue: {e}");
+            }
+            drop(permit);
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            continue;
+        };
+
+        let _ = events.send(WsEvent::StatusChange {
+            id: id.clone(),
+            status: DownloadStatus::Downloading.to_string(),
+        });
+
+        let worker = worker.clone();
         let config = Arc::clone(&config);
-        let events = events.clone();
+        let in_flight = in_flight.clone();
+        let wake = Arc::clone(&wake);
 
         tokio::spawn(async move {
             let _permit = permit; // held for the duration of the download
-            run_download(id, db, config, events).await;
+            run_download(id.clone(), worker, config, cancel).await;
+            in_flight.release(&id);
+            // The item may have been queued again while it was winding down
+            // (e.g. a cancelled download retried before its cleanup finished)
+            // and passed over by the scheduler in the meantime.
+            wake.notify_one();
         });
     }
 }
 
-/// On startup: reset any interrupted-mid-download items back to `queued`, then
-/// push all pending `queued` items (including any that were already queued before
-/// the restart) back into the worker channel so they're actually downloaded.
-async fn requeue_interrupted(
-    db: &Db,
-    events: &broadcast::Sender<WsEvent>,
-    tx: &mpsc::UnboundedSender<String>,
-) {
-    // 1. Reset anything stuck in `downloading` back to `queued`
+/// Keep the worker semaphore in step with the `max_concurrent` setting.
+///
+/// Growing adds permits straight away. Shrinking forgets the free permits
+/// and owes the rest, which are taken out of circulation as running
+/// downloads finish, so nothing is interrupted. Growing again while permits
+/// are owed cancels the debt first, so no change is ever missed.
+async fn resize_on_change(sem: Arc<Semaphore>, mut current: usize, settings: SettingsService) {
+    let mut rx = settings.subscribe();
+    let mut owed = 0;
+    loop {
+        tokio::select! {
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let target = rx.borrow_and_update().max_concurrent.max(1);
+                if target > current {
+                    let grow = target - current;
+                    let repaid = grow.min(owed);
+                    owed -= repaid;
+                    sem.add_permits(grow - repaid);
+                } else if target < current {
+                    let shrink = current - target;
+                    owed += shrink - sem.forget_permits(shrink);
+                }
+                if target != current {
+                    info!("Worker pool resized from {current} to {target}");
+                    current = target;
+                }
+            }
+            permit = sem.acquire(), if owed > 0 => match permit {
+                Ok(permit) => {
+                    permit.forget();
+                    owed -= 1;
+                }
+                Err(_) => return, // semaphore closed
+            },
+        }
+    }
+}
+
+/// Atomically claim the highest-priority `queued` item that is unscheduled or
+/// already due and not in `in_flight`, moving it to `downloading`. The status
+/// check in the outer `WHERE` means a row can only ever be claimed once per
+/// `queued` spell.
+async fn claim_next(db: &Db, in_flight: &[String]) -> sqlx::Result<Option<String>> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let row: Option<(String,)> = sqlx::query_as(
+        "UPDATE queue_items SET status='downloading', started_at=?, progress=0 \
+         WHERE status = 'queued' AND id = ( \
+             SELECT id FROM queue_items \
+             WHERE status = 'queued' \
+               AND (scheduled_at IS NULL OR scheduled_at <= ?) \
+               AND id NOT IN (SELECT value FROM json_each(?)) \
+             ORDER BY priority ASC, added_at ASC \
+             LIMIT 1 \
+         ) \
+         RETURNING id",
+    )
+    .bind(&now)
+    .bind(&now)
+    .bind(serde_json::to_string(in_flight).unwrap_or_default())
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(|(id,)| id))
+}
+
+/// Undo a claim that could not be handed to a worker.
+async fn unclaim(db: &Db, id: &str) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE queue_items SET status='queued', started_at=NULL \
+         WHERE id=? AND status='downloading'",
+    )
+    .bind(id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// On startup: reset any interrupted-mid-download items back to `queued` so the
+/// scheduler picks them up again.
+async fn reset_interrupted(db: &Db, events: &broadcast::Sender<WsEvent>, webhooks: &Webhooks) {
     let interrupted: Vec<(String,)> =
         match sqlx::query_as("SELECT id FROM queue_items WHERE status = 'downloading'")
             .fetch_all(db)
@@ -118,35 +505,194 @@ async fn requeue_interrupted(
         });
     }
 
-    // 2. Enqueue all items currently in `queued` state that are either
-    //    unscheduled or whose scheduled time has already passed.
-    //    (Future-scheduled items are handled by the minute-tick watcher.)
-    let now = chrono::Utc::now().to_rfc3339();
-    let queued: Vec<(String,)> = match sqlx::query_as(
-        "SELECT id FROM queue_items \
-         WHERE status = 'queued' \
-           AND (scheduled_at IS NULL OR scheduled_at <= ?) \
-         ORDER BY added_at ASC",
+    // So did any post-processing; the failed step can be retried.
+    if let Err(e) = postprocess::fail_interrupted(db).await {
+        error!("Startup: failed to close interrupted post-processing steps: {e}");
+    }
+    let processing: Vec<(String,)> = sqlx::query_as(
+        "UPDATE queue_items \
+         SET status='failed', completed_at=?, error='Post-processing was interrupted by a restart', \
+             error_kind=? \
+         WHERE status='processing' \
+         RETURNING id",
     )
-    .bind(&now)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(DownloadErrorKind::PostProcessing)
     .fetch_all(db)
     .await
+    .unwrap_or_else(|e| {
+        error!("Startup: failed to fail interrupted post-processing: {e}");
+        Vec::new()
+    });
+    for (id,) in processing {
+        webhooks.notify(WebhookEvent::DownloadFailed, &id).await;
+        let _ = events.send(WsEvent::StatusChange {
+            id,
+            status: DownloadStatus::Failed.to_string(),
+        });
+    }
+
+    // Their get_iplayer runs died with the old process.
+    if let Err(e) = sqlx::query(
+        "UPDATE download_attempts SET ended_at=?, error='Interrupted by a restart' \
+         WHERE ended_at IS NULL",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(db)
+    .await
+    {
+        error!("Startup: failed to close interrupted download attempts: {e}");
+    }
+}
+
+// ── Attempt history ────────────────────────────────────────────────────────────
+
+/// Record the start of a get_iplayer run for `item_id`. Attempts are numbered
+/// across manual retries too, so the history reads in order. Returns the row
+/// id and attempt number, or `None` if the row could not be written; the
+/// download goes ahead regardless.
+async fn start_attempt(db: &Db, item_id: &str) -> Option<(i64, i64)> {
+    sqlx::query_as(
+        "INSERT INTO download_attempts (queue_item_id, attempt, started_at) \
+         VALUES (?1, \
+                 (SELECT COALESCE(MAX(attempt), 0) + 1 FROM download_attempts \
+                  WHERE queue_item_id = ?1), \
+                 ?2) \
+         RETURNING id, attempt",
+    )
+    .bind(item_id)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_one(db)
+    .await
+    .inspect_err(|e| warn!("Could not record download attempt for {item_id}: {e}"))
+    .ok()
+}
+
+/// Fill in how an attempt started by [`start_attempt`] ended.
+/// Save a running time the item was queued without but an attempt found, so
+/// later attempts and the item itself have it.
+async fn found_duration(
+    db: &Db,
+    id: &str,
+    result: &Result<Downloaded, DownloadError>,
+) -> Option<i64> {
+    let report = match result {
+        Ok(downloaded) => &downloaded.report,
+        Err(e) => &e.report,
+    };
+    let secs = report.duration_secs?.round() as i64;
+    if let Err(e) =
+        sqlx::query("UPDATE queue_items SET duration_secs=? WHERE id=? AND duration_secs IS NULL")
+            .bind(secs)
+            .bind(id)
+            .execute(db)
+            .await
+    {
+        warn!("Could not record the duration of {id}: {e}");
+    }
+    Some(secs)
+}
+
+async fn finish_attempt(
+    db: &Db,
+    attempt_id: Option<i64>,
+    result: &Result<Downloaded, DownloadError>,
+) {
+    let Some(attempt_id) = attempt_id else {
+        return;
+    };
+    let (report, kind, error) = match result {
+        Ok(downloaded) => (&downloaded.report, None, None),
+        Err(e) => (&e.report, Some(e.kind), Some(e.to_string())),
+    };
+    let log_tail = (!report.log_tail.is_empty()).then(|| report.log_tail.join("\n"));
+    if let Err(e) = sqlx::query(
+        "UPDATE download_attempts \
+         SET ended_at=?, exit_code=?, error_kind=?, error=?, log_tail=?, bytes=?, quality=? \
+         WHERE id=?",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(report.exit_code)
+    .bind(kind)
+    .bind(error)
+    .bind(log_tail)
+    .bind(report.bytes)
+    .bind(&report.quality)
+    .bind(attempt_id)
+    .execute(db)
+    .await
     {
-        Ok(rows) => rows,
+        warn!("Could not record the end of download attempt {attempt_id}: {e}");
+    }
+}
+
+/// Probe the finished recording at `path` and store what it contains: file
+/// size, running time, resolution, codecs and subtitles, both in typed
+/// columns and under "media" in the item's `metadata`. `files` are all the
+/// files the download produced. A missing ffprobe only leaves the probed
+/// fields empty.
+async fn record_media_info(
+    db: &Db,
+    settings: &RuntimeSettings,
+    item: &QueueItem,
+    path: &str,
+    files: &[String],
+) {
+    let path = std::path::Path::new(path);
+    let file_size = match tokio::fs::metadata(path).await {
+        Ok(m) => Some(m.len() as i64),
         Err(e) => {
-            error!("Startup: failed to query queued items: {e}");
-            return;
+            warn!("Could not stat {}: {e}", path.display());
+            None
+        }
+    };
+    let (info, probed) = match media::probe(&settings.ffmpeg_path, path).await {
+        Ok(info) => (info, true),
+        Err(e) => {
+            warn!("Could not probe {}: {e:#}", path.display());
+            (MediaInfo::default(), false)
         }
     };
+    let sidecars: Vec<&str> = files
+        .iter()
+        .map(String::as_str)
+        .filter(|f| artefacts::kind(std::path::Path::new(f)) == ArtefactKind::Subtitles)
+        .collect();
 
-    info!("Startup: resuming {} queued item(s)", queued.len());
+    let mut metadata = match serde_json::from_str(&item.metadata) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    let mut media = match serde_json::to_value(&info) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    media.insert("file_size".into(), file_size.into());
+    media.insert("subtitle_files".into(), sidecars.clone().into());
+    metadata.insert("media".into(), media.into());
 
-    for (id,) in queued {
-        info!("Startup: re-enqueuing item {id}");
-        if tx.send(id).is_err() {
-            error!("Startup: worker channel closed, aborting requeue");
-            break;
-        }
+    if let Err(e) = sqlx::query(
+        "UPDATE queue_items \
+         SET file_size=?, duration_secs=COALESCE(?, duration_secs), video_codec=?, \
+             audio_codec=?, width=?, height=?, audio_channels=?, embedded_subtitles=?, \
+             sidecar_subtitles=?, metadata=? \
+         WHERE id=?",
+    )
+    .bind(file_size)
+    .bind(info.duration_secs.map(|d| d.round() as i64))
+    .bind(&info.video_codec)
+    .bind(&info.audio_codec)
+    .bind(info.width)
+    .bind(info.height)
+    .bind(info.audio_channels)
+    .bind(probed.then_some(info.embedded_subtitles))
+    .bind(!sidecars.is_empty())
+    .bind(serde_json::Value::Object(metadata).to_string())
+    .bind(&item.id)
+    .execute(db)
+    .await
+    {
+        warn!("Could not record media details for {}: {e}", item.id);
     }
 }
 
@@ -154,10 +700,17 @@ async fn requeue_interrupted(
 
 async fn run_download(
     id: String,
-    db: Db,
+    worker: Worker,
     config: Arc<AppConfig>,
-    events: broadcast::Sender<WsEvent>,
+    cancel: CancellationToken,
 ) {
+    let Worker {
+        db,
+        settings,
+        events,
+        logs,
+        ..
+    } = worker.clone();
     // Fetch the item
     let item: Option<QueueItem> = sqlx::query_as("SELECT * FROM queue_items WHERE id = ?")
         .bind(&id)
@@ -173,70 +726,60 @@ async fn run_download(
         }
     };
 
-    // Check it hasn't been cancelled since it was enqueued
-    if item.status == DownloadStatus::Cancelled.to_string() {
-        info!("Item {id} was cancelled before download started");
-        return;
-    }
-
-    // Mark as downloading
-    let now = chrono::Utc::now().to_rfc3339();
-    if let Err(e) = sqlx::query(
-        "UPDATE queue_items SET status='downloading', started_at=?, progress=0 WHERE id=?",
-    )
-    .bind(&now)
-    .bind(&id)
-    .execute(&db)
-    .await
-    {
-        error!("Failed to mark {id} as downloading: {e}");
-        return;
-    }
-
-    let _ = events.send(WsEvent::StatusChange {
-        id: id.clone(),
-        status: DownloadStatus::Downloading.to_string(),
-    });
-
     info!("Starting download for PID {} (item {})", item.pid, id);
-
-    // ── Read max_download_retries from DB settings (falls back to env config) ──
-    let max_retries: u32 = {
-        let row: Option<(String,)> =
-            sqlx::query_as("SELECT value FROM settings WHERE key='max_download_retries'")
-                .fetch_optional(&db)
-                .await
-                .unwrap_or(None);
-        row.and_then(|(v,)| v.parse().ok())
-            .unwrap_or(config.max_download_retries)
-    };
+    let log = logs.writer(&id);
+    // Once per download, however many attempts it takes.
+    worker
+        .webhooks
+        .notify(WebhookEvent::DownloadStarted, &id)
+        .await;
 
     // ── Download with exponential-backoff retries ──────────────────────────────
+    // Settings are re-read on every attempt so changes made while an item is
+    // backing off apply to its next attempt.
+    let mut duration_secs = item.duration_secs;
     let mut attempt = 0u32;
+    let mut max_retries;
     let final_result = loop {
         let id_clone = id.clone();
         let db_clone = db.clone();
         let events_clone = events.clone();
 
+        let current = settings.current();
+        max_retries = current.max_download_retries;
+
+        if let Err(e) = tokio::fs::create_dir_all(&current.output_dir).await {
+            warn!(
+                "Could not create output directory {}: {e}",
+                current.output_dir
+            );
+        }
+
         let opts = DownloadOptions {
             pid: &item.pid,
             media_type: &item.media_type,
-            quality: &item.quality,
+            quality: current.effective_quality(&item.media_type, &item.quality),
             subtitles: item.subtitles,
-            output_dir: &config.output_dir,
-            get_iplayer_path: &config.get_iplayer_path,
-            ffmpeg_path: &config.ffmpeg_path,
+            output_dir: &current.output_dir,
+            get_iplayer_path: &current.get_iplayer_path,
+            ffmpeg_path: &current.ffmpeg_path,
             cache_dir: &config.iplayer_cache_dir,
-            proxy: config.proxy.as_deref(),
+            proxy: current.proxy.as_deref(),
+            duration_secs: duration_secs.map(|d| d as f64),
+            log: &log,
+            cancel: &cancel,
         };
 
         let result = {
             // Spawn a heartbeat that logs elapsed time every 30 s while the
-            // download is running.  This keeps docker logs alive and sends WS
-            // events so the UI indeterminate bar stays live.
+            // download is running.  This keeps docker logs alive and, until
+            // get_iplayer reports progress of its own, sends WS events so the
+            // UI indeterminate bar stays live.
             let hb_id = id_clone.clone();
             let hb_events = events_clone.clone();
             let start = std::time::Instant::now();
+            let reporting = Arc::new(AtomicBool::new(false));
+            let hb_reporting = Arc::clone(&reporting);
             let heartbeat = tokio::spawn(async move {
                 let mut ticker = tokio::time::interval(std::time::Duration::from_secs(30));
                 ticker.tick().await; // skip the immediate first tick
@@ -244,16 +787,33 @@ async fn run_download(
                     ticker.tick().await;
                     let elapsed = start.elapsed().as_secs();
                     info!("Download in progress for {} (elapsed: {}s)", hb_id, elapsed);
+                    if hb_reporting.load(Ordering::Relaxed) {
+                        continue;
+                    }
                     let _ = hb_events.send(WsEvent::Progress {
                         id: hb_id.clone(),
                         progress: 0.0,
                         speed: None,
-                        eta: Some(format!("{}m elapsed", elapsed / 60)),
+                        eta: None,
+                        elapsed: Some(iplayer::format_hms(elapsed as f64)),
+                        bytes: None,
+                        total_bytes: None,
                     });
                 }
             });
 
+            let attempt_row = start_attempt(&db, &id).await;
+            let attempt_no = attempt_row.map_or_else(String::new, |(_, n)| format!(" {n}"));
+            log.write(
+                Stream::Tapedeck,
+                &format!(
+                    "Attempt{attempt_no} started: PID {}, quality {}",
+                    item.pid, opts.quality
+                ),
+            )
+            .await;
             let result = iplayer::download(opts, move |progress| {
+                reporting.store(true, Ordering::Relaxed);
                 let id = id_clone.clone();
                 let db = db_clone.clone();
                 let events = events_clone.clone();
@@ -273,32 +833,53 @@ async fn run_download(
                         progress: progress.percent,
                         speed: progress.speed,
                         eta: progress.eta,
+                        elapsed: progress.elapsed,
+                        bytes: progress.bytes,
+                        total_bytes: progress.total_bytes,
                     });
                 });
             })
             .await;
 
             heartbeat.abort();
+            finish_attempt(&db, attempt_row.map(|(row, _)| row), &result).await;
+            if duration_secs.is_none() {
+                duration_secs = found_duration(&db, &id, &result).await;
+            }
+            let outcome = match &result {
+                Ok(_) => "succeeded".to_string(),
+                Err(e) => format!("failed: {}", e.kind.describe()),
+            };
+            log.write(Stream::Tapedeck, &format!("Attempt{attempt_no} {outcome}"))
+                .await;
             result
         };
 
         match result {
-            Ok(path) => break Ok(path),
+            Ok(downloaded) => break Ok(downloaded),
             Err(e) => {
-                if attempt >= max_retries {
+                if e.kind.is_permanent() {
+                    info!("Not retrying {id}: {}", e.kind.describe());
+                    break Err(e);
+                }
+                if attempt >= max_retries || cancel.is_cancelled() {
                     break Err(e);
                 }
                 attempt += 1;
                 let delay_secs = 2u64.pow(attempt);
+                // `attempt` retries so far, so this was attempt number `attempt`
+                // of at most `max_retries + 1`.
+                let attempts = max_retries + 1;
                 warn!(
-                    "Download attempt {attempt}/{max_retries} failed for {id}, \
+                    "Download attempt {attempt}/{attempts} failed for {id}, \
                      retrying in {delay_secs}s: {e:#}"
                 );
                 let error_msg = format!(
-                    "Attempt {attempt}/{max_retries} failed: {e}. Retrying in {delay_secs}s\u{2026}"
+                    "Attempt {attempt}/{attempts} failed: {e}. Retrying in {delay_secs}s\u{2026}"
                 );
-                let _ = sqlx::query("UPDATE queue_items SET error=? WHERE id=?")
+                let _ = sqlx::query("UPDATE queue_items SET error=?, error_kind=? WHERE id=?")
                     .bind(&error_msg)
+                    .bind(e.kind)
                     .bind(&id)
                     .execute(&db)
                     .await;
@@ -306,13 +887,18 @@ async fn run_download(
                 let _ = events.send(WsEvent::Error {
                     id: id.clone(),
                     message: error_msg,
+                    kind: Some(e.kind),
                 });
-                tokio::time::sleep(std::time::Duration::from_secs(delay_secs)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(std::time::Duration::from_secs(delay_secs)) => {}
+                    _ = cancel.cancelled() => break Err(e),
+                }
                 // Clear the stale error and signal a fresh attempt is starting
-                let _ = sqlx::query("UPDATE queue_items SET error=NULL WHERE id=?")
-                    .bind(&id)
-                    .execute(&db)
-                    .await;
+                let _ =
+                    sqlx::query("UPDATE queue_items SET error=NULL, error_kind=NULL WHERE id=?")
+                        .bind(&id)
+                        .execute(&db)
+                        .await;
                 let _ = events.send(WsEvent::StatusChange {
                     id: id.clone(),
                     status: DownloadStatus::Downloading.to_string(),
@@ -324,7 +910,11 @@ async fn run_download(
     let completed_at = chrono::Utc::now().to_rfc3339();
 
     match final_result {
-        Ok(output_path) => {
+        Ok(Downloaded {
+            output_path,
+            files: reported,
+            ..
+        }) => {
             info!("Download complete for {id}: {output_path}");
 
             // Check if it was cancelled while running
@@ -336,43 +926,87 @@ async fn run_download(
                     .unwrap_or(None);
 
             if current.map(|(s,)| s) == Some(DownloadStatus::Cancelled.to_string()) {
-                // Clean up the file written during a cancelled download
-                if !output_path.is_empty() {
-                    if let Err(e) = tokio::fs::remove_file(&output_path).await {
-                        warn!("Could not delete cancelled download file {output_path}: {e}");
+                // Clean up the files this download said it wrote; others
+                // sharing the name may belong to an earlier recording.
+                for path in &reported {
+                    if let Err(e) = tokio::fs::remove_file(path).await {
+                        warn!("Could not delete cancelled download file {path}: {e}");
                     }
                 }
                 return;
             }
 
+            // Everything get_iplayer said it wrote, plus anything it didn't
+            // mention that is named after the recording.
+            let mut files = reported;
+            if !output_path.is_empty() {
+                match artefacts::siblings(std::path::Path::new(&output_path)).await {
+                    Ok(siblings) => {
+                        for path in siblings {
+                            let path = path.to_string_lossy().into_owned();
+                            if !files.contains(&path) {
+                                files.push(path);
+                            }
+                        }
+                    }
+                    Err(e) => warn!("Could not look for files next to {output_path}: {e}"),
+                }
+            }
+
+            let output_path = match output_path.as_str() {
+                "" => None,
+                path => {
+                    let current = settings.current();
+                    let template = item
+                        .filename_template
+                        .as_deref()
+                        .unwrap_or(&current.filename_template);
+                    let placed =
+                        naming::place(&current.output_dir, template, &item, path, &files).await;
+                    files = placed.files;
+                    Some(placed.media)
+                }
+            };
+            if let Err(e) = artefacts::record(&db, &id, &files).await {
+                warn!("Could not record the files of {id}: {e}");
+            }
+            if output_path.is_some()
+                && let Err(e) =
+                    postprocess::plan(&db, &id, &settings.current().postprocess_steps).await
+            {
+                warn!("Could not plan post-processing for {id}: {e}");
+            }
+
+            // Remember the PID so subscriptions never fetch it again, even if
+            // this queue item is later removed.
             let _ = sqlx::query(
-                "UPDATE queue_items \
-                 SET status='done', completed_at=?, progress=100, output_path=?, error=NULL \
-                 WHERE id=?",
+                "INSERT OR IGNORE INTO download_history (pid, source) VALUES (?, 'queue')",
             )
-            .bind(&completed_at)
-            .bind(if output_path.is_empty() {
-                None
-            } else {
-                Some(output_path)
-            })
-            .bind(&id)
+            .bind(&item.pid)
             .execute(&db)
             .await;
 
-            let _ = events.send(WsEvent::StatusChange {
-                id,
-                status: DownloadStatus::Done.to_string(),
-            });
+            finish(&worker, &item, output_path, files, &log, &cancel).await;
+        }
+        Err(_) if cancel.is_cancelled() => {
+            // The cancelling request has already set the status; get_iplayer
+            // has been killed and its partial files removed.
+            info!("Download cancelled for {id}");
         }
         Err(e) => {
-            error!("Download failed for {id} after {max_retries} retries: {e:#}");
+            error!(
+                "Download failed for {id} after {} attempt(s) ({:?}): {e:#}",
+                attempt + 1,
+                e.kind
+            );
 
             let _ = sqlx::query(
-                "UPDATE queue_items SET status='failed', completed_at=?, error=? WHERE id=?",
+                "UPDATE queue_items SET status='failed', completed_at=?, error=?, error_kind=? \
+                 WHERE id=?",
             )
             .bind(&completed_at)
             .bind(e.to_string())
+            .bind(e.kind)
             .bind(&id)
             .execute(&db)
             .await;
@@ -380,11 +1014,164 @@ async fn run_download(
             let _ = events.send(WsEvent::Error {
                 id: id.clone(),
                 message: e.to_string(),
+                kind: Some(e.kind),
             });
             let _ = events.send(WsEvent::StatusChange {
-                id,
+                id: id.clone(),
                 status: DownloadStatus::Failed.to_string(),
             });
+            worker
+                .webhooks
+                .notify(WebhookEvent::DownloadFailed, &id)
+                .await;
+            worker.hooks.finished(&id);
+        }
+    }
+}
+
+/// Run `item`'s pending post-processing steps, if there are any, then mark it
+/// done — or failed, if a step fails. Used both when a download finishes and
+/// when a failed step is retried.
+async fn finish(
+    w: &Worker,
+    item: &QueueItem,
+    mut output_path: Option<String>,
+    mut files: Vec<String>,
+    log: &ItemLog,
+    cancel: &CancellationToken,
+) {
+    let id = &item.id;
+    let pending = match postprocess::list(&w.db, id).await {
+        Ok(steps) => steps.iter().any(|s| s.status == StepStatus::Pending),
+        Err(e) => {
+            warn!("Could not read the post-processing steps of {id}: {e}");
+            false
+        }
+    };
+
+    if let Some(media) = output_path.clone().filter(|_| pending) {
+        let _ = sqlx::query(
+            "UPDATE queue_items \
+             SET status='processing', progress=100, output_path=?, error=NULL, error_kind=NULL \
+             WHERE id=?",
+        )
+        .bind(&media)
+        .bind(id)
+        .execute(&w.db)
+        .await;
+        let _ = w.events.send(WsEvent::StatusChange {
+            id: id.clone(),
+            status: DownloadStatus::Processing.to_string(),
+        });
+
+        let settings = w.settings.current();
+        let pipeline = postprocess::Pipeline {
+            db: &w.db,
+            settings: &settings,
+            item,
+            events: &w.events,
+            log,
+            cancel,
+        };
+        match pipeline.run(postprocess::Files { media, files }).await {
+            Ok(processed) => {
+                output_path = Some(processed.media);
+                files = processed.files;
+            }
+            Err(_) if cancel.is_cancelled() => {
+                info!("Post-processing cancelled for {id}");
+                return;
+            }
+            Err(e) => {
+                error!("Post-processing failed for {id}: {e:#}");
+                let kind = DownloadErrorKind::PostProcessing;
+                let _ = sqlx::query(
+                    "UPDATE queue_items SET status='failed', completed_at=?, error=?, error_kind=? \
+                     WHERE id=?",
+                )
+                .bind(chrono::Utc::now().to_rfc3339())
+                .bind(e.to_string())
+                .bind(kind)
+                .bind(id)
+                .execute(&w.db)
+                .await;
+
+                let _ = w.events.send(WsEvent::Error {
+                    id: id.clone(),
+                    message: e.to_string(),
+                    kind: Some(kind),
+                });
+                let _ = w.events.send(WsEvent::StatusChange {
+                    id: id.clone(),
+                    status: DownloadStatus::Failed.to_string(),
+                });
+                w.webhooks.notify(WebhookEvent::DownloadFailed, id).await;
+                w.hooks.finished(id);
+                return;
+            }
         }
     }
+
+    let settings = w.settings.current();
+    if let Some(path) = &output_path {
+        record_media_info(&w.db, &settings, item, path, &files).await;
+    }
+    // The item may have been cancelled while it was being probed.
+    let done = sqlx::query(
+        "UPDATE queue_items \
+         SET status='done', completed_at=?, progress=100, output_path=?, error=NULL, \
+             error_kind=NULL \
+         WHERE id=? AND status != 'cancelled'",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&output_path)
+    .bind(id)
+    .execute(&w.db)
+    .await;
+    match done {
+        Ok(done) if done.rows_affected() == 0 => {
+            info!("{id} was cancelled before it could be marked done");
+            return;
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Could not mark {id} done: {e}"),
+    }
+
+    // Files the move step took out of the output directory leave the library.
+    if let Some(path) = &output_path
+        && std::path::Path::new(path).starts_with(&settings.output_dir)
+        && let Err(e) = library::index_file(&w.db, &settings, std::path::Path::new(path)).await
+    {
+        warn!("Could not add {path} to the library: {e:#}");
+    }
+
+    let _ = w.events.send(WsEvent::StatusChange {
+        id: id.clone(),
+        status: DownloadStatus::Done.to_string(),
+    });
+    w.webhooks.notify(WebhookEvent::DownloadDone, id).await;
+    w.hooks.finished(id);
+}
+
+/// Carry on with a failed item's post-processing from its pending steps.
+async fn resume_processing(w: &Worker, id: &str, cancel: &CancellationToken) {
+    let item: Option<QueueItem> = sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
+        .bind(id)
+        .fetch_optional(&w.db)
+        .await
+        .unwrap_or(None);
+    let Some(item) = item else {
+        warn!("Queue item {id} not found, not resuming post-processing");
+        return;
+    };
+    let files = match artefacts::list(&w.db, id).await {
+        Ok(files) => files.into_iter().map(|f| f.path).collect(),
+        Err(e) => {
+            warn!("Could not read the files of {id}: {e}");
+            Vec::new()
+        }
+    };
+    info!("Resuming post-processing for {id}");
+    let log = w.logs.writer(id);
+    finish(w, &item, item.output_path.clone(), files, &log, cancel).await;
 }
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+
+use crate::{
+    auth::{self, SessionUser},
+    error::{AppError, Result},
+    models::{ApiScope, ApiToken, CreateApiTokenRequest, CreatedApiToken, User},
+    state::AppState,
+};
+
+/// The token `id`, if `user` may see it.
+async fn fetch(state: &AppState, user: &User, id: &str) -> Result<ApiToken> {
+    let token: Option<ApiToken> = sqlx::query_as("SELECT * FROM api_tokens WHERE id=?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    token
+        .filter(|t| user.can_manage(&t.user_id))
+        .ok_or(AppError::NotFound)
+}
+
+/// GET /api/tokens  — own tokens (everyone's for admins); never the secrets
+pub async fn list_api_tokens(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ApiToken>>> {
+    let tokens: Vec<ApiToken> = sqlx::query_as(
+        "SELECT * FROM api_tokens WHERE ?1 IS NULL OR user_id=?1 ORDER BY created_at DESC",
+    )
+    .bind(user.owner_filter())
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(tokens))
+}
+
+/// POST /api/tokens  — the response holds the token; it is not shown again
+pub async fn create_api_token(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateApiTokenRequest>,
+) -> Result<(StatusCode, Json<CreatedApiToken>)> {
+    let mut errors = BTreeMap::new();
+    let name = req.name.trim();
+    if name.is_empty() {
+        errors.insert("name".to_string(), "must not be blank".to_string());
+    } else if name.chars().count() > 100 {
+        errors.insert(
+            "name".to_string(),
+            "must be at most 100 characters".to_string(),
+        );
+    }
+    if req.scopes.contains(&ApiScope::Admin) && !user.is_admin() {
+        errors.insert(
+            "scopes".to_string(),
+            "only admins can grant admin".to_string(),
+        );
+    }
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let mut scopes = req.scopes;
+    scopes.sort_by_key(|s| *s as u8);
+    scopes.dedup();
+
+    let (token, prefix) = auth::generate_api_token();
+    let id = ApiToken::new_id();
+    sqlx::query(
+        "INSERT INTO api_tokens (id, user_id, name, token_hash, prefix, scopes) \
+         VALUES (?,?,?,?,?,?)",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(name)
+    .bind(auth::hash_token(&token, &state.config.secret))
+    .bind(&prefix)
+    .bind(sqlx::types::Json(&scopes))
+    .execute(&state.db)
+    .await?;
+
+    let api_token = fetch(&state, &user, &id).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiToken { api_token, token }),
+    ))
+}
+
+/// DELETE /api/tokens/:id  — revoke; scripts using it get 401 from then on
+pub async fn delete_api_token(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    fetch(&state, &user, &id).await?;
+    sqlx::query("DELETE FROM api_tokens WHERE id=?")
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,50 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+
+use crate::{
+    auth::AdminUser,
+    error::Result,
+    models::{AuthEvent, AuthEventQuery, PaginatedResponse},
+    state::AppState,
+};
+
+/// GET /api/auth/events  (admin) — newest first; `?username=&kind=&page=&per_page=`
+pub async fn list_auth_events(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Query(q): Query<AuthEventQuery>,
+) -> Result<Json<PaginatedResponse<AuthEvent>>> {
+    let page = q.page.unwrap_or(1).max(1);
+    let per_page = q.per_page.unwrap_or(50).clamp(1, 200);
+    let offset = (page - 1) * per_page;
+
+    let events: Vec<AuthEvent> = sqlx::query_as(
+        "SELECT * FROM auth_events \
+         WHERE (?1 IS NULL OR username=?1) AND (?2 IS NULL OR kind=?2) \
+         ORDER BY id DESC LIMIT ?3 OFFSET ?4",
+    )
+    .bind(&q.username)
+    .bind(q.kind)
+    .bind(per_page)
+    .bind(offset)
+    .fetch_all(&state.db)
+    .await?;
+
+    let (total,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM auth_events \
+         WHERE (?1 IS NULL OR username=?1) AND (?2 IS NULL OR kind=?2)",
+    )
+    .bind(&q.username)
+    .bind(q.kind)
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok(Json(PaginatedResponse {
+        data: events,
+        total,
+        page,
+        per_page,
+    }))
+}
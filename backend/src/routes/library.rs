@@ -0,0 +1,196 @@
+use axum::{
+    Json,
+    extract::{Path, Query, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use crate::{
+    artefacts,
+    auth::{self, AdminUser, AuthUser, MediaUser},
+    error::{AppError, Result},
+    library,
+    models::{LibraryFile, LibraryProgramme, LibraryScanSummary, LibrarySeries, MediaLink},
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct LibraryQuery {
+    /// Matched against programme, series and episode.
+    pub q: Option<String>,
+    /// tv | radio
+    pub r#type: Option<String>,
+}
+
+async fn fetch(state: &AppState, id: &str) -> Result<LibraryFile> {
+    let file: Option<LibraryFile> = sqlx::query_as("SELECT * FROM library_files WHERE id=?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    let mut file = file.ok_or(AppError::NotFound)?;
+    artefacts::attach(&state.db, std::slice::from_mut(&mut file)).await?;
+    Ok(file)
+}
+
+/// Escape `LIKE`'s wildcards in `s` (for `ESCAPE '\'`), so a search for
+/// `100%` or `my_show` matches those characters literally.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// GET /api/library?q=&type=tv|radio  — indexed files grouped by programme
+/// and series
+pub async fn list_library(
+    AuthUser(_user): AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<LibraryQuery>,
+) -> Result<Json<Vec<LibraryProgramme>>> {
+    let pattern = params
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(|q| format!("%{}%", escape_like(q)));
+
+    let mut files: Vec<LibraryFile> = sqlx::query_as(
+        "SELECT * FROM library_files \
+         WHERE (?1 IS NULL OR programme LIKE ?1 ESCAPE '\\' OR series LIKE ?1 ESCAPE '\\' \
+                OR episode LIKE ?1 ESCAPE '\\') \
+           AND (?2 IS NULL OR media_type = ?2) \
+         ORDER BY programme COLLATE NOCASE, series IS NOT NULL, series COLLATE NOCASE, \
+                  episode COLLATE NOCASE, path",
+    )
+    .bind(&pattern)
+    .bind(&params.r#type)
+    .fetch_all(&state.db)
+    .await?;
+    artefacts::attach(&state.db, &mut files).await?;
+
+    let mut programmes: Vec<LibraryProgramme> = Vec::new();
+    for file in files {
+        let programme = match programmes.last_mut() {
+            Some(p) if p.programme == file.programme => p,
+            _ => {
+                programmes.push(LibraryProgramme {
+                    programme: file.programme.clone(),
+                    file_count: 0,
+                    total_size: 0,
+                    series: Vec::new(),
+                });
+                programmes.last_mut().expect("just pushed")
+            }
+        };
+        programme.file_count += 1;
+        // Subtitles, artwork and the like count towards the total too.
+        programme.total_size += file.size
+            + file
+                .artefacts
+                .iter()
+                .filter(|a| a.path != file.path)
+                .filter_map(|a| a.size)
+                .sum::<i64>();
+        match programme.series.last_mut() {
+            Some(s) if s.series == file.series => s.files.push(file),
+            _ => programme.series.push(LibrarySeries {
+                series: file.series.clone(),
+                files: vec![file],
+            }),
+        }
+    }
+
+    Ok(Json(programmes))
+}
+
+/// GET /api/library/files/:id
+pub async fn get_library_file(
+    AuthUser(_user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<LibraryFile>> {
+    fetch(&state, &id).await.map(Json)
+}
+
+/// DELETE /api/library/files/:id  (admin) — deletes the recording, its subtitle
+/// sidecars and everything else its download produced from disk
+pub async fn delete_library_file(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    let file = fetch(&state, &id).await?;
+    library::delete_file(&state.db, &file).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    /// Send `Content-Disposition: attachment` so browsers save the file.
+    #[serde(default)]
+    pub download: bool,
+}
+
+/// POST /api/library/files/:id/link  — a stream URL carrying a media token,
+/// usable as a `<video>`/`<audio>` source or link for a few hours
+pub async fn link_library_file(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<MediaLink>> {
+    let file = fetch(&state, &id).await?;
+    let path = format!("/api/library/files/{}/stream", file.id);
+    let (token, expires_at) = auth::media_token(&state, &user.id, &path);
+    Ok(Json(MediaLink {
+        url: format!("{path}?token={token}"),
+        expires_at,
+    }))
+}
+
+/// GET /api/library/files/:id/stream?download=&token=  — serve the file with
+/// HTTP range support. Also accepts a media token from
+/// `POST /api/library/files/:id/link` as a query parameter, so it can be used
+/// directly as a `<video>`/`<audio>` source or link.
+pub async fn stream_library_file(
+    MediaUser(_user): MediaUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<StreamQuery>,
+    request: Request,
+) -> Result<Response> {
+    let file = fetch(&state, &id).await?;
+    if !tokio::fs::try_exists(&file.path).await.unwrap_or(false) {
+        return Err(AppError::NotFound);
+    }
+
+    let mut response = match ServeFile::new(&file.path).oneshot(request).await {
+        Ok(res) => res.into_response(),
+        Err(never) => match never {},
+    };
+
+    if params.download {
+        let name = std::path::Path::new(&file.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().replace('"', ""))
+            .unwrap_or_default();
+        let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{name}\""))
+            .unwrap_or(HeaderValue::from_static("attachment"));
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(response)
+}
+
+/// POST /api/library/scan  (admin) — re-index everything under the output directory
+pub async fn scan_library(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<LibraryScanSummary>> {
+    let settings = state.settings.current();
+    let summary = library::scan(&state.db, &settings).await?;
+    Ok(Json(summary))
+}
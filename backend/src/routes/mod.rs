@@ -1,7 +1,14 @@
+pub mod api_tokens;
+pub mod audit;
+pub mod library;
 pub mod queue;
 pub mod search;
 pub mod settings;
+pub mod subscriptions;
+pub mod two_factor;
 pub mod users;
+pub mod watch_rules;
+pub mod webhooks;
 pub mod ws;
 
 use axum::{
@@ -15,12 +22,37 @@ use tower_http::{
     trace::TraceLayer,
 };
 
-use crate::{auth::login_handler, state::AppState};
+use crate::{
+    auth::{
+        login_handler, login_two_factor_handler, logout_all_handler, logout_handler,
+        refresh_handler,
+    },
+    state::AppState,
+};
 
 pub fn build_router(state: AppState, static_dir: &str) -> Router {
     let api = Router::new()
         // Auth
         .route("/auth/login", post(login_handler))
+        .route("/auth/login/2fa", post(login_two_factor_handler))
+        .route("/auth/logout", post(logout_handler))
+        .route("/auth/logout-all", post(logout_all_handler))
+        .route("/auth/refresh", post(refresh_handler))
+        .route("/auth/events", get(audit::list_auth_events))
+        .route("/auth/2fa", get(two_factor::get_two_factor))
+        .route("/auth/2fa/setup", post(two_factor::setup_two_factor))
+        .route("/auth/2fa/enable", post(two_factor::enable_two_factor))
+        .route("/auth/2fa/disable", post(two_factor::disable_two_factor))
+        .route(
+            "/auth/2fa/recovery-codes",
+            post(two_factor::regenerate_recovery_codes),
+        )
+        // API tokens
+        .route(
+            "/tokens",
+            get(api_tokens::list_api_tokens).post(api_tokens::create_api_token),
+        )
+        .route("/tokens/{id}", delete(api_tokens::delete_api_token))
         // Queue
         .route("/queue", get(queue::list_queue).post(queue::add_to_queue))
         .route(
@@ -28,6 +60,11 @@ pub fn build_router(state: AppState, static_dir: &str) -> Router {
             get(queue::get_queue_item).delete(queue::remove_from_queue),
         )
         .route("/queue/{id}/retry", post(queue::retry_queue_item))
+        .route("/queue/{id}/attempts", get(queue::list_attempts))
+        .route("/queue/{id}/files", get(queue::list_files))
+        .route("/queue/{id}/steps", get(queue::list_steps))
+        .route("/queue/{id}/steps/{step_id}/retry", post(queue::retry_step))
+        .route("/queue/{id}/log", get(queue::get_log))
         .route("/queue/reorder", post(queue::reorder_queue))
         // Search
         .route("/search", get(search::search))
@@ -38,15 +75,77 @@ pub fn build_router(state: AppState, static_dir: &str) -> Router {
             "/settings",
             get(settings::list_settings).patch(settings::bulk_update_settings),
         )
+        .route("/settings/schema", get(settings::settings_schema))
         .route(
             "/settings/{key}",
             get(settings::get_setting).put(settings::set_setting),
         )
+        // Library
+        .route("/library", get(library::list_library))
+        .route("/library/scan", post(library::scan_library))
+        .route(
+            "/library/files/{id}",
+            get(library::get_library_file).delete(library::delete_library_file),
+        )
+        .route("/library/files/{id}/link", post(library::link_library_file))
+        .route(
+            "/library/files/{id}/stream",
+            get(library::stream_library_file),
+        )
+        // Subscriptions
+        .route(
+            "/subscriptions",
+            get(subscriptions::list_subscriptions).post(subscriptions::create_subscription),
+        )
+        .route(
+            "/subscriptions/{id}",
+            get(subscriptions::get_subscription)
+                .patch(subscriptions::update_subscription)
+                .delete(subscriptions::delete_subscription),
+        )
+        .route(
+            "/subscriptions/{id}/check",
+            post(subscriptions::check_subscription),
+        )
+        // Watch rules
+        .route(
+            "/watch-rules",
+            get(watch_rules::list_watch_rules).post(watch_rules::create_watch_rule),
+        )
+        .route(
+            "/watch-rules/preview",
+            post(watch_rules::preview_watch_rule),
+        )
+        .route(
+            "/watch-rules/{id}",
+            get(watch_rules::get_watch_rule)
+                .patch(watch_rules::update_watch_rule)
+                .delete(watch_rules::delete_watch_rule),
+        )
+        // Webhooks
+        .route(
+            "/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route(
+            "/webhooks/{id}",
+            get(webhooks::get_webhook)
+                .patch(webhooks::update_webhook)
+                .delete(webhooks::delete_webhook),
+        )
+        .route("/webhooks/{id}/deliveries", get(webhooks::list_deliveries))
+        .route("/webhooks/{id}/test", post(webhooks::test_webhook))
         // Users
         .route("/users", get(users::list_users).post(users::create_user))
         .route("/users/me", get(users::get_me))
+        .route(
+            "/users/me/preferences",
+            get(users::get_preferences).put(users::update_preferences),
+        )
         .route("/users/{id}", delete(users::delete_user))
-        .route("/users/{id}/password", put(users::change_password));
+        .route("/users/{id}/password", put(users::change_password))
+        .route("/users/{id}/role", put(users::set_role))
+        .route("/users/{id}/2fa", delete(two_factor::reset_two_factor));
 
     // CORS — in production, restrict `allow_origin` to your domain
     let cors = CorsLayer::new()
@@ -1,59 +1,65 @@
 use axum::{
     Json,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{StatusCode, header},
+    response::IntoResponse,
 };
 
 use crate::{
+    artefacts,
     auth::AuthUser,
     error::{AppError, Result},
     models::{
-        AddQueueItemRequest, DownloadStatus, PaginatedResponse, QueueItem, QueueQuery, WsEvent,
+        AddQueueItemRequest, DownloadAttempt, DownloadStatus, LogQuery, PaginatedResponse,
+        PostprocessStep, QueueItem, QueueItemFileCheck, QueueQuery, StepStatus, User, WebhookEvent,
+        WsEvent,
     },
+    naming, postprocess, queue,
     state::AppState,
 };
 
-/// GET /api/queue
+/// The item `id`, if `user` may see it. Other users' items are reported as
+/// not found rather than forbidden so their ids don't leak.
+async fn fetch_owned(state: &AppState, user: &User, id: &str) -> Result<QueueItem> {
+    let item: Option<QueueItem> = sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    item.filter(|i| user.can_manage(&i.user_id))
+        .ok_or(AppError::NotFound)
+}
+
+/// GET /api/queue  — admins see every user's items, others only their own
 pub async fn list_queue(
-    AuthUser(_user): AuthUser,
+    AuthUser(user): AuthUser,
     State(state): State<AppState>,
     Query(q): Query<QueueQuery>,
 ) -> Result<Json<PaginatedResponse<QueueItem>>> {
     let page = q.page.unwrap_or(1).max(1);
     let per_page = q.per_page.unwrap_or(25).clamp(1, 100);
     let offset = (page - 1) * per_page;
+    let owner = user.owner_filter();
 
-    let (items, total) = if let Some(status) = &q.status {
-        let items: Vec<QueueItem> = sqlx::query_as(
-            "SELECT * FROM queue_items WHERE status=? ORDER BY priority ASC, added_at ASC LIMIT ? OFFSET ?",
-        )
-        .bind(status)
-        .bind(per_page)
-        .bind(offset)
-        .fetch_all(&state.db)
-        .await?;
-
-        let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM queue_items WHERE status=?")
-            .bind(status)
-            .fetch_one(&state.db)
-            .await?;
-
-        (items, total)
-    } else {
-        let items: Vec<QueueItem> = sqlx::query_as(
-            "SELECT * FROM queue_items ORDER BY priority ASC, added_at ASC LIMIT ? OFFSET ?",
-        )
-        .bind(per_page)
-        .bind(offset)
-        .fetch_all(&state.db)
-        .await?;
-
-        let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM queue_items")
-            .fetch_one(&state.db)
-            .await?;
+    let items: Vec<QueueItem> = sqlx::query_as(
+        "SELECT * FROM queue_items \
+         WHERE (?1 IS NULL OR status=?1) AND (?2 IS NULL OR user_id=?2) \
+         ORDER BY priority ASC, added_at ASC LIMIT ?3 OFFSET ?4",
+    )
+    .bind(&q.status)
+    .bind(owner)
+    .bind(per_page)
+    .bind(offset)
+    .fetch_all(&state.db)
+    .await?;
 
-        (items, total)
-    };
+    let (total,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM queue_items \
+         WHERE (?1 IS NULL OR status=?1) AND (?2 IS NULL OR user_id=?2)",
+    )
+    .bind(&q.status)
+    .bind(owner)
+    .fetch_one(&state.db)
+    .await?;
 
     Ok(Json(PaginatedResponse {
         data: items,
@@ -65,27 +71,119 @@ pub async fn list_queue(
 
 /// GET /api/queue/:id
 pub async fn get_queue_item(
-    AuthUser(_user): AuthUser,
+    AuthUser(user): AuthUser,
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<QueueItem>> {
-    let item: Option<QueueItem> = sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
-        .bind(&id)
-        .fetch_optional(&state.db)
-        .await?;
+    fetch_owned(&state, &user, &id).await.map(Json)
+}
 
-    item.map(Json).ok_or(AppError::NotFound)
+/// GET /api/queue/:id/attempts  — every get_iplayer run of the item, oldest first
+pub async fn list_attempts(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<DownloadAttempt>>> {
+    let item = fetch_owned(&state, &user, &id).await?;
+    let attempts = sqlx::query_as(
+        "SELECT * FROM download_attempts WHERE queue_item_id=? ORDER BY attempt ASC, id ASC",
+    )
+    .bind(&item.id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(attempts))
+}
+
+/// GET /api/queue/:id/files  — every file the download produced, each checked
+/// against the disk
+pub async fn list_files(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<QueueItemFileCheck>>> {
+    let item = fetch_owned(&state, &user, &id).await?;
+    let files = artefacts::list(&state.db, &item.id).await?;
+    Ok(Json(artefacts::check(files).await))
+}
+
+/// GET /api/queue/:id/steps  — the item's post-processing steps, in order
+pub async fn list_steps(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<PostprocessStep>>> {
+    let item = fetch_owned(&state, &user, &id).await?;
+    Ok(Json(postprocess::list(&state.db, &item.id).await?))
+}
+
+/// POST /api/queue/:id/steps/:step_id/retry  — run a failed post-processing
+/// step again, then the rest of the chain
+pub async fn retry_step(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path((id, step_id)): Path<(String, i64)>,
+) -> Result<Json<QueueItem>> {
+    let item = fetch_owned(&state, &user, &id).await?;
+    let step = postprocess::list(&state.db, &item.id)
+        .await?
+        .into_iter()
+        .find(|s| s.id == step_id)
+        .ok_or(AppError::NotFound)?;
+    if step.status != StepStatus::Failed || item.status != DownloadStatus::Failed.to_string() {
+        return Err(AppError::Conflict(format!(
+            "step {} of item {id} has not failed",
+            step.step
+        )));
+    }
+    if state.queue.is_running(&id) {
+        return Err(AppError::Conflict(format!(
+            "item {id} is still being processed; try again shortly"
+        )));
+    }
+
+    postprocess::reset_from(&state.db, &step).await?;
+    if !state.queue.resume_processing(&id) {
+        return Err(AppError::Conflict(format!(
+            "item {id} is still being processed; try again shortly"
+        )));
+    }
+
+    let item = fetch_owned(&state, &user, &id).await?;
+    Ok(Json(item))
+}
+
+/// GET /api/queue/:id/log  — the item's full get_iplayer output as plain text
+pub async fn get_log(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(q): Query<LogQuery>,
+) -> Result<impl IntoResponse> {
+    let item = fetch_owned(&state, &user, &id).await?;
+    let text = state
+        .download_logs
+        .read(&item.id, q.tail)
+        .await
+        .map_err(|e| AppError::Internal(format!("read log: {e}")))?;
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text))
 }
 
 /// POST /api/queue
 pub async fn add_to_queue(
     AuthUser(user): AuthUser,
     State(state): State<AppState>,
-    Json(req): Json<AddQueueItemRequest>,
+    Json(mut req): Json<AddQueueItemRequest>,
 ) -> Result<(StatusCode, Json<QueueItem>)> {
+    if let Some(template) = &req.filename_template {
+        let template = naming::validate(template)
+            .map_err(|e| AppError::BadRequest(format!("filename_template {e}")))?;
+        // Blank means "use the setting", same as leaving it out.
+        req.filename_template = Some(template).filter(|t| !t.is_empty());
+    }
+
     // Reject duplicate PIDs that are already queued or downloading
     let existing: Option<(String,)> = sqlx::query_as(
-        "SELECT id FROM queue_items WHERE pid=? AND status IN ('queued','downloading')",
+        "SELECT id FROM queue_items WHERE pid=? AND status IN ('queued','downloading','processing')",
     )
     .bind(&req.pid)
     .fetch_optional(&state.db)
@@ -98,116 +196,101 @@ pub async fn add_to_queue(
         )));
     }
 
-    let id = QueueItem::new_id();
-    let now = chrono::Utc::now().to_rfc3339();
-    let scheduled = req.scheduled_at.map(|t| t.to_rfc3339());
-
-    sqlx::query(
-        "INSERT INTO queue_items \
-         (id, pid, title, series, episode, channel, media_type, thumbnail_url, \
-          added_at, scheduled_at, priority, status, quality, subtitles, metadata, user_id) \
-         VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)",
-    )
-    .bind(&id)
-    .bind(&req.pid)
-    .bind(&req.title)
-    .bind(&req.series)
-    .bind(&req.episode)
-    .bind(&req.channel)
-    .bind(&req.media_type)
-    .bind(&req.thumbnail_url)
-    .bind(&now)
-    .bind(&scheduled)
-    .bind(req.priority)
-    .bind(DownloadStatus::Queued.to_string())
-    .bind(&req.quality)
-    .bind(req.subtitles)
-    .bind("{}")
-    .bind(&user.id)
-    .execute(&state.db)
-    .await?;
-
-    let item: QueueItem = sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
-        .bind(&id)
-        .fetch_one(&state.db)
-        .await?;
+    let settings = state.settings.current();
+    let mut conn = state.db.acquire().await?;
+    let item = queue::insert_item(&mut conn, &settings, &req, &user.id).await?;
 
-    // Notify the worker (only enqueue immediately if no scheduled time)
+    // Wake the scheduler (scheduled items are woken by the minute watcher)
     if req.scheduled_at.is_none() {
-        state.queue.enqueue(id.clone());
+        state.queue.wake();
     }
 
-    let _ = state.events.send(WsEvent::ItemAdded { item: item.clone() });
+    state
+        .webhooks
+        .notify(WebhookEvent::ItemAdded, &item.id)
+        .await;
+    let _ = state.events.send(WsEvent::ItemAdded {
+        item: Box::new(item.clone()),
+    });
 
     Ok((StatusCode::CREATED, Json(item)))
 }
 
-/// DELETE /api/queue/:id   — cancel and remove
+/// DELETE /api/queue/:id   — cancel and remove, deleting the downloaded files
 pub async fn remove_from_queue(
-    AuthUser(_user): AuthUser,
+    AuthUser(user): AuthUser,
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<StatusCode> {
-    let item: Option<QueueItem> = sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
-        .bind(&id)
-        .fetch_optional(&state.db)
-        .await?;
-
-    let item = item.ok_or(AppError::NotFound)?;
+    let item = fetch_owned(&state, &user, &id).await?;
 
-    // If actively downloading, mark cancelled so the worker task notices;
-    // the worker is responsible for cleaning up the partial file.
-    if item.status == DownloadStatus::Downloading.to_string() {
+    // If actively downloading or post-processing, mark cancelled and signal
+    // the worker, which kills get_iplayer or ffmpeg and cleans up the partial
+    // files.
+    if item.status == DownloadStatus::Downloading.to_string()
+        || item.status == DownloadStatus::Processing.to_string()
+    {
         sqlx::query("UPDATE queue_items SET status='cancelled' WHERE id=?")
             .bind(&id)
             .execute(&state.db)
             .await?;
-    } else {
-        // Delete the output file from disk if present
-        if let Some(ref path) = item.output_path {
-            if !path.is_empty() {
-                if let Err(e) = tokio::fs::remove_file(path).await {
-                    // Not fatal — file may have already been moved or deleted
-                    tracing::warn!("Could not delete output file {path}: {e}");
-                }
-            }
+        if !state.queue.cancel(&id) {
+            tracing::warn!("Item {id} is marked downloading but no worker is running it");
         }
+    } else {
+        // Delete the recording and everything downloaded with it
+        artefacts::delete(&state.db, &item).await?;
         sqlx::query("DELETE FROM queue_items WHERE id=?")
             .bind(&id)
             .execute(&state.db)
             .await?;
+        state.download_logs.remove(&id).await;
+        // The queue may now be idle, with a hook batch waiting for that.
+        state.queue.wake();
     }
 
-    let _ = state.events.send(WsEvent::ItemRemoved { id });
+    let _ = state.events.send(WsEvent::ItemRemoved {
+        id,
+        user_id: item.user_id,
+    });
     Ok(StatusCode::NO_CONTENT)
 }
 
 /// POST /api/queue/:id/retry
 pub async fn retry_queue_item(
-    AuthUser(_user): AuthUser,
+    AuthUser(user): AuthUser,
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<QueueItem>> {
+    fetch_owned(&state, &user, &id).await?;
+
+    // A cancelled download may still be tearing down get_iplayer; queueing it
+    // again now would let a second worker start on the same item.
+    if state.queue.is_running(&id) {
+        return Err(AppError::Conflict(format!(
+            "item {id} is still being processed; try again shortly"
+        )));
+    }
+
     sqlx::query(
         "UPDATE queue_items \
-         SET status='queued', error=NULL, progress=0, started_at=NULL, completed_at=NULL \
+         SET status='queued', error=NULL, error_kind=NULL, progress=0, started_at=NULL, completed_at=NULL \
          WHERE id=? AND status IN ('failed','cancelled')",
     )
     .bind(&id)
     .execute(&state.db)
     .await?;
 
-    let item: Option<QueueItem> = sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
-        .bind(&id)
-        .fetch_optional(&state.db)
-        .await?;
-
-    let item = item.ok_or(AppError::NotFound)?;
-    state.queue.enqueue(id);
+    let item = fetch_owned(&state, &user, &id).await?;
+    state.queue.wake();
     Ok(Json(item))
 }
 
 /// POST /api/queue/reorder  — body: [{ id, priority }]
+///
+/// The scheduler reads priorities from the database each time a worker slot
+/// frees up, so this applies to every item that has not started yet.
+/// Entries for other users' items are ignored unless the caller is an admin.
 #[derive(serde::Deserialize)]
 pub struct ReorderEntry {
     pub id: String,
@@ -215,14 +298,16 @@ pub struct ReorderEntry {
 }
 
 pub async fn reorder_queue(
-    AuthUser(_user): AuthUser,
+    AuthUser(user): AuthUser,
     State(state): State<AppState>,
     Json(entries): Json<Vec<ReorderEntry>>,
 ) -> Result<StatusCode> {
     for entry in entries {
-        sqlx::query("UPDATE queue_items SET priority=? WHERE id=?")
+        sqlx::query("UPDATE queue_items SET priority=? WHERE id=? AND (? IS NULL OR user_id=?)")
             .bind(entry.priority)
             .bind(&entry.id)
+            .bind(user.owner_filter())
+            .bind(user.owner_filter())
             .execute(&state.db)
             .await?;
     }
@@ -5,7 +5,7 @@ use axum::{
 use serde::Deserialize;
 
 use crate::{
-    auth::AuthUser,
+    auth::{AdminUser, AuthUser},
     error::Result,
     iplayer::{self, EpisodesOptions, SearchOptions},
     models::SearchResult,
@@ -29,12 +29,13 @@ pub async fn search(
     State(state): State<AppState>,
     Query(params): Query<SearchQuery>,
 ) -> Result<Json<Vec<SearchResult>>> {
+    let settings = state.settings.current();
     let opts = SearchOptions {
         query: &params.q,
         media_type: &params.r#type,
-        get_iplayer_path: &state.config.get_iplayer_path,
+        get_iplayer_path: &settings.get_iplayer_path,
         cache_dir: &state.config.iplayer_cache_dir,
-        proxy: state.config.proxy.as_deref(),
+        proxy: settings.proxy.as_deref(),
     };
 
     let results = iplayer::search(opts)
@@ -58,12 +59,13 @@ pub async fn list_episodes(
     State(state): State<AppState>,
     Query(params): Query<EpisodesQuery>,
 ) -> Result<Json<Vec<SearchResult>>> {
+    let settings = state.settings.current();
     let opts = EpisodesOptions {
         pid: &params.pid,
         media_type: &params.r#type,
-        get_iplayer_path: &state.config.get_iplayer_path,
+        get_iplayer_path: &settings.get_iplayer_path,
         cache_dir: &state.config.iplayer_cache_dir,
-        proxy: state.config.proxy.as_deref(),
+        proxy: settings.proxy.as_deref(),
     };
 
     let results = iplayer::list_episodes(opts)
@@ -73,7 +75,7 @@ pub async fn list_episodes(
     Ok(Json(results))
 }
 
-/// POST /api/search/refresh  — refresh the get_iplayer programme cache
+/// POST /api/search/refresh  (admin) — refresh the get_iplayer programme cache
 #[derive(Deserialize)]
 pub struct RefreshBody {
     #[serde(default = "default_type")]
@@ -81,17 +83,24 @@ pub struct RefreshBody {
 }
 
 pub async fn refresh_cache(
-    AuthUser(_user): AuthUser,
+    AdminUser(_admin): AdminUser,
     State(state): State<AppState>,
     Json(body): Json<RefreshBody>,
 ) -> Result<axum::http::StatusCode> {
-    let path = state.config.get_iplayer_path.clone();
+    let settings = state.settings.current();
     let media_type = body.r#type.clone();
     let cache_dir = state.config.iplayer_cache_dir.clone();
 
     // Run in background — returns 202 Accepted immediately
     tokio::spawn(async move {
-        if let Err(e) = iplayer::refresh_cache(&path, &media_type, &cache_dir).await {
+        if let Err(e) = iplayer::refresh_cache(
+            &settings.get_iplayer_path,
+            &media_type,
+            &cache_dir,
+            settings.proxy.as_deref(),
+        )
+        .await
+        {
             tracing::warn!("Cache refresh failed: {e:#}");
         }
     });
@@ -4,17 +4,51 @@ use axum::{
     http::StatusCode,
 };
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::{
-    auth::AuthUser,
+    auth::{AdminUser, AuthUser},
     error::{AppError, Result},
     models::Setting,
+    settings::{self, SCHEMA, SettingSpec},
     state::AppState,
 };
 
-/// GET /api/settings
+/// Validate a batch of writes against the settings schema. Returns the
+/// normalised values, or every problem found keyed by setting name.
+fn validate_updates(updates: HashMap<String, String>) -> Result<Vec<(String, String)>> {
+    let mut valid = Vec::with_capacity(updates.len());
+    let mut errors = BTreeMap::new();
+
+    for (key, value) in updates {
+        match settings::spec(&key) {
+            None => {
+                errors.insert(key, "unknown setting".to_string());
+            }
+            Some(spec) => match spec.validate(&value) {
+                Ok(v) => valid.push((key, v)),
+                Err(e) => {
+                    errors.insert(key, e);
+                }
+            },
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(valid)
+    } else {
+        Err(AppError::Validation(errors))
+    }
+}
+
+/// GET /api/settings/schema
+pub async fn settings_schema(AuthUser(_user): AuthUser) -> Json<&'static [SettingSpec]> {
+    Json(SCHEMA)
+}
+
+/// GET /api/settings  (admin) — values include the hook command and proxy
 pub async fn list_settings(
-    AuthUser(_user): AuthUser,
+    AdminUser(_admin): AdminUser,
     State(state): State<AppState>,
 ) -> Result<Json<Vec<Setting>>> {
     let settings: Vec<Setting> = sqlx::query_as("SELECT * FROM settings ORDER BY key")
@@ -23,9 +57,9 @@ pub async fn list_settings(
     Ok(Json(settings))
 }
 
-/// GET /api/settings/:key
+/// GET /api/settings/:key  (admin)
 pub async fn get_setting(
-    AuthUser(_user): AuthUser,
+    AdminUser(_admin): AdminUser,
     State(state): State<AppState>,
     Path(key): Path<String>,
 ) -> Result<Json<Setting>> {
@@ -42,13 +76,16 @@ pub struct SetSettingRequest {
     pub value: String,
 }
 
-/// PUT /api/settings/:key
+/// PUT /api/settings/:key  (admin)
 pub async fn set_setting(
-    AuthUser(_user): AuthUser,
+    AdminUser(_admin): AdminUser,
     State(state): State<AppState>,
     Path(key): Path<String>,
     Json(req): Json<SetSettingRequest>,
 ) -> Result<Json<Setting>> {
+    let (key, value) = validate_updates(HashMap::from([(key, req.value)]))?
+        .pop()
+        .expect("one validated setting");
     let now = chrono::Utc::now().to_rfc3339();
 
     sqlx::query(
@@ -56,10 +93,11 @@ pub async fn set_setting(
          ON CONFLICT(key) DO UPDATE SET value=excluded.value, updated_at=excluded.updated_at",
     )
     .bind(&key)
-    .bind(&req.value)
+    .bind(&value)
     .bind(&now)
     .execute(&state.db)
     .await?;
+    state.settings.reload().await?;
 
     let setting: Setting = sqlx::query_as("SELECT * FROM settings WHERE key=?")
         .bind(&key)
@@ -69,13 +107,19 @@ pub async fn set_setting(
     Ok(Json(setting))
 }
 
-/// PATCH /api/settings  — bulk update
+/// PATCH /api/settings  — bulk update (admin)
+///
+/// All-or-nothing: if any value fails validation nothing is written. Changes
+/// apply immediately: the cached settings are reloaded and the worker pool
+/// resizes itself if `max_concurrent` changed.
 pub async fn bulk_update_settings(
-    AuthUser(_user): AuthUser,
+    AdminUser(_admin): AdminUser,
     State(state): State<AppState>,
-    Json(updates): Json<std::collections::HashMap<String, String>>,
+    Json(updates): Json<HashMap<String, String>>,
 ) -> Result<StatusCode> {
+    let updates = validate_updates(updates)?;
     let now = chrono::Utc::now().to_rfc3339();
+    let mut tx = state.db.begin().await?;
     for (key, value) in updates {
         sqlx::query(
             "INSERT INTO settings (key, value, updated_at) VALUES (?, ?, ?) \
@@ -84,8 +128,10 @@ pub async fn bulk_update_settings(
         .bind(&key)
         .bind(&value)
         .bind(&now)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await?;
     }
+    tx.commit().await?;
+    state.settings.reload().await?;
     Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,180 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+
+use crate::{
+    auth::AuthUser,
+    error::{AppError, Result},
+    iplayer,
+    models::{CreateSubscriptionRequest, QueueItem, Subscription, UpdateSubscriptionRequest, User},
+    settings,
+    state::AppState,
+    subscriptions,
+};
+
+/// Reject quality strings the download settings would not accept either.
+fn validate_quality(quality: Option<&str>) -> Result<Option<String>> {
+    let Some(q) = quality else {
+        return Ok(None);
+    };
+    settings::spec("default_quality")
+        .expect("default_quality is in the schema")
+        .validate(q)
+        .map(Some)
+        .map_err(|e| AppError::BadRequest(format!("quality {e}")))
+}
+
+/// The entry `id`, if `user` may see it.
+async fn fetch(state: &AppState, user: &User, id: &str) -> Result<Subscription> {
+    let sub: Option<Subscription> = sqlx::query_as("SELECT * FROM subscriptions WHERE id=?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    sub.filter(|s| user.can_manage(&s.user_id))
+        .ok_or(AppError::NotFound)
+}
+
+/// GET /api/subscriptions
+pub async fn list_subscriptions(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Subscription>>> {
+    let subs: Vec<Subscription> =
+        sqlx::query_as("SELECT * FROM subscriptions WHERE ?1 IS NULL OR user_id=?1 ORDER BY title")
+            .bind(user.owner_filter())
+            .fetch_all(&state.db)
+            .await?;
+    Ok(Json(subs))
+}
+
+/// GET /api/subscriptions/:id
+pub async fn get_subscription(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Subscription>> {
+    fetch(&state, &user, &id).await.map(Json)
+}
+
+/// POST /api/subscriptions
+///
+/// The first check runs in the background straight away; with `backfill`
+/// false it only records the current episodes as seen.
+pub async fn create_subscription(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateSubscriptionRequest>,
+) -> Result<(StatusCode, Json<Subscription>)> {
+    let pid = iplayer::extract_pid(req.pid.trim())
+        .ok_or_else(|| AppError::BadRequest(format!("'{}' is not a BBC PID", req.pid)))?;
+    if req.media_type != "tv" && req.media_type != "radio" {
+        return Err(AppError::BadRequest(
+            "media_type must be tv or radio".into(),
+        ));
+    }
+    if req.title.trim().is_empty() {
+        return Err(AppError::BadRequest("title cannot be empty".into()));
+    }
+    let quality = validate_quality(req.quality.as_deref())?;
+
+    let existing: Option<(String,)> =
+        sqlx::query_as("SELECT id FROM subscriptions WHERE pid=? AND user_id=?")
+            .bind(&pid)
+            .bind(&user.id)
+            .fetch_optional(&state.db)
+            .await?;
+    if existing.is_some() {
+        return Err(AppError::Conflict(format!("already subscribed to {pid}")));
+    }
+
+    let id = Subscription::new_id();
+    sqlx::query(
+        "INSERT INTO subscriptions (id, pid, title, media_type, quality, subtitles, user_id) \
+         VALUES (?,?,?,?,?,?,?)",
+    )
+    .bind(&id)
+    .bind(&pid)
+    .bind(req.title.trim())
+    .bind(&req.media_type)
+    .bind(&quality)
+    .bind(req.subtitles)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await?;
+
+    let sub = fetch(&state, &user, &id).await?;
+
+    {
+        let state = state.clone();
+        let sub = sub.clone();
+        let backfill = req.backfill;
+        tokio::spawn(async move {
+            if let Err(e) = subscriptions::check(&state, &sub, backfill).await {
+                tracing::warn!("Initial check of subscription {} failed: {e:#}", sub.id);
+            }
+        });
+    }
+
+    Ok((StatusCode::CREATED, Json(sub)))
+}
+
+/// PATCH /api/subscriptions/:id
+pub async fn update_subscription(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateSubscriptionRequest>,
+) -> Result<Json<Subscription>> {
+    let sub = fetch(&state, &user, &id).await?;
+
+    let title = match req.title {
+        Some(t) if t.trim().is_empty() => {
+            return Err(AppError::BadRequest("title cannot be empty".into()));
+        }
+        Some(t) => t.trim().to_string(),
+        None => sub.title,
+    };
+    let quality = match req.quality {
+        Some(q) if q.is_empty() => None, // back to the default_quality setting
+        Some(q) => validate_quality(Some(&q))?,
+        None => sub.quality,
+    };
+
+    sqlx::query("UPDATE subscriptions SET title=?, quality=?, subtitles=?, enabled=? WHERE id=?")
+        .bind(&title)
+        .bind(&quality)
+        .bind(req.subtitles.or(sub.subtitles))
+        .bind(req.enabled.unwrap_or(sub.enabled))
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+
+    fetch(&state, &user, &id).await.map(Json)
+}
+
+/// DELETE /api/subscriptions/:id  — episodes already queued are kept
+pub async fn delete_subscription(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    fetch(&state, &user, &id).await?;
+    sqlx::query("DELETE FROM subscriptions WHERE id=?")
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/subscriptions/:id/check  — check now; returns the items queued
+pub async fn check_subscription(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<QueueItem>>> {
+    let sub = fetch(&state, &user, &id).await?;
+    let queued = subscriptions::check(&state, &sub, true).await?;
+    Ok(Json(queued))
+}
@@ -0,0 +1,191 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+};
+
+use crate::{
+    audit,
+    auth::{self, AdminUser, AuthUser, SessionUser, verify_password},
+    error::{AppError, Result},
+    models::{
+        AuthEventKind, PasswordConfirmation, RecoveryCodes, TotpCodeRequest, TwoFactorSetup,
+        TwoFactorStatus, User,
+    },
+    state::AppState,
+    two_factor,
+};
+
+/// 2FA changes need the password again, so a stolen session alone can't
+/// lock the owner out or strip their second factor.
+fn confirm_password(user: &User, password: &str) -> Result<()> {
+    let valid =
+        verify_password(password, &user.password).map_err(|e| AppError::Internal(e.to_string()))?;
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::Validation(
+            [("password".to_string(), "is incorrect".to_string())].into(),
+        ))
+    }
+}
+
+/// GET /api/auth/2fa
+pub async fn get_two_factor(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<TwoFactorStatus>> {
+    let remaining: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM recovery_codes WHERE user_id=? AND used_at IS NULL",
+    )
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await?;
+    Ok(Json(TwoFactorStatus {
+        enabled: user.totp_enabled,
+        recovery_codes_remaining: remaining,
+    }))
+}
+
+/// POST /api/auth/2fa/setup  — `{ password }`; starts enrolment with a new
+/// secret. 2FA stays off until `/auth/2fa/enable` accepts a code from it.
+pub async fn setup_two_factor(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+    Json(req): Json<PasswordConfirmation>,
+) -> Result<Json<TwoFactorSetup>> {
+    confirm_password(&user, &req.password)?;
+    if user.totp_enabled {
+        return Err(AppError::Conflict(
+            "two-factor authentication is already enabled".into(),
+        ));
+    }
+
+    let secret = two_factor::new_secret();
+    let otpauth_uri = two_factor::provisioning_uri(&secret, &user.username)?;
+    sqlx::query("UPDATE users SET totp_secret=?, updated_at=datetime('now') WHERE id=?")
+        .bind(&secret)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await?;
+    Ok(Json(TwoFactorSetup {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+/// POST /api/auth/2fa/enable  — `{ code }` from the authenticator app;
+/// returns the recovery codes, which are not shown again
+pub async fn enable_two_factor(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<Json<RecoveryCodes>> {
+    if user.totp_enabled {
+        return Err(AppError::Conflict(
+            "two-factor authentication is already enabled".into(),
+        ));
+    }
+    if user.totp_secret.is_none() {
+        return Err(AppError::BadRequest(
+            "call /api/auth/2fa/setup first".into(),
+        ));
+    }
+    let Some(step) = two_factor::check_setup_code(&user, &req.code)? else {
+        return Err(AppError::Validation(
+            [("code".to_string(), "is not valid".to_string())].into(),
+        ));
+    };
+
+    sqlx::query(
+        "UPDATE users SET totp_enabled=1, totp_last_step=?, updated_at=datetime('now') \
+         WHERE id=?",
+    )
+    .bind(step)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await?;
+    let recovery_codes = two_factor::replace_recovery_codes(&state, &user.id).await?;
+    audit::record(
+        &state.db,
+        AuthEventKind::TwoFactorEnabled,
+        &user.username,
+        Some(&user.id),
+        Some(auth::client_ip(&state, &headers, addr.ip())),
+        None,
+    )
+    .await;
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// POST /api/auth/2fa/disable  — `{ password }`
+pub async fn disable_two_factor(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<PasswordConfirmation>,
+) -> Result<StatusCode> {
+    confirm_password(&user, &req.password)?;
+    two_factor::disable(&state, &user.id).await?;
+    if user.totp_enabled {
+        audit::record(
+            &state.db,
+            AuthEventKind::TwoFactorDisabled,
+            &user.username,
+            Some(&user.id),
+            Some(auth::client_ip(&state, &headers, addr.ip())),
+            None,
+        )
+        .await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/auth/2fa/recovery-codes  — `{ password }`; replaces every
+/// recovery code with a new set
+pub async fn regenerate_recovery_codes(
+    SessionUser(user): SessionUser,
+    State(state): State<AppState>,
+    Json(req): Json<PasswordConfirmation>,
+) -> Result<Json<RecoveryCodes>> {
+    confirm_password(&user, &req.password)?;
+    if !user.totp_enabled {
+        return Err(AppError::BadRequest(
+            "two-factor authentication is not enabled".into(),
+        ));
+    }
+    let recovery_codes = two_factor::replace_recovery_codes(&state, &user.id).await?;
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// DELETE /api/users/:id/2fa  (admin) — reset 2FA for a user who has lost
+/// their authenticator and recovery codes
+pub async fn reset_two_factor(
+    AdminUser(admin): AdminUser,
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    let user: User = sqlx::query_as("SELECT * FROM users WHERE id=?")
+        .bind(&id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    two_factor::disable(&state, &user.id).await?;
+    audit::record(
+        &state.db,
+        AuthEventKind::TwoFactorDisabled,
+        &user.username,
+        Some(&user.id),
+        Some(auth::client_ip(&state, &headers, addr.ip())),
+        Some(&format!("reset by {}", admin.username)),
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
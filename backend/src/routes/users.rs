@@ -1,14 +1,17 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
     Json,
 };
 use serde::Serialize;
+use std::net::SocketAddr;
 
 use crate::{
-    auth::{hash_password, AuthUser},
+    audit,
+    auth::{self, hash_password, AdminUser, AuthUser, SessionUser},
     error::{AppError, Result},
-    models::{CreateUserRequest, User},
+    models::{AuthEventKind, CreateUserRequest, Role, User, UserPreferences},
+    settings,
     state::AppState,
 };
 
@@ -16,6 +19,8 @@ use crate::{
 pub struct UserView {
     pub id: String,
     pub username: String,
+    pub role: Role,
+    pub two_factor_enabled: bool,
     pub created_at: String,
 }
 
@@ -24,6 +29,8 @@ impl From<User> for UserView {
         UserView {
             id: u.id,
             username: u.username,
+            role: u.role,
+            two_factor_enabled: u.totp_enabled,
             created_at: u.created_at,
         }
     }
@@ -31,7 +38,7 @@ impl From<User> for UserView {
 
 /// GET /api/users  (admin: lists all users)
 pub async fn list_users(
-    AuthUser(_user): AuthUser,
+    AdminUser(_admin): AdminUser,
     State(state): State<AppState>,
 ) -> Result<Json<Vec<UserView>>> {
     let users: Vec<User> = sqlx::query_as("SELECT * FROM users ORDER BY created_at")
@@ -45,9 +52,49 @@ pub async fn get_me(AuthUser(user): AuthUser) -> Json<UserView> {
     Json(UserView::from(user))
 }
 
-/// POST /api/users
+/// GET /api/users/me/preferences
+pub async fn get_preferences(AuthUser(user): AuthUser) -> Json<UserPreferences> {
+    Json(UserPreferences {
+        default_quality: user.default_quality,
+        default_subtitles: user.default_subtitles,
+    })
+}
+
+/// PUT /api/users/me/preferences  — `null` falls back to the global setting
+pub async fn update_preferences(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(req): Json<UserPreferences>,
+) -> Result<Json<UserPreferences>> {
+    let quality = match req.default_quality.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(q) => Some(
+            settings::spec("default_quality")
+                .expect("default_quality is in the schema")
+                .validate(q)
+                .map_err(|e| AppError::BadRequest(format!("default_quality {e}")))?,
+        ),
+    };
+
+    sqlx::query(
+        "UPDATE users SET default_quality=?, default_subtitles=?, updated_at=datetime('now') \
+         WHERE id=?",
+    )
+    .bind(&quality)
+    .bind(req.default_subtitles)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await?;
+
+    Ok(Json(UserPreferences {
+        default_quality: quality,
+        default_subtitles: req.default_subtitles,
+    }))
+}
+
+/// POST /api/users  (admin)
 pub async fn create_user(
-    AuthUser(_caller): AuthUser,
+    AdminUser(_admin): AdminUser,
     State(state): State<AppState>,
     Json(req): Json<CreateUserRequest>,
 ) -> Result<(StatusCode, Json<UserView>)> {
@@ -76,10 +123,11 @@ pub async fn create_user(
     let hash = hash_password(&req.password)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    sqlx::query("INSERT INTO users (id, username, password) VALUES (?, ?, ?)")
+    sqlx::query("INSERT INTO users (id, username, password, role) VALUES (?, ?, ?, ?)")
         .bind(&id)
         .bind(&req.username)
         .bind(&hash)
+        .bind(req.role)
         .execute(&state.db)
         .await?;
 
@@ -91,9 +139,9 @@ pub async fn create_user(
     Ok((StatusCode::CREATED, Json(UserView::from(user))))
 }
 
-/// DELETE /api/users/:id
+/// DELETE /api/users/:id  (admin)
 pub async fn delete_user(
-    AuthUser(caller): AuthUser,
+    AdminUser(caller): AdminUser,
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<StatusCode> {
@@ -101,6 +149,12 @@ pub async fn delete_user(
         return Err(AppError::BadRequest("cannot delete yourself".into()));
     }
 
+    // Their queue items go with them; so must the items' logs.
+    let items: Vec<(String,)> = sqlx::query_as("SELECT id FROM queue_items WHERE user_id=?")
+        .bind(&id)
+        .fetch_all(&state.db)
+        .await?;
+
     let result = sqlx::query("DELETE FROM users WHERE id=?")
         .bind(&id)
         .execute(&state.db)
@@ -110,6 +164,10 @@ pub async fn delete_user(
         return Err(AppError::NotFound);
     }
 
+    for (item_id,) in &items {
+        state.download_logs.remove(item_id).await;
+    }
+
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -120,13 +178,15 @@ pub struct ChangePasswordRequest {
 }
 
 pub async fn change_password(
-    AuthUser(caller): AuthUser,
+    SessionUser(caller): SessionUser,
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Path(id): Path<String>,
     Json(req): Json<ChangePasswordRequest>,
 ) -> Result<StatusCode> {
-    // Users can only change their own password (extend with admin role if needed)
-    if caller.id != id {
+    // Users can change their own password; admins can reset anyone's
+    if caller.id != id && !caller.is_admin() {
         return Err(AppError::Forbidden);
     }
     if req.new_password.len() < 8 {
@@ -138,11 +198,80 @@ pub async fn change_password(
     let hash = hash_password(&req.new_password)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    sqlx::query("UPDATE users SET password=?, updated_at=datetime('now') WHERE id=?")
-        .bind(&hash)
+    let username: String = sqlx::query_scalar(
+        "UPDATE users SET password=?, updated_at=datetime('now') WHERE id=? RETURNING username",
+    )
+    .bind(&hash)
+    .bind(&id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    let detail = (caller.id != id).then(|| format!("reset by {}", caller.username));
+    audit::record(
+        &state.db,
+        AuthEventKind::PasswordChanged,
+        &username,
+        Some(&id),
+        Some(auth::client_ip(&state, &headers, addr.ip())),
+        detail.as_deref(),
+    )
+    .await;
+
+    // Sign out everywhere else; whoever made the change stays logged in.
+    let keep = if caller.id == id {
+        auth::request_token(&headers)
+    } else {
+        None
+    };
+    auth::revoke_sessions(&state, &id, keep.as_deref()).await?;
+    // API tokens too: one may have leaked along with the old password.
+    sqlx::query("DELETE FROM api_tokens WHERE user_id=?")
         .bind(&id)
         .execute(&state.db)
         .await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// PUT /api/users/:id/role  (admin) — promote or demote
+#[derive(serde::Deserialize)]
+pub struct SetRoleRequest {
+    pub role: Role,
+}
+
+pub async fn set_role(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<SetRoleRequest>,
+) -> Result<Json<UserView>> {
+    let user: User = sqlx::query_as("SELECT * FROM users WHERE id=?")
+        .bind(&id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    // Never leave the install without anyone who can manage it
+    if user.is_admin() && req.role != Role::Admin {
+        let (admins,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE role='admin'")
+            .fetch_one(&state.db)
+            .await?;
+        if admins <= 1 {
+            return Err(AppError::Conflict("cannot demote the last admin".into()));
+        }
+    }
+
+    sqlx::query("UPDATE users SET role=?, updated_at=datetime('now') WHERE id=?")
+        .bind(req.role)
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+
+    let user: User = sqlx::query_as("SELECT * FROM users WHERE id=?")
+        .bind(&id)
+        .fetch_one(&state.db)
+        .await?;
+
+    Ok(Json(UserView::from(user)))
+}
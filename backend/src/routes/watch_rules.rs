@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+
+use crate::{
+    auth::AuthUser,
+    error::{AppError, Result},
+    models::{CreateWatchRuleRequest, UpdateWatchRuleRequest, User, WatchRule, WatchRuleMatch},
+    settings,
+    state::AppState,
+    watch_rules::{self, Matcher},
+};
+
+/// Check a rule body, returning its compiled matcher and normalised quality,
+/// or per-field errors.
+fn validate(req: &CreateWatchRuleRequest) -> Result<(Matcher, Option<String>)> {
+    let mut errors = BTreeMap::new();
+
+    if req.query.trim().is_empty() {
+        errors.insert("query".to_string(), "must not be blank".to_string());
+    }
+    if req.media_type != "tv" && req.media_type != "radio" {
+        errors.insert("media_type".to_string(), "must be tv or radio".to_string());
+    }
+    let quality = match req.quality.as_deref() {
+        Some(q) => match settings::spec("default_quality")
+            .expect("default_quality is in the schema")
+            .validate(q)
+        {
+            Ok(q) => Some(q),
+            Err(e) => {
+                errors.insert("quality".to_string(), e);
+                None
+            }
+        },
+        None => None,
+    };
+    let matcher = Matcher::new(
+        &req.query,
+        req.is_regex,
+        req.channel.as_deref(),
+        req.exclude.as_deref(),
+    );
+    if let Err(e) = &matcher {
+        errors.insert("query".to_string(), format!("invalid regex: {e}"));
+    }
+
+    match matcher {
+        Ok(matcher) if errors.is_empty() => Ok((matcher, quality)),
+        _ => Err(AppError::Validation(errors)),
+    }
+}
+
+/// Treat blank optional filters as unset.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// The entry `id`, if `user` may see it.
+async fn fetch(state: &AppState, user: &User, id: &str) -> Result<WatchRule> {
+    let rule: Option<WatchRule> = sqlx::query_as("SELECT * FROM watch_rules WHERE id=?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    rule.filter(|r| user.can_manage(&r.user_id))
+        .ok_or(AppError::NotFound)
+}
+
+/// GET /api/watch-rules
+pub async fn list_watch_rules(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WatchRule>>> {
+    let rules: Vec<WatchRule> =
+        sqlx::query_as("SELECT * FROM watch_rules WHERE ?1 IS NULL OR user_id=?1 ORDER BY name")
+            .bind(user.owner_filter())
+            .fetch_all(&state.db)
+            .await?;
+    Ok(Json(rules))
+}
+
+/// GET /api/watch-rules/:id
+pub async fn get_watch_rule(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<WatchRule>> {
+    fetch(&state, &user, &id).await.map(Json)
+}
+
+/// POST /api/watch-rules
+pub async fn create_watch_rule(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateWatchRuleRequest>,
+) -> Result<(StatusCode, Json<WatchRule>)> {
+    let (_, quality) = validate(&req)?;
+    let name = match req.name.trim() {
+        "" => req.query.trim(),
+        name => name,
+    };
+
+    let id = WatchRule::new_id();
+    sqlx::query(
+        "INSERT INTO watch_rules \
+         (id, name, query, is_regex, media_type, channel, exclude, quality, subtitles, user_id) \
+         VALUES (?,?,?,?,?,?,?,?,?,?)",
+    )
+    .bind(&id)
+    .bind(name)
+    .bind(&req.query)
+    .bind(req.is_regex)
+    .bind(&req.media_type)
+    .bind(non_empty(req.channel))
+    .bind(non_empty(req.exclude))
+    .bind(&quality)
+    .bind(req.subtitles)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await?;
+
+    let rule = fetch(&state, &user, &id).await?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// PATCH /api/watch-rules/:id
+pub async fn update_watch_rule(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateWatchRuleRequest>,
+) -> Result<Json<WatchRule>> {
+    let rule = fetch(&state, &user, &id).await?;
+
+    let merged = CreateWatchRuleRequest {
+        name: req.name.unwrap_or(rule.name),
+        query: req.query.unwrap_or(rule.query),
+        is_regex: req.is_regex.unwrap_or(rule.is_regex),
+        media_type: req.media_type.unwrap_or(rule.media_type),
+        channel: req.channel.or(rule.channel),
+        exclude: req.exclude.or(rule.exclude),
+        // An empty quality goes back to the default_quality setting.
+        quality: match req.quality {
+            Some(q) if q.is_empty() => None,
+            Some(q) => Some(q),
+            None => rule.quality,
+        },
+        subtitles: req.subtitles.or(rule.subtitles),
+    };
+    let (_, quality) = validate(&merged)?;
+    if merged.name.trim().is_empty() {
+        return Err(AppError::BadRequest("name cannot be empty".into()));
+    }
+
+    sqlx::query(
+        "UPDATE watch_rules SET name=?, query=?, is_regex=?, media_type=?, channel=?, \
+         exclude=?, quality=?, subtitles=?, enabled=? WHERE id=?",
+    )
+    .bind(merged.name.trim())
+    .bind(&merged.query)
+    .bind(merged.is_regex)
+    .bind(&merged.media_type)
+    .bind(non_empty(merged.channel))
+    .bind(non_empty(merged.exclude))
+    .bind(&quality)
+    .bind(merged.subtitles)
+    .bind(req.enabled.unwrap_or(rule.enabled))
+    .bind(&id)
+    .execute(&state.db)
+    .await?;
+
+    fetch(&state, &user, &id).await.map(Json)
+}
+
+/// DELETE /api/watch-rules/:id  — programmes already queued are kept
+pub async fn delete_watch_rule(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    fetch(&state, &user, &id).await?;
+    sqlx::query("DELETE FROM watch_rules WHERE id=?")
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/watch-rules/preview  — dry run: what a rule body would match in
+/// the programme cache right now. Nothing is saved or queued.
+pub async fn preview_watch_rule(
+    AuthUser(_user): AuthUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateWatchRuleRequest>,
+) -> Result<Json<Vec<WatchRuleMatch>>> {
+    let (matcher, _) = validate(&req)?;
+    let matches = watch_rules::preview(&state, &matcher, &req.media_type).await?;
+    Ok(Json(matches))
+}
@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+
+use crate::{
+    auth::AdminUser,
+    error::{AppError, Result},
+    models::{
+        CreateWebhookRequest, CreatedWebhook, PaginatedResponse, UpdateWebhookRequest, Webhook,
+        WebhookDelivery, WebhookDeliveryQuery, WebhookEvent,
+    },
+    state::AppState,
+};
+
+/// Check a webhook's fields, returning per-field errors.
+fn validate(name: &str, url: &str, events: &[WebhookEvent], secret: Option<&str>) -> Result<()> {
+    let mut errors = BTreeMap::new();
+    if name.trim().is_empty() {
+        errors.insert("name".to_string(), "must not be blank".to_string());
+    }
+    match reqwest::Url::parse(url.trim()) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") => {}
+        Ok(_) => {
+            errors.insert(
+                "url".to_string(),
+                "must be an http or https URL".to_string(),
+            );
+        }
+        Err(e) => {
+            errors.insert("url".to_string(), format!("invalid URL: {e}"));
+        }
+    }
+    if events.is_empty() {
+        errors.insert(
+            "events".to_string(),
+            "choose at least one event".to_string(),
+        );
+    } else if events.contains(&WebhookEvent::Ping) {
+        errors.insert(
+            "events".to_string(),
+            "ping is only sent by the test endpoint".to_string(),
+        );
+    }
+    if secret.is_some_and(|s| s.trim().len() < 16) {
+        errors.insert(
+            "secret".to_string(),
+            "must be at least 16 characters".to_string(),
+        );
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation(errors))
+    }
+}
+
+async fn fetch(state: &AppState, id: &str) -> Result<Webhook> {
+    let webhook: Option<Webhook> = sqlx::query_as("SELECT * FROM webhooks WHERE id=?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    webhook.ok_or(AppError::NotFound)
+}
+
+/// Events in a fixed order, each once.
+fn normalise(mut events: Vec<WebhookEvent>) -> Vec<WebhookEvent> {
+    events.sort_by_key(|e| *e as u8);
+    events.dedup();
+    events
+}
+
+/// GET /api/webhooks  (admin) — never the secrets
+pub async fn list_webhooks(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Webhook>>> {
+    let webhooks: Vec<Webhook> = sqlx::query_as("SELECT * FROM webhooks ORDER BY name")
+        .fetch_all(&state.db)
+        .await?;
+    Ok(Json(webhooks))
+}
+
+/// GET /api/webhooks/:id  (admin)
+pub async fn get_webhook(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Webhook>> {
+    fetch(&state, &id).await.map(Json)
+}
+
+/// POST /api/webhooks  (admin) — the response holds the signing secret; it
+/// is not shown again
+pub async fn create_webhook(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<CreatedWebhook>)> {
+    validate(&req.name, &req.url, &req.events, req.secret.as_deref())?;
+    let secret = match req.secret {
+        Some(secret) => secret.trim().to_string(),
+        None => hex::encode(rand::random::<[u8; 32]>()),
+    };
+
+    let id = Webhook::new_id();
+    sqlx::query("INSERT INTO webhooks (id, name, url, secret, events) VALUES (?,?,?,?,?)")
+        .bind(&id)
+        .bind(req.name.trim())
+        .bind(req.url.trim())
+        .bind(&secret)
+        .bind(sqlx::types::Json(normalise(req.events)))
+        .execute(&state.db)
+        .await?;
+
+    let webhook = fetch(&state, &id).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedWebhook { webhook, secret }),
+    ))
+}
+
+/// PATCH /api/webhooks/:id  (admin)
+pub async fn update_webhook(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateWebhookRequest>,
+) -> Result<Json<Webhook>> {
+    let webhook = fetch(&state, &id).await?;
+    let name = req.name.unwrap_or(webhook.name);
+    let url = req.url.unwrap_or(webhook.url);
+    let events = req.events.unwrap_or(webhook.events);
+    validate(&name, &url, &events, req.secret.as_deref())?;
+
+    sqlx::query("UPDATE webhooks SET name=?, url=?, secret=?, events=?, enabled=? WHERE id=?")
+        .bind(name.trim())
+        .bind(url.trim())
+        .bind(
+            req.secret
+                .as_deref()
+                .map_or(webhook.secret, |s| s.trim().to_string()),
+        )
+        .bind(sqlx::types::Json(normalise(events)))
+        .bind(req.enabled.unwrap_or(webhook.enabled))
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+
+    fetch(&state, &id).await.map(Json)
+}
+
+/// DELETE /api/webhooks/:id  (admin) — its delivery log and any waiting
+/// retries go with it
+pub async fn delete_webhook(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    fetch(&state, &id).await?;
+    sqlx::query("DELETE FROM webhooks WHERE id=?")
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/webhooks/:id/deliveries  (admin) — newest first;
+/// `?status=&page=&per_page=`
+pub async fn list_deliveries(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(q): Query<WebhookDeliveryQuery>,
+) -> Result<Json<PaginatedResponse<WebhookDelivery>>> {
+    fetch(&state, &id).await?;
+    let page = q.page.unwrap_or(1).max(1);
+    let per_page = q.per_page.unwrap_or(50).clamp(1, 200);
+    let offset = (page - 1) * per_page;
+
+    let deliveries: Vec<WebhookDelivery> = sqlx::query_as(
+        "SELECT * FROM webhook_deliveries WHERE webhook_id=?1 AND (?2 IS NULL OR status=?2) \
+         ORDER BY id DESC LIMIT ?3 OFFSET ?4",
+    )
+    .bind(&id)
+    .bind(q.status)
+    .bind(per_page)
+    .bind(offset)
+    .fetch_all(&state.db)
+    .await?;
+
+    let (total,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM webhook_deliveries WHERE webhook_id=?1 AND (?2 IS NULL OR status=?2)",
+    )
+    .bind(&id)
+    .bind(q.status)
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok(Json(PaginatedResponse {
+        data: deliveries,
+        total,
+        page,
+        per_page,
+    }))
+}
+
+/// POST /api/webhooks/:id/test  (admin) — send a `ping` now, even if the
+/// webhook is disabled; the delivery's outcome shows up in its log
+pub async fn test_webhook(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<WebhookDelivery>)> {
+    let webhook = fetch(&state, &id).await?;
+    let delivery = state.webhooks.ping(&webhook).await?;
+    Ok((StatusCode::ACCEPTED, Json(delivery)))
+}
@@ -1,16 +1,27 @@
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Query, State,
+        ConnectInfo, Query, State,
     },
+    http::HeaderMap,
     response::Response,
 };
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+};
 use tokio::sync::broadcast;
 use tracing::{debug, warn};
 
-use crate::{auth::verify_token, models::WsEvent, state::AppState};
+use crate::{
+    auth::{authenticate, proxy_user},
+    db::Db,
+    download_log::{DownloadLogs, LogLine},
+    models::{User, WsEvent},
+    state::AppState,
+};
 
 #[derive(Deserialize)]
 pub struct WsQuery {
@@ -19,68 +30,238 @@ pub struct WsQuery {
     pub token: Option<String>,
 }
 
+/// Messages a client may send over the socket.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Start streaming an item's get_iplayer output as `log_line` events,
+    /// beginning with its most recent lines.
+    Tail {
+        id: String,
+    },
+    Untail {
+        id: String,
+    },
+}
+
+/// Lines of existing log sent when a client starts tailing an item.
+const TAIL_BACKLOG_LINES: usize = 100;
+
 /// GET /ws  — real-time event stream
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     Query(query): Query<WsQuery>,
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> Response {
-    // Validate token before upgrading
-    let authed = query
-        .token
-        .as_deref()
-        .and_then(|t| verify_token(t, &state.config.secret))
-        .is_some();
-
-    if !authed {
+    // Validate token before upgrading; without one, fall back to a trusted
+    // proxy's user header.
+    let user = match query.token.as_deref().filter(|t| !t.is_empty()) {
+        Some(t) => authenticate(&state, t).await,
+        None => proxy_user(&state, &headers, Some(addr.ip())).await,
+    };
+
+    let Some(user) = user else {
         // Return 401 without upgrading
         return axum::response::IntoResponse::into_response((
             axum::http::StatusCode::UNAUTHORIZED,
             "unauthorized",
         ));
-    }
+    };
 
     let rx = state.events.subscribe();
-    ws.on_upgrade(move |socket| handle_socket(socket, rx))
+    let filter = EventFilter::new(user, state.db.clone());
+    let logs = state.download_logs.clone();
+    ws.on_upgrade(move |socket| handle_socket(socket, rx, filter, logs))
 }
 
-async fn handle_socket(socket: WebSocket, mut rx: broadcast::Receiver<WsEvent>) {
+/// Decides which events a connection receives. Admins see everything; other
+/// users only events about their own queue items, subscriptions and rules.
+struct EventFilter {
+    user: User,
+    db: Db,
+    /// Queue item id → owner, so progress events don't each hit the database.
+    owners: HashMap<String, String>,
+}
+
+impl EventFilter {
+    fn new(user: User, db: Db) -> Self {
+        EventFilter {
+            user,
+            db,
+            owners: HashMap::new(),
+        }
+    }
+
+    async fn allows(&mut self, event: &WsEvent) -> bool {
+        if self.user.is_admin() {
+            return true;
+        }
+        let owner = match event {
+            WsEvent::ItemAdded { item } => {
+                self.owners.insert(item.id.clone(), item.user_id.clone());
+                Some(item.user_id.clone())
+            }
+            WsEvent::ItemRemoved { id, user_id } => {
+                self.owners.remove(id);
+                Some(user_id.clone())
+            }
+            WsEvent::Progress { id, .. }
+            | WsEvent::StatusChange { id, .. }
+            | WsEvent::Error { id, .. }
+            | WsEvent::LogLine { id, .. }
+            | WsEvent::StepChange { id, .. } => self.item_owner(id).await,
+            WsEvent::SubscriptionNewEpisodes {
+                subscription_id, ..
+            } => {
+                self.lookup(
+                    "SELECT user_id FROM subscriptions WHERE id=?",
+                    subscription_id,
+                )
+                .await
+            }
+            WsEvent::WatchRuleMatched { rule_id, .. } => {
+                self.lookup("SELECT user_id FROM watch_rules WHERE id=?", rule_id)
+                    .await
+            }
+        };
+        owner.as_deref() == Some(self.user.id.as_str())
+    }
+
+    /// Whether this connection may tail queue item `id`.
+    async fn may_tail(&mut self, id: &str) -> bool {
+        match self.item_owner(id).await {
+            Some(owner) => self.user.can_manage(&owner),
+            None => false,
+        }
+    }
+
+    async fn item_owner(&mut self, id: &str) -> Option<String> {
+        if let Some(owner) = self.owners.get(id) {
+            return Some(owner.clone());
+        }
+        let owner = self
+            .lookup("SELECT user_id FROM queue_items WHERE id=?", id)
+            .await?;
+        self.owners.insert(id.to_string(), owner.clone());
+        Some(owner)
+    }
+
+    async fn lookup(&self, sql: &str, id: &str) -> Option<String> {
+        sqlx::query_as::<_, (String,)>(sql)
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .ok()
+            .flatten()
+            .map(|(owner,)| owner)
+    }
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    mut rx: broadcast::Receiver<WsEvent>,
+    mut filter: EventFilter,
+    logs: DownloadLogs,
+) {
     let (mut sink, mut stream) = socket.split();
 
-    // Task: forward broadcast events → client
-    let send_task = tokio::spawn(async move {
-        loop {
-            match rx.recv().await {
-                Ok(event) => {
-                    let json = match serde_json::to_string(&event) {
-                        Ok(j) => j,
+    // Items this client is tailing. The log channel is only subscribed to
+    // while there is at least one.
+    let mut tailing: HashSet<String> = HashSet::new();
+    let mut log_rx: Option<broadcast::Receiver<LogLine>> = None;
+
+    loop {
+        let events: Vec<WsEvent> = tokio::select! {
+            // Forward broadcast events → client
+            event = rx.recv() => match event {
+                Ok(event) if filter.allows(&event).await => vec![event],
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("WS subscriber lagged by {n} messages");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            line = next_log_line(&mut log_rx) => match line {
+                Ok(line) if tailing.contains(&line.item_id) => vec![WsEvent::LogLine {
+                    id: line.item_id,
+                    line: line.line,
+                }],
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("WS log tail lagged by {n} lines");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            // Ping/pong and close frames are handled automatically; text
+            // frames may start or stop a log tail.
+            msg = stream.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    match serde_json::from_str::<ClientMessage>(text.as_str()) {
+                        Ok(ClientMessage::Tail { id }) => {
+                            if !filter.may_tail(&id).await {
+                                debug!("WS tail of {id} refused");
+                                continue;
+                            }
+                            log_rx.get_or_insert_with(|| logs.subscribe());
+                            let backlog = logs
+                                .read(&id, Some(TAIL_BACKLOG_LINES))
+                                .await
+                                .unwrap_or_default();
+                            tailing.insert(id.clone());
+                            backlog
+                                .lines()
+                                .map(|line| WsEvent::LogLine {
+                                    id: id.clone(),
+                                    line: line.to_string(),
+                                })
+                                .collect()
+                        }
+                        Ok(ClientMessage::Untail { id }) => {
+                            tailing.remove(&id);
+                            if tailing.is_empty() {
+                                log_rx = None;
+                            }
+                            continue;
+                        }
                         Err(e) => {
-                            warn!("WS serialise error: {e}");
+                            debug!("WS recv (ignored): {e}");
                             continue;
                         }
-                    };
-                    if sink.send(Message::Text(json.into())).await.is_err() {
-                        break; // client disconnected
                     }
                 }
-                Err(broadcast::error::RecvError::Lagged(n)) => {
-                    warn!("WS subscriber lagged by {n} messages");
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(m)) => {
+                    debug!("WS recv (ignored): {m:?}");
+                    continue;
                 }
-                Err(broadcast::error::RecvError::Closed) => break,
-            }
-        }
-    });
-
-    // Keep reading from the client (ping/pong and close frames are handled
-    // automatically; we don't currently process any client→server messages).
-    while let Some(msg) = stream.next().await {
-        match msg {
-            Ok(Message::Close(_)) | Err(_) => break,
-            Ok(m) => {
-                debug!("WS recv (ignored): {m:?}");
+            },
+        };
+
+        for event in events {
+            let json = match serde_json::to_string(&event) {
+                Ok(j) => j,
+                Err(e) => {
+                    warn!("WS serialise error: {e}");
+                    continue;
+                }
+            };
+            if sink.send(Message::Text(json.into())).await.is_err() {
+                return; // client disconnected
             }
         }
     }
+}
 
-    send_task.abort();
+/// The next line for a log tail, or never if nothing is being tailed.
+async fn next_log_line(
+    rx: &mut Option<broadcast::Receiver<LogLine>>,
+) -> Result<LogLine, broadcast::error::RecvError> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
 }
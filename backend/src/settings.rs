@@ -0,0 +1,506 @@
+/// Runtime settings.
+///
+/// The `settings` table overrides the environment defaults in `AppConfig`.
+/// `SettingsService` keeps a typed snapshot of the merged values in memory and
+/// publishes a new one on a watch channel whenever the table is written, so
+/// downloads, searches and long-running tasks always see the current values
+/// without a restart.
+///
+/// `SCHEMA` describes every key the table may hold; writes through the API are
+/// validated against it before they reach the database.
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::{config::AppConfig, db::Db, hooks, naming, postprocess};
+
+// ── Schema ─────────────────────────────────────────────────────────────────────
+
+/// The value type of a setting, with its constraints.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SettingKind {
+    /// Free text; `required` settings may not be blank.
+    Text { required: bool },
+    /// A whole number within `min..=max`.
+    Integer { min: i64, max: i64 },
+    /// `true` or `false`.
+    Boolean,
+    /// One of `values`.
+    Choice { values: &'static [&'static str] },
+    /// One of the friendly `presets`, or a comma-separated list of raw
+    /// get_iplayer quality `values` tried in order.
+    Quality {
+        presets: &'static [&'static str],
+        values: &'static [&'static str],
+    },
+    /// An `http`, `https` or `socks5` URL, or blank for none.
+    ProxyUrl,
+    /// A file naming template made of `tokens`, one of the named `presets`,
+    /// or blank to keep get_iplayer's own names.
+    Template {
+        presets: &'static [&'static str],
+        tokens: &'static [&'static str],
+    },
+    /// A comma-separated list of post-processing `steps`, run in the order
+    /// given, or blank for none.
+    Steps { steps: &'static [&'static str] },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingSpec {
+    pub key: &'static str,
+    pub label: &'static str,
+    /// Groups related settings on the settings page.
+    pub section: &'static str,
+    #[serde(flatten)]
+    pub kind: SettingKind,
+    pub default: &'static str,
+    pub description: &'static str,
+}
+
+const QUALITY_PRESETS: &[&str] = &["best", "good", "worst"];
+const QUALITY_VALUES: &[&str] = &[
+    "fhd", "hd", "sd", "web", "mobile", "1080p", "720p", "540p", "396p", "288p", "default", "high",
+    "standard", "low",
+];
+
+pub static SCHEMA: &[SettingSpec] = &[
+    SettingSpec {
+        key: "output_dir",
+        label: "Output Directory",
+        section: "Downloads",
+        kind: SettingKind::Text { required: false },
+        default: "/downloads",
+        description: "Where downloaded files will be saved. Blank uses OUTPUT_DIR.",
+    },
+    SettingSpec {
+        key: "max_concurrent",
+        label: "Max Concurrent Downloads",
+        section: "Downloads",
+        kind: SettingKind::Integer { min: 1, max: 10 },
+        default: "5",
+        description: "Downloads that may run at once. Changes resize the worker pool immediately.",
+    },
+    SettingSpec {
+        key: "max_download_retries",
+        label: "Max Download Retries",
+        section: "Downloads",
+        kind: SettingKind::Integer { min: 0, max: 10 },
+        default: "5",
+        description: "Times to retry a failed download. Each retry backs off exponentially (2s, 4s, 8s…).",
+    },
+    SettingSpec {
+        key: "default_quality",
+        label: "Default Quality",
+        section: "Downloads",
+        kind: SettingKind::Quality {
+            presets: QUALITY_PRESETS,
+            values: QUALITY_VALUES,
+        },
+        default: "best",
+        description: "Quality for new queue items that do not choose one.",
+    },
+    SettingSpec {
+        key: "subtitles",
+        label: "Download subtitles",
+        section: "Downloads",
+        kind: SettingKind::Boolean,
+        default: "true",
+        description: "Whether new queue items fetch subtitles unless they say otherwise.",
+    },
+    SettingSpec {
+        key: "tvmode",
+        label: "TV quality for \"best\"",
+        section: "Downloads",
+        kind: SettingKind::Quality {
+            presets: &["best"],
+            values: QUALITY_VALUES,
+        },
+        default: "best",
+        description: "get_iplayer TV qualities to try for items set to \"best\", e.g. \"hd,sd\".",
+    },
+    SettingSpec {
+        key: "radiomode",
+        label: "Radio quality for \"best\"",
+        section: "Downloads",
+        kind: SettingKind::Quality {
+            presets: &["best"],
+            values: QUALITY_VALUES,
+        },
+        default: "best",
+        description: "get_iplayer radio qualities to try for items set to \"best\", e.g. \"high\".",
+    },
+    SettingSpec {
+        key: "filename_template",
+        label: "File naming",
+        section: "Downloads",
+        kind: SettingKind::Template {
+            presets: &naming::PRESET_NAMES,
+            tokens: naming::TOKENS,
+        },
+        default: "",
+        description: "Where finished downloads are moved under the output directory, e.g. \"plex\" or \"{title}/Season {season}/{title} - S{season}E{number}\". Leave blank to keep get_iplayer's file names.",
+    },
+    SettingSpec {
+        key: "get_iplayer_path",
+        label: "get_iplayer path",
+        section: "Tools",
+        kind: SettingKind::Text { required: false },
+        default: "get_iplayer",
+        description: "Path to the get_iplayer binary. Blank uses GET_IPLAYER_PATH.",
+    },
+    SettingSpec {
+        key: "ffmpeg_path",
+        label: "ffmpeg path",
+        section: "Tools",
+        kind: SettingKind::Text { required: false },
+        default: "ffmpeg",
+        description: "Path to the ffmpeg binary. Blank uses FFMPEG_PATH.",
+    },
+    SettingSpec {
+        key: "proxy",
+        label: "HTTP Proxy",
+        section: "Tools",
+        kind: SettingKind::ProxyUrl,
+        default: "",
+        description: "Proxy for get_iplayer and BBC lookups. Blank uses PROXY, if set.",
+    },
+    SettingSpec {
+        key: "subscription_check_hours",
+        label: "Subscription check interval (hours)",
+        section: "Subscriptions",
+        kind: SettingKind::Integer { min: 1, max: 168 },
+        default: "6",
+        description: "How often subscribed series are checked for new episodes.",
+    },
+    SettingSpec {
+        key: "postprocess_steps",
+        label: "Steps",
+        section: "Post-processing",
+        kind: SettingKind::Steps {
+            steps: postprocess::STEPS,
+        },
+        default: "",
+        description: "Run after every download, in this order, e.g. \"remux_mkv,embed_subtitles,tag\". Steps: remux_mkv, embed_subtitles, tag, normalise_audio, transcode, move. Leave blank to keep files as downloaded.",
+    },
+    SettingSpec {
+        key: "loudness_target",
+        label: "Loudness target (LUFS)",
+        section: "Post-processing",
+        kind: SettingKind::Integer { min: -70, max: -5 },
+        default: "-16",
+        description: "Integrated loudness the normalise_audio step aims for.",
+    },
+    SettingSpec {
+        key: "transcode_video_codec",
+        label: "Transcode video codec",
+        section: "Post-processing",
+        kind: SettingKind::Text { required: false },
+        default: "",
+        description: "ffmpeg encoder the transcode step uses for video, e.g. \"libx265\". Blank keeps the video unless a bitrate is set.",
+    },
+    SettingSpec {
+        key: "transcode_video_bitrate",
+        label: "Transcode video bitrate",
+        section: "Post-processing",
+        kind: SettingKind::Text { required: false },
+        default: "",
+        description: "Target video bitrate for the transcode step, e.g. \"2M\".",
+    },
+    SettingSpec {
+        key: "transcode_audio_codec",
+        label: "Transcode audio codec",
+        section: "Post-processing",
+        kind: SettingKind::Text { required: false },
+        default: "",
+        description: "ffmpeg encoder the transcode step uses for audio, e.g. \"libopus\". Blank keeps the audio unless a bitrate is set.",
+    },
+    SettingSpec {
+        key: "transcode_audio_bitrate",
+        label: "Audio bitrate",
+        section: "Post-processing",
+        kind: SettingKind::Text { required: false },
+        default: "",
+        description: "Target audio bitrate for the transcode and normalise_audio steps, e.g. \"128k\".",
+    },
+    SettingSpec {
+        key: "final_dir",
+        label: "Final location",
+        section: "Post-processing",
+        kind: SettingKind::Text { required: false },
+        default: "",
+        description: "Where the move step puts finished files, keeping their path under the output directory. Files moved out of the output directory leave the library.",
+    },
+    SettingSpec {
+        key: "hook_command",
+        label: "Command",
+        section: "Hooks",
+        kind: SettingKind::Text { required: false },
+        default: "",
+        description: "Shell command run when a download is done or has failed for good. It gets the item as TAPEDECK_* environment variables and as JSON on stdin. Leave blank for none.",
+    },
+    SettingSpec {
+        key: "hook_mode",
+        label: "Run",
+        section: "Hooks",
+        kind: SettingKind::Choice {
+            values: hooks::MODES,
+        },
+        default: "item",
+        description: "\"item\" runs the command for every download as it finishes; \"batch\" runs it once for everything that finished, when the queue has nothing left to do.",
+    },
+    SettingSpec {
+        key: "hook_timeout_secs",
+        label: "Timeout (seconds)",
+        section: "Hooks",
+        kind: SettingKind::Integer { min: 1, max: 3600 },
+        default: "60",
+        description: "How long the command may run before it is killed.",
+    },
+];
+
+/// Look up the schema entry for `key`.
+pub fn spec(key: &str) -> Option<&'static SettingSpec> {
+    SCHEMA.iter().find(|s| s.key == key)
+}
+
+impl SettingSpec {
+    /// Check `value` against this setting's type and constraints, returning
+    /// the normalised value to store or a message explaining the problem.
+    pub fn validate(&self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        match &self.kind {
+            SettingKind::Text { required } => {
+                if *required && value.is_empty() {
+                    return Err("must not be blank".to_string());
+                }
+                Ok(value.to_string())
+            }
+            SettingKind::Integer { min, max } => match value.parse::<i64>() {
+                Ok(n) if (*min..=*max).contains(&n) => Ok(n.to_string()),
+                Ok(_) => Err(format!("must be between {min} and {max}")),
+                Err(_) => Err("must be a whole number".to_string()),
+            },
+            SettingKind::Boolean => match value.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Ok("true".to_string()),
+                "false" | "0" | "no" | "off" => Ok("false".to_string()),
+                _ => Err("must be true or false".to_string()),
+            },
+            SettingKind::Choice { values } => {
+                let value = value.to_ascii_lowercase();
+                if values.contains(&value.as_str()) {
+                    Ok(value)
+                } else {
+                    Err(format!("must be one of {}", values.join(", ")))
+                }
+            }
+            SettingKind::Quality { presets, values } => {
+                if presets.contains(&value) {
+                    return Ok(value.to_string());
+                }
+                let tokens: Vec<&str> = value.split(',').map(str::trim).collect();
+                match tokens.iter().find(|t| !values.contains(t)) {
+                    None if !value.is_empty() => Ok(tokens.join(",")),
+                    _ => Err(format!(
+                        "must be one of {} or a comma-separated list of {}",
+                        presets.join(", "),
+                        values.join(", ")
+                    )),
+                }
+            }
+            SettingKind::ProxyUrl => {
+                if value.is_empty() {
+                    return Ok(String::new());
+                }
+                match reqwest::Url::parse(value) {
+                    Ok(url) if matches!(url.scheme(), "http" | "https" | "socks5") => {
+                        Ok(value.to_string())
+                    }
+                    Ok(_) => Err("must be an http, https or socks5 URL".to_string()),
+                    Err(e) => Err(format!("invalid URL: {e}")),
+                }
+            }
+            SettingKind::Template { .. } => naming::validate(value),
+            SettingKind::Steps { .. } => postprocess::validate(value),
+        }
+    }
+}
+
+// ── Runtime snapshot ───────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct RuntimeSettings {
+    pub output_dir: String,
+    pub default_quality: String,
+    pub proxy: Option<String>,
+    pub max_concurrent: usize,
+    pub max_download_retries: u32,
+    pub get_iplayer_path: String,
+    pub ffmpeg_path: String,
+    pub subtitles: bool,
+    pub tvmode: String,
+    pub radiomode: String,
+    pub subscription_check_hours: u64,
+    /// Blank keeps get_iplayer's names.
+    pub filename_template: String,
+    /// Post-processing chain, in order; see `postprocess::STEPS`.
+    pub postprocess_steps: Vec<String>,
+    pub loudness_target: i64,
+    /// Blank leaves the stream alone unless its bitrate is set.
+    pub transcode_video_codec: String,
+    pub transcode_video_bitrate: String,
+    pub transcode_audio_codec: String,
+    pub transcode_audio_bitrate: String,
+    /// Where the `move` step puts files; blank makes it fail.
+    pub final_dir: String,
+    /// Blank runs no hook.
+    pub hook_command: String,
+    pub hook_mode: hooks::Mode,
+    pub hook_timeout_secs: u64,
+}
+
+impl RuntimeSettings {
+    fn from_config(config: &AppConfig) -> Self {
+        RuntimeSettings {
+            output_dir: config.output_dir.clone(),
+            default_quality: "best".to_string(),
+            proxy: config.proxy.clone().filter(|p| !p.is_empty()),
+            max_concurrent: config.max_concurrent,
+            max_download_retries: config.max_download_retries,
+            get_iplayer_path: config.get_iplayer_path.clone(),
+            ffmpeg_path: config.ffmpeg_path.clone(),
+            subtitles: true,
+            tvmode: "best".to_string(),
+            radiomode: "best".to_string(),
+            subscription_check_hours: 6,
+            filename_template: String::new(),
+            postprocess_steps: Vec::new(),
+            loudness_target: -16,
+            transcode_video_codec: String::new(),
+            transcode_video_bitrate: String::new(),
+            transcode_audio_codec: String::new(),
+            transcode_audio_bitrate: String::new(),
+            final_dir: String::new(),
+            hook_command: String::new(),
+            hook_mode: hooks::Mode::Item,
+            hook_timeout_secs: 60,
+        }
+    }
+
+    /// Overlay one row from the `settings` table. Unknown keys are ignored and
+    /// unparseable values keep the previous value. A blank value leaves the
+    /// setting unset, so the environment variable behind it, if any, applies.
+    fn apply(&mut self, key: &str, value: &str) {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str, into: &mut T) {
+            match value.trim().parse() {
+                Ok(v) => *into = v,
+                Err(_) => warn!("Ignoring invalid value {value:?} for setting {key}"),
+            }
+        }
+
+        match key {
+            "output_dir" if !value.is_empty() => self.output_dir = value.to_string(),
+            "default_quality" if !value.is_empty() => self.default_quality = value.to_string(),
+            "proxy" if !value.is_empty() => self.proxy = Some(value.to_string()),
+            "max_concurrent" if !value.is_empty() => parse(key, value, &mut self.max_concurrent),
+            "max_download_retries" if !value.is_empty() => {
+                parse(key, value, &mut self.max_download_retries)
+            }
+            "get_iplayer_path" if !value.is_empty() => self.get_iplayer_path = value.to_string(),
+            "ffmpeg_path" if !value.is_empty() => self.ffmpeg_path = value.to_string(),
+            "subtitles" => parse(key, value, &mut self.subtitles),
+            "tvmode" if !value.is_empty() => self.tvmode = value.to_string(),
+            "radiomode" if !value.is_empty() => self.radiomode = value.to_string(),
+            "subscription_check_hours" => parse(key, value, &mut self.subscription_check_hours),
+            "filename_template" => self.filename_template = value.trim().to_string(),
+            "postprocess_steps" => {
+                self.postprocess_steps = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "loudness_target" => parse(key, value, &mut self.loudness_target),
+            "transcode_video_codec" => self.transcode_video_codec = value.trim().to_string(),
+            "transcode_video_bitrate" => self.transcode_video_bitrate = value.trim().to_string(),
+            "transcode_audio_codec" => self.transcode_audio_codec = value.trim().to_string(),
+            "transcode_audio_bitrate" => self.transcode_audio_bitrate = value.trim().to_string(),
+            "final_dir" => self.final_dir = value.trim().to_string(),
+            "hook_command" => self.hook_command = value.trim().to_string(),
+            "hook_mode" => parse(key, value, &mut self.hook_mode),
+            "hook_timeout_secs" => parse(key, value, &mut self.hook_timeout_secs),
+            _ => {}
+        }
+    }
+
+    /// The quality to request from get_iplayer for an item. `tvmode` and
+    /// `radiomode` replace the built-in expansion of `best` for their media
+    /// type when set to anything other than `best`.
+    pub fn effective_quality<'a>(&'a self, media_type: &str, item_quality: &'a str) -> &'a str {
+        let mode = if media_type == "radio" {
+            &self.radiomode
+        } else {
+            &self.tvmode
+        };
+        if item_quality == "best" && mode != "best" {
+            mode
+        } else {
+            item_quality
+        }
+    }
+}
+
+// ── Service ────────────────────────────────────────────────────────────────────
+
+/// Cached, hot-reloadable view of the runtime settings.
+#[derive(Debug, Clone)]
+pub struct SettingsService {
+    db: Db,
+    config: Arc<AppConfig>,
+    tx: Arc<watch::Sender<Arc<RuntimeSettings>>>,
+}
+
+impl SettingsService {
+    pub async fn load(db: Db, config: Arc<AppConfig>) -> anyhow::Result<Self> {
+        let settings = read_settings(&db, &config).await?;
+        let (tx, _) = watch::channel(Arc::new(settings));
+        Ok(SettingsService {
+            db,
+            config,
+            tx: Arc::new(tx),
+        })
+    }
+
+    /// The current snapshot. Cheap to call; take a fresh one per operation.
+    pub fn current(&self) -> Arc<RuntimeSettings> {
+        Arc::clone(&self.tx.borrow())
+    }
+
+    /// Receive every new snapshot published by `reload`.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<RuntimeSettings>> {
+        self.tx.subscribe()
+    }
+
+    /// Re-read the `settings` table. Call after any write to it.
+    pub async fn reload(&self) -> sqlx::Result<()> {
+        let settings = read_settings(&self.db, &self.config).await?;
+        self.tx.send_replace(Arc::new(settings));
+        Ok(())
+    }
+}
+
+async fn read_settings(db: &Db, config: &AppConfig) -> sqlx::Result<RuntimeSettings> {
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT key, value FROM settings")
+        .fetch_all(db)
+        .await?;
+
+    let mut settings = RuntimeSettings::from_config(config);
+    for (key, value) in rows {
+        settings.apply(&key, &value);
+    }
+    Ok(settings)
+}
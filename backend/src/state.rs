@@ -1,14 +1,25 @@
 use std::sync::Arc;
 use tokio::sync::broadcast;
 
-use crate::{config::AppConfig, db::Db, models::WsEvent, queue::QueueHandle};
+use crate::{
+    config::AppConfig, db::Db, download_log::DownloadLogs, login_limiter::LoginLimiter,
+    models::WsEvent, queue::QueueHandle, settings::SettingsService, webhooks::Webhooks,
+};
 
 /// Shared application state injected into every Axum handler.
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub db: Db,
     pub config: Arc<AppConfig>,
+    /// Runtime settings from the `settings` table, reloaded on every write.
+    pub settings: SettingsService,
     pub queue: QueueHandle,
     /// Broadcast channel for real-time WebSocket events.
     pub events: broadcast::Sender<WsEvent>,
+    /// Each queue item's full get_iplayer output, with live tails.
+    pub download_logs: DownloadLogs,
+    /// Failed-login counts for brute-force lockouts.
+    pub login_limiter: Arc<LoginLimiter>,
+    /// Sends queue events to the configured webhooks.
+    pub webhooks: Webhooks,
 }
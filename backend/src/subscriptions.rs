@@ -0,0 +1,152 @@
+/// Series subscriptions (PVR mode).
+///
+/// Each subscription names a brand or series PID. A check lists its episodes
+/// with `get_iplayer --pid-recursive-list` and queues every episode PID that
+/// is neither in the queue nor in `download_history`, so an episode is only
+/// ever fetched once however often the series is checked.
+use std::time::Duration;
+
+use anyhow::Context;
+use tracing::{info, warn};
+
+use crate::{
+    iplayer::{self, EpisodesOptions},
+    models::{AddQueueItemRequest, QueueItem, SearchResult, Subscription, WebhookEvent, WsEvent},
+    queue,
+    state::AppState,
+};
+
+/// Upper bound on one `--pid-recursive-list` run; large brands are slow.
+const LIST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Check every enabled subscription on the `subscription_check_hours` interval.
+pub fn spawn_checker(state: AppState) {
+    tokio::spawn(async move {
+        // Give the server a moment to come up before the first sweep.
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        loop {
+            check_all(&state).await;
+            let hours = state.settings.current().subscription_check_hours.max(1);
+            tokio::time::sleep(Duration::from_secs(hours * 3600)).await;
+        }
+    });
+}
+
+pub async fn check_all(state: &AppState) {
+    let subs: Vec<Subscription> =
+        match sqlx::query_as("SELECT * FROM subscriptions WHERE enabled=1 ORDER BY created_at")
+            .fetch_all(&state.db)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("Subscriptions: failed to load subscriptions: {e}");
+                return;
+            }
+        };
+
+    info!("Checking {} subscription(s) for new episodes", subs.len());
+    for sub in subs {
+        if let Err(e) = check(state, &sub, true).await {
+            warn!(
+                "Subscription {} ({}) check failed: {e:#}",
+                sub.id, sub.title
+            );
+        }
+    }
+}
+
+/// List the episodes of `sub` and queue the ones never seen before. With
+/// `queue_new` false they are only recorded as seen (used to skip the back
+/// catalogue when subscribing). Returns the items that were queued.
+pub async fn check(
+    state: &AppState,
+    sub: &Subscription,
+    queue_new: bool,
+) -> anyhow::Result<Vec<QueueItem>> {
+    let result = find_new_episodes(state, sub).await;
+    let now = chrono::Utc::now().to_rfc3339();
+    let _ = sqlx::query("UPDATE subscriptions SET last_checked_at=?, last_error=? WHERE id=?")
+        .bind(&now)
+        .bind(result.as_ref().err().map(|e| format!("{e:#}")))
+        .bind(&sub.id)
+        .execute(&state.db)
+        .await;
+    let episodes = result?;
+
+    let source = format!("subscription:{}", sub.id);
+    let settings = state.settings.current();
+    let mut queued = Vec::new();
+
+    for episode in episodes {
+        if !queue_new {
+            sqlx::query("INSERT OR IGNORE INTO download_history (pid, source) VALUES (?, ?)")
+                .bind(&episode.pid)
+                .bind(&source)
+                .execute(&state.db)
+                .await?;
+            continue;
+        }
+        let req = AddQueueItemRequest {
+            quality: sub.quality.clone(),
+            subtitles: sub.subtitles,
+            ..AddQueueItemRequest::from_search_result(&episode)
+        };
+        let item = queue::insert_new_item(&state.db, &settings, &req, &sub.user_id, &source)
+            .await
+            .with_context(|| format!("queue episode {}", episode.pid))?;
+        if let Some(item) = item {
+            state
+                .webhooks
+                .notify(WebhookEvent::ItemAdded, &item.id)
+                .await;
+            let _ = state.events.send(WsEvent::ItemAdded {
+                item: Box::new(item.clone()),
+            });
+            queued.push(item);
+        }
+    }
+
+    if !queued.is_empty() {
+        info!(
+            "Subscription {} ({}) queued {} new episode(s)",
+            sub.id,
+            sub.title,
+            queued.len()
+        );
+        state.queue.wake();
+        let _ = state.events.send(WsEvent::SubscriptionNewEpisodes {
+            subscription_id: sub.id.clone(),
+            title: sub.title.clone(),
+            pids: queued.iter().map(|i| i.pid.clone()).collect(),
+        });
+    }
+
+    Ok(queued)
+}
+
+/// Episodes of `sub` whose PID is in neither the queue nor the history.
+async fn find_new_episodes(
+    state: &AppState,
+    sub: &Subscription,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let settings = state.settings.current();
+    let opts = EpisodesOptions {
+        pid: &sub.pid,
+        media_type: &sub.media_type,
+        get_iplayer_path: &settings.get_iplayer_path,
+        cache_dir: &state.config.iplayer_cache_dir,
+        proxy: settings.proxy.as_deref(),
+    };
+    let episodes = tokio::time::timeout(LIST_TIMEOUT, iplayer::list_episodes(opts))
+        .await
+        .context("episode listing timed out")??;
+
+    let mut fresh = Vec::new();
+    for episode in episodes {
+        if !queue::is_known_pid(&state.db, &episode.pid).await? {
+            fresh.push(episode);
+        }
+    }
+    Ok(fresh)
+}
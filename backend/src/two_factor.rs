@@ -0,0 +1,211 @@
+/// TOTP two-factor authentication.
+///
+/// Codes follow RFC 6238 with the parameters every authenticator app
+/// expects: SHA-1, 6 digits, 30-second steps, and one step of clock drift
+/// either way. A secret from setup only takes effect once a code from it has
+/// been verified. Recovery codes are stored hashed, like session tokens, and
+/// each works once.
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{
+    auth::hash_token,
+    error::{AppError, Result},
+    models::User,
+    state::AppState,
+};
+
+const ISSUER: &str = "Tapedeck";
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+/// How long the second login step may take.
+const CHALLENGE_TTL_MINUTES: u32 = 5;
+
+/// How a code was accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeKind {
+    Totp,
+    Recovery,
+}
+
+// ── Secrets ────────────────────────────────────────────────────────────────────
+
+/// A new random 160-bit secret, base32-encoded.
+pub fn new_secret() -> String {
+    match Secret::Raw(rand::random::<[u8; 20]>().to_vec()).to_encoded() {
+        Secret::Encoded(s) => s,
+        Secret::Raw(_) => unreachable!("to_encoded always encodes"),
+    }
+}
+
+fn totp(secret: &str, username: &str) -> Result<TOTP> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| AppError::Internal(format!("TOTP secret: {e}")))?;
+    // Labels are `issuer:account`, so the account name can't hold a colon.
+    // Drift is allowed for by `matching_step`, which needs to know the step.
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP_SECS,
+        bytes,
+        Some(ISSUER.to_string()),
+        username.replace(':', "_"),
+    )
+    .map_err(|e| AppError::Internal(format!("TOTP: {e}")))
+}
+
+/// The `otpauth://` URI authenticator apps scan to add the account.
+pub fn provisioning_uri(secret: &str, username: &str) -> Result<String> {
+    Ok(totp(secret, username)?.get_url())
+}
+
+/// The time step `code` is valid for right now, if any.
+fn matching_step(secret: &str, username: &str, code: &str) -> Result<Option<i64>> {
+    let totp = totp(secret, username)?;
+    let now = chrono::Utc::now().timestamp() as u64 / STEP_SECS;
+    Ok((now.saturating_sub(1)..=now + 1)
+        .find(|step| totp.check(code, step * STEP_SECS))
+        .map(|step| step as i64))
+}
+
+/// Check a TOTP code against the user's pending secret, during setup.
+pub fn check_setup_code(user: &User, code: &str) -> Result<Option<i64>> {
+    match &user.totp_secret {
+        Some(secret) => matching_step(secret, &user.username, &normalise(code)),
+        None => Ok(None),
+    }
+}
+
+/// Verify a login code: a TOTP code not used before, or an unused recovery
+/// code, which is then spent.
+pub async fn verify_code(state: &AppState, user: &User, code: &str) -> Result<Option<CodeKind>> {
+    let code = normalise(code);
+    if code.len() == DIGITS && code.bytes().all(|b| b.is_ascii_digit()) {
+        let Some(secret) = user.totp_secret.as_deref().filter(|_| user.totp_enabled) else {
+            return Ok(None);
+        };
+        let Some(step) = matching_step(secret, &user.username, &code)? else {
+            return Ok(None);
+        };
+        // Only a later step than the last accepted one counts.
+        let used = sqlx::query(
+            "UPDATE users SET totp_last_step=? \
+             WHERE id=? AND (totp_last_step IS NULL OR totp_last_step < ?)",
+        )
+        .bind(step)
+        .bind(&user.id)
+        .bind(step)
+        .execute(&state.db)
+        .await?;
+        return Ok((used.rows_affected() == 1).then_some(CodeKind::Totp));
+    }
+
+    let spent = sqlx::query(
+        "UPDATE recovery_codes SET used_at=datetime('now') \
+         WHERE user_id=? AND code_hash=? AND used_at IS NULL",
+    )
+    .bind(&user.id)
+    .bind(hash_token(&code, &state.config.secret))
+    .execute(&state.db)
+    .await?;
+    Ok((spent.rows_affected() == 1).then_some(CodeKind::Recovery))
+}
+
+/// Codes are typed by hand: ignore spaces, dashes and case.
+fn normalise(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// ── Recovery codes ─────────────────────────────────────────────────────────────
+
+/// Replace the user's recovery codes with a fresh set, returned for display.
+pub async fn replace_recovery_codes(state: &AppState, user_id: &str) -> Result<Vec<String>> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let hex = hex::encode(rand::random::<[u8; 5]>());
+            format!("{}-{}", &hex[..5], &hex[5..])
+        })
+        .collect();
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id=?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    for code in &codes {
+        sqlx::query("INSERT INTO recovery_codes (id, user_id, code_hash) VALUES (?,?,?)")
+            .bind(uuid::Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(hash_token(&normalise(code), &state.config.secret))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(codes)
+}
+
+/// Turn 2FA off and forget the secret and recovery codes.
+pub async fn disable(state: &AppState, user_id: &str) -> Result<()> {
+    let mut tx = state.db.begin().await?;
+    sqlx::query(
+        "UPDATE users SET totp_secret=NULL, totp_enabled=0, totp_last_step=NULL, \
+         updated_at=datetime('now') WHERE id=?",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id=?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM login_challenges WHERE user_id=?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// ── Login challenges ───────────────────────────────────────────────────────────
+
+/// Start the second login step for `user_id`, returning the challenge token
+/// and its expiry.
+pub async fn create_challenge(state: &AppState, user_id: &str) -> Result<(String, String)> {
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+
+    let challenge = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+    let expires_at: String = sqlx::query_scalar(
+        "INSERT INTO login_challenges (token_hash, user_id, expires_at) \
+         VALUES (?, ?, datetime('now', ?)) RETURNING expires_at",
+    )
+    .bind(hash_token(&challenge, &state.config.secret))
+    .bind(user_id)
+    .bind(format!("+{CHALLENGE_TTL_MINUTES} minutes"))
+    .fetch_one(&state.db)
+    .await?;
+    Ok((challenge, expires_at))
+}
+
+/// The user an unexpired challenge belongs to.
+pub async fn challenge_user(state: &AppState, challenge: &str) -> Result<Option<User>> {
+    Ok(sqlx::query_as(
+        "SELECT u.* FROM login_challenges c JOIN users u ON u.id = c.user_id \
+         WHERE c.token_hash = ? AND c.expires_at > datetime('now')",
+    )
+    .bind(hash_token(challenge, &state.config.secret))
+    .fetch_optional(&state.db)
+    .await?)
+}
+
+pub async fn end_challenge(state: &AppState, challenge: &str) -> Result<()> {
+    sqlx::query("DELETE FROM login_challenges WHERE token_hash = ?")
+        .bind(hash_token(challenge, &state.config.secret))
+        .execute(&state.db)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+/// Watch rules: saved searches evaluated against the programme cache.
+///
+/// After each hourly cache refresh the whole cache is listed once per media
+/// type and every enabled rule is matched against it in Rust. Matching
+/// programmes that are neither queued nor in `download_history` are queued
+/// with the rule's quality and subtitle preferences.
+use std::collections::HashSet;
+
+use anyhow::Context;
+use regex::{Regex, RegexBuilder};
+use tracing::{info, warn};
+
+use crate::{
+    iplayer::{self, SearchOptions},
+    models::{AddQueueItemRequest, SearchResult, WatchRule, WatchRuleMatch, WebhookEvent, WsEvent},
+    queue,
+    state::AppState,
+};
+
+// ── Matching ───────────────────────────────────────────────────────────────────
+
+/// A rule's filters, compiled once per evaluation.
+#[derive(Debug)]
+pub struct Matcher {
+    query: Regex,
+    channel: Option<String>,
+    exclude: Vec<String>,
+}
+
+impl Matcher {
+    /// Compile a rule. Plain-text queries are matched literally; all matching
+    /// is case-insensitive.
+    pub fn new(
+        query: &str,
+        is_regex: bool,
+        channel: Option<&str>,
+        exclude: Option<&str>,
+    ) -> Result<Self, regex::Error> {
+        let pattern = if is_regex {
+            query.to_string()
+        } else {
+            regex::escape(query.trim())
+        };
+        let query = RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .size_limit(1 << 20)
+            .build()?;
+        let channel = channel
+            .map(|c| c.trim().to_lowercase())
+            .filter(|c| !c.is_empty());
+        let exclude = exclude
+            .unwrap_or_default()
+            .split(',')
+            .map(|t| t.trim().to_lowercase())
+            .filter(|t| !t.is_empty())
+            .collect();
+        Ok(Matcher {
+            query,
+            channel,
+            exclude,
+        })
+    }
+
+    pub fn from_rule(rule: &WatchRule) -> Result<Self, regex::Error> {
+        Matcher::new(
+            &rule.query,
+            rule.is_regex,
+            rule.channel.as_deref(),
+            rule.exclude.as_deref(),
+        )
+    }
+
+    pub fn matches(&self, programme: &SearchResult) -> bool {
+        if let Some(want) = &self.channel {
+            let channel = programme.channel.as_deref().unwrap_or_default();
+            if !channel.to_lowercase().contains(want.as_str()) {
+                return false;
+            }
+        }
+
+        let fields = [
+            Some(programme.title.as_str()),
+            programme.series.as_deref(),
+            programme.episode.as_deref(),
+            programme.description.as_deref(),
+        ];
+        if !fields.iter().flatten().any(|f| self.query.is_match(f)) {
+            return false;
+        }
+
+        let text = fields
+            .iter()
+            .flatten()
+            .map(|f| f.to_lowercase())
+            .collect::<Vec<_>>()
+            .join("\n");
+        !self.exclude.iter().any(|term| text.contains(term.as_str()))
+    }
+}
+
+// ── Evaluation ─────────────────────────────────────────────────────────────────
+
+/// Every programme in the local cache for `media_type`, one entry per PID.
+pub async fn list_cache(state: &AppState, media_type: &str) -> anyhow::Result<Vec<SearchResult>> {
+    let settings = state.settings.current();
+    let opts = SearchOptions {
+        query: ".*",
+        media_type,
+        get_iplayer_path: &settings.get_iplayer_path,
+        cache_dir: &state.config.iplayer_cache_dir,
+        proxy: settings.proxy.as_deref(),
+    };
+    let mut seen = HashSet::new();
+    let programmes = iplayer::search_local_cache(opts)
+        .await
+        .context("list programme cache")?;
+    Ok(programmes
+        .into_iter()
+        .filter(|p| seen.insert(p.pid.clone()))
+        .collect())
+}
+
+/// What `matcher` would match in the cache right now, without queueing.
+pub async fn preview(
+    state: &AppState,
+    matcher: &Matcher,
+    media_type: &str,
+) -> anyhow::Result<Vec<WatchRuleMatch>> {
+    let mut matches = Vec::new();
+    for programme in list_cache(state, media_type).await? {
+        if matcher.matches(&programme) {
+            let seen = queue::is_known_pid(&state.db, &programme.pid).await?;
+            matches.push(WatchRuleMatch { programme, seen });
+        }
+    }
+    Ok(matches)
+}
+
+/// Evaluate every enabled rule and queue what they match. Called after each
+/// cache refresh.
+pub async fn evaluate_all(state: &AppState) {
+    let rules: Vec<WatchRule> =
+        match sqlx::query_as("SELECT * FROM watch_rules WHERE enabled=1 ORDER BY created_at")
+            .fetch_all(&state.db)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("Watch rules: failed to load rules: {e}");
+                return;
+            }
+        };
+    if rules.is_empty() {
+        return;
+    }
+
+    info!("Evaluating {} watch rule(s)", rules.len());
+    for media_type in ["tv", "radio"] {
+        let rules: Vec<&WatchRule> = rules
+            .iter()
+            .filter(|r| r.media_type == media_type)
+            .collect();
+        if rules.is_empty() {
+            continue;
+        }
+        let programmes = match list_cache(state, media_type).await {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("Watch rules: {e:#}");
+                continue;
+            }
+        };
+        for rule in rules {
+            let result = evaluate(state, rule, &programmes).await;
+            let now = chrono::Utc::now().to_rfc3339();
+            let _ = sqlx::query("UPDATE watch_rules SET last_run_at=?, last_error=? WHERE id=?")
+                .bind(&now)
+                .bind(result.as_ref().err().map(|e| format!("{e:#}")))
+                .bind(&rule.id)
+                .execute(&state.db)
+                .await;
+            if let Err(e) = result {
+                warn!("Watch rule {} ({}) failed: {e:#}", rule.id, rule.name);
+            }
+        }
+    }
+}
+
+/// Queue the programmes `rule` matches that have never been seen before.
+async fn evaluate(
+    state: &AppState,
+    rule: &WatchRule,
+    programmes: &[SearchResult],
+) -> anyhow::Result<()> {
+    let matcher = Matcher::from_rule(rule).context("invalid query")?;
+    let settings = state.settings.current();
+    let source = format!("rule:{}", rule.id);
+    let mut pids = Vec::new();
+
+    for programme in programmes.iter().filter(|p| matcher.matches(p)) {
+        if queue::is_known_pid(&state.db, &programme.pid).await? {
+            continue;
+        }
+        let req = AddQueueItemRequest {
+            quality: rule.quality.clone(),
+            subtitles: rule.subtitles,
+            ..AddQueueItemRequest::from_search_result(programme)
+        };
+        let item = queue::insert_new_item(&state.db, &settings, &req, &rule.user_id, &source)
+            .await
+            .with_context(|| format!("queue {}", programme.pid))?;
+        let Some(item) = item else {
+            continue;
+        };
+        pids.push(item.pid.clone());
+        state
+            .webhooks
+            .notify(WebhookEvent::ItemAdded, &item.id)
+            .await;
+        let _ = state.events.send(WsEvent::ItemAdded {
+            item: Box::new(item),
+        });
+    }
+
+    if !pids.is_empty() {
+        info!(
+            "Watch rule {} ({}) queued {} programme(s)",
+            rule.id,
+            rule.name,
+            pids.len()
+        );
+        state.queue.wake();
+        let _ = state.events.send(WsEvent::WatchRuleMatched {
+            rule_id: rule.id.clone(),
+            name: rule.name.clone(),
+            pids,
+        });
+    }
+    Ok(())
+}
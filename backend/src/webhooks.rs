@@ -0,0 +1,299 @@
+/// Outgoing webhooks.
+///
+/// Where a queue item is added, starts downloading, or is done or has failed
+/// for good, the queue calls `notify`, which records a delivery for every
+/// enabled webhook that wants the event before sending it. A delivery is a
+/// JSON POST:
+///
+/// ```json
+/// { "event": "download_done", "occurred_at": "…", "item": { … } }
+/// ```
+///
+/// with `X-Tapedeck-Event`, `X-Tapedeck-Delivery` and
+/// `X-Tapedeck-Signature: sha256=<hex>` headers, the signature being the
+/// HMAC-SHA256 of the body under the webhook's secret.
+///
+/// Anything but a 2xx response is retried after each of `RETRY_DELAYS`,
+/// with the same body, before the delivery is marked failed; disabling the
+/// webhook fails its waiting deliveries instead. Every delivery and the
+/// outcome of its latest attempt is kept in `webhook_deliveries`; those
+/// still waiting are picked up again at startup.
+use std::time::Duration;
+
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    auth,
+    db::Db,
+    models::{DeliveryStatus, QueueItem, Webhook, WebhookDelivery, WebhookEvent},
+};
+
+/// Seconds to wait before each retry of a failed delivery.
+const RETRY_DELAYS: &[u64] = &[10, 60, 300, 1800, 7200];
+
+/// How long a webhook has to respond.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Finished deliveries older than this are deleted at startup.
+const KEEP_DAYS: i64 = 30;
+
+#[derive(Debug, Clone)]
+pub struct Webhooks {
+    db: Db,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    /// Start sending to webhooks, carrying on with deliveries that were
+    /// waiting when the service last stopped.
+    pub fn start(db: Db) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .user_agent(concat!("tapedeck/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("HTTP client configuration is valid");
+        let webhooks = Webhooks { db, client };
+        tokio::spawn(webhooks.clone().resume());
+        webhooks
+    }
+
+    /// Send `webhook` a `ping`, whatever events it subscribes to, returning
+    /// the new delivery.
+    pub async fn ping(&self, webhook: &Webhook) -> sqlx::Result<WebhookDelivery> {
+        let payload = json!({
+            "event": WebhookEvent::Ping,
+            "occurred_at": chrono::Utc::now().to_rfc3339(),
+            "webhook": { "id": webhook.id, "name": webhook.name },
+        });
+        let id = self
+            .enqueue(&webhook.id, WebhookEvent::Ping, &payload.to_string())
+            .await?;
+        let delivery = sqlx::query_as("SELECT * FROM webhook_deliveries WHERE id=?")
+            .bind(id)
+            .fetch_one(&self.db)
+            .await?;
+        self.spawn_delivery(id, Duration::ZERO);
+        Ok(delivery)
+    }
+
+    // ── Events ─────────────────────────────────────────────────────────────────
+
+    /// `kind` has just happened to queue item `id`: send it, as it is now, to
+    /// each webhook that wants the event.
+    pub async fn notify(&self, kind: WebhookEvent, id: &str) {
+        if let Err(e) = self.dispatch(kind, id).await {
+            warn!("Could not queue webhook deliveries for {id}: {e}");
+        }
+    }
+
+    async fn dispatch(&self, kind: WebhookEvent, id: &str) -> sqlx::Result<()> {
+        let item: Option<QueueItem> = sqlx::query_as("SELECT * FROM queue_items WHERE id=?")
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await?;
+        let Some(item) = item else {
+            return Ok(());
+        };
+
+        let webhooks: Vec<Webhook> = sqlx::query_as(
+            "SELECT * FROM webhooks \
+             WHERE enabled AND EXISTS (SELECT 1 FROM json_each(events) WHERE value=?)",
+        )
+        .bind(kind)
+        .fetch_all(&self.db)
+        .await?;
+        if webhooks.is_empty() {
+            return Ok(());
+        }
+
+        let payload = json!({
+            "event": kind,
+            "occurred_at": chrono::Utc::now().to_rfc3339(),
+            "item": item,
+        })
+        .to_string();
+        for webhook in webhooks {
+            let id = self.enqueue(&webhook.id, kind, &payload).await?;
+            self.spawn_delivery(id, Duration::ZERO);
+        }
+        Ok(())
+    }
+
+    async fn enqueue(
+        &self,
+        webhook_id: &str,
+        event: WebhookEvent,
+        payload: &str,
+    ) -> sqlx::Result<i64> {
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO webhook_deliveries (webhook_id, event, payload) VALUES (?, ?, ?) \
+             RETURNING id",
+        )
+        .bind(webhook_id)
+        .bind(event)
+        .bind(payload)
+        .fetch_one(&self.db)
+        .await?;
+        Ok(id)
+    }
+
+    // ── Delivery ───────────────────────────────────────────────────────────────
+
+    fn spawn_delivery(&self, id: i64, delay: Duration) {
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            webhooks.deliver(id).await;
+        });
+    }
+
+    /// Attempt delivery `id` until an attempt succeeds or the retries run
+    /// out, recording each outcome.
+    async fn deliver(&self, id: i64) {
+        loop {
+            let delivery: Option<WebhookDelivery> =
+                sqlx::query_as("SELECT * FROM webhook_deliveries WHERE id=? AND status='pending'")
+                    .bind(id)
+                    .fetch_optional(&self.db)
+                    .await
+                    .unwrap_or(None);
+            let Some(delivery) = delivery else {
+                return;
+            };
+            let webhook: Option<Webhook> = sqlx::query_as("SELECT * FROM webhooks WHERE id=?")
+                .bind(&delivery.webhook_id)
+                .fetch_optional(&self.db)
+                .await
+                .unwrap_or(None);
+            let Some(webhook) = webhook else {
+                return;
+            };
+            // Test pings go out whether or not the webhook is enabled.
+            if !webhook.enabled && delivery.event != WebhookEvent::Ping {
+                if let Err(e) = sqlx::query(
+                    "UPDATE webhook_deliveries \
+                     SET status=?, error='webhook is disabled', next_attempt_at=NULL WHERE id=?",
+                )
+                .bind(DeliveryStatus::Failed)
+                .bind(id)
+                .execute(&self.db)
+                .await
+                {
+                    warn!("Could not record webhook delivery {id}: {e}");
+                }
+                return;
+            }
+
+            let attempts = delivery.attempts + 1;
+            let (response_status, error) = match self.send(&webhook, &delivery).await {
+                Ok(code) if (200..300).contains(&code) => (Some(code), None),
+                Ok(code) => (Some(code), Some(format!("HTTP {code}"))),
+                Err(e) => (None, Some(e)),
+            };
+            let now = chrono::Utc::now();
+            let retry_in = error
+                .as_ref()
+                .and_then(|_| RETRY_DELAYS.get(delivery.attempts as usize))
+                .map(|secs| Duration::from_secs(*secs));
+            let status = match (&error, retry_in) {
+                (None, _) => DeliveryStatus::Delivered,
+                (Some(_), Some(_)) => DeliveryStatus::Pending,
+                (Some(_), None) => DeliveryStatus::Failed,
+            };
+            let next_attempt_at = retry_in
+                .and_then(|d| chrono::Duration::from_std(d).ok())
+                .map(|d| (now + d).to_rfc3339());
+            if let Some(error) = &error {
+                warn!(
+                    "Webhook {} ({}) attempt {attempts} failed: {error}",
+                    webhook.name, webhook.url
+                );
+            }
+
+            if let Err(e) = sqlx::query(
+                "UPDATE webhook_deliveries \
+                 SET status=?, attempts=?, response_status=?, error=?, last_attempt_at=?, \
+                     next_attempt_at=? \
+                 WHERE id=?",
+            )
+            .bind(status)
+            .bind(attempts)
+            .bind(response_status.map(i64::from))
+            .bind(&error)
+            .bind(now.to_rfc3339())
+            .bind(&next_attempt_at)
+            .bind(id)
+            .execute(&self.db)
+            .await
+            {
+                warn!("Could not record webhook delivery {id}: {e}");
+                return;
+            }
+
+            match retry_in {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return,
+            }
+        }
+    }
+
+    /// POST the delivery, returning the response status or why there was
+    /// none.
+    async fn send(&self, webhook: &Webhook, delivery: &WebhookDelivery) -> Result<u16, String> {
+        // The same HMAC-SHA256 as session token hashes, keyed by the
+        // webhook's secret.
+        let signature = auth::hash_token(&delivery.payload, &webhook.secret);
+        let event = serde_json::to_value(delivery.event).unwrap_or_default();
+        let response = self
+            .client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Tapedeck-Event", event.as_str().unwrap_or_default())
+            .header("X-Tapedeck-Delivery", delivery.id.to_string())
+            .header("X-Tapedeck-Signature", format!("sha256={signature}"))
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|e| format!("{e:#}"))?;
+        Ok(response.status().as_u16())
+    }
+
+    /// On startup: prune old deliveries and carry on with waiting ones.
+    async fn resume(self) {
+        if let Err(e) = sqlx::query(
+            "DELETE FROM webhook_deliveries \
+             WHERE status != 'pending' AND created_at < datetime('now', ?)",
+        )
+        .bind(format!("-{KEEP_DAYS} days"))
+        .execute(&self.db)
+        .await
+        {
+            warn!("Could not prune webhook deliveries: {e}");
+        }
+
+        let waiting: Vec<(i64, Option<String>)> = match sqlx::query_as(
+            "SELECT id, next_attempt_at FROM webhook_deliveries WHERE status='pending'",
+        )
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("Could not load waiting webhook deliveries: {e}");
+                return;
+            }
+        };
+        if !waiting.is_empty() {
+            info!("Resuming {} webhook delivery(s)", waiting.len());
+        }
+        let now = chrono::Utc::now();
+        for (id, next_attempt_at) in waiting {
+            let delay = next_attempt_at
+                .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+                .and_then(|at| (at.with_timezone(&chrono::Utc) - now).to_std().ok())
+                .unwrap_or_default();
+            self.spawn_delivery(id, delay);
+        }
+    }
+}
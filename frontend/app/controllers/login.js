@@ -10,6 +10,9 @@ export default class LoginController extends Controller {
 
   @tracked username = '';
   @tracked password = '';
+  @tracked code = '';
+  /** Set once the password is accepted and a 2FA code is needed. */
+  @tracked challenge = null;
   @tracked error = null;
   @tracked loading = false;
 
@@ -19,7 +22,17 @@ export default class LoginController extends Controller {
     this.error = null;
     this.loading = true;
     try {
-      await this.api.login(this.username, this.password);
+      if (this.challenge) {
+        await this.api.loginTwoFactor(this.challenge, this.code);
+      } else {
+        const result = await this.api.login(this.username, this.password);
+        if (result.two_factor_required) {
+          this.challenge = result.challenge;
+          return;
+        }
+      }
+      this.challenge = null;
+      this.code = '';
       this.socket.connect();
       this.router.transitionTo('queue');
     } catch (e) {
@@ -38,4 +51,16 @@ export default class LoginController extends Controller {
   updatePassword(event) {
     this.password = event.target.value;
   }
+
+  @action
+  updateCode(event) {
+    this.code = event.target.value;
+  }
+
+  @action
+  cancelTwoFactor() {
+    this.challenge = null;
+    this.code = '';
+    this.error = null;
+  }
 }
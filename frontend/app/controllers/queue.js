@@ -3,6 +3,8 @@ import { tracked } from '@glimmer/tracking';
 import { action } from '@ember/object';
 import { service } from '@ember/service';
 
+const MAX_LOG_LINES = 500;
+
 export default class QueueController extends Controller {
   @service api;
   @service socket;
@@ -11,6 +13,12 @@ export default class QueueController extends Controller {
   @tracked items = [];
   @tracked filter = 'all';
   @tracked error = null;
+  /** The item whose log is open, and the lines received so far. */
+  @tracked logItemId = null;
+  @tracked logLines = [];
+  /** The item whose post-processing steps are open, and those steps. */
+  @tracked stepsItemId = null;
+  @tracked steps = [];
 
   #pollTimer = null;
 
@@ -25,6 +33,8 @@ export default class QueueController extends Controller {
     this.socket.on('error', this.#onError);
     this.socket.on('item_added', this.#onItemAdded);
     this.socket.on('item_removed', this.#onItemRemoved);
+    this.socket.on('log_line', this.#onLogLine);
+    this.socket.on('step_change', this.#onStepChange);
     this.#pollTimer = setInterval(() => this.refresh(), 5000);
   }
 
@@ -37,6 +47,10 @@ export default class QueueController extends Controller {
     this.socket.off('error', this.#onError);
     this.socket.off('item_added', this.#onItemAdded);
     this.socket.off('item_removed', this.#onItemRemoved);
+    this.socket.off('log_line', this.#onLogLine);
+    this.socket.off('step_change', this.#onStepChange);
+    this.closeLog();
+    this.closeSteps();
   }
 
   // ── WS handlers ───────────────────────────────────────────────────────────
@@ -47,6 +61,7 @@ export default class QueueController extends Controller {
       item.progress = event.progress;
       item.speed = event.speed;
       item.eta = event.eta;
+      item.elapsed = event.elapsed;
       // Trigger Glimmer reactivity by reassigning
       this.items = [...this.items];
     }
@@ -57,8 +72,9 @@ export default class QueueController extends Controller {
     if (item) {
       item.status = event.status;
       // Clear any stale error message when a new attempt starts or finishes successfully
-      if (event.status === 'downloading' || event.status === 'done') {
+      if (['downloading', 'processing', 'done'].includes(event.status)) {
         item.error = null;
+        item.error_kind = null;
       }
       this.items = [...this.items];
     }
@@ -68,6 +84,7 @@ export default class QueueController extends Controller {
     const item = this.items.find((i) => i.id === event.id);
     if (item) {
       item.error = event.message;
+      item.error_kind = event.kind;
       this.items = [...this.items];
     }
   };
@@ -80,6 +97,17 @@ export default class QueueController extends Controller {
     this.items = this.items.filter((i) => i.id !== event.id);
   };
 
+  #onLogLine = (event) => {
+    if (event.id !== this.logItemId) return;
+    // Keep the panel bounded on long downloads
+    this.logLines = [...this.logLines, event.line].slice(-MAX_LOG_LINES);
+  };
+
+  #onStepChange = (event) => {
+    if (event.id !== this.stepsItemId) return;
+    this.steps = this.steps.map((s) => (s.id === event.step.id ? event.step : s));
+  };
+
   // ── Computed ──────────────────────────────────────────────────────────────
 
   get filteredItems() {
@@ -87,8 +115,19 @@ export default class QueueController extends Controller {
     return this.items.filter((i) => i.status === this.filter);
   }
 
+  get logText() {
+    return this.logLines.join('\n');
+  }
+
   get statusCounts() {
-    const counts = { all: this.items.length, queued: 0, downloading: 0, done: 0, failed: 0 };
+    const counts = {
+      all: this.items.length,
+      queued: 0,
+      downloading: 0,
+      processing: 0,
+      done: 0,
+      failed: 0,
+    };
     for (const item of this.items) {
       counts[item.status] = (counts[item.status] ?? 0) + 1;
     }
@@ -123,6 +162,56 @@ export default class QueueController extends Controller {
     }
   }
 
+  @action
+  async retryStep(id, stepId) {
+    try {
+      const updated = await this.api.retryStep(id, stepId);
+      this.items = this.items.map((i) => (i.id === id ? updated : i));
+      this.steps = await this.api.fetchSteps(id);
+    } catch (e) {
+      this.error = e.message;
+    }
+  }
+
+  @action
+  async toggleSteps(id) {
+    const wasOpen = this.stepsItemId === id;
+    this.closeSteps();
+    if (wasOpen) return;
+    this.stepsItemId = id;
+    try {
+      this.steps = await this.api.fetchSteps(id);
+    } catch (e) {
+      this.error = e.message;
+    }
+  }
+
+  @action
+  closeSteps() {
+    this.stepsItemId = null;
+    this.steps = [];
+  }
+
+  @action
+  toggleLog(id) {
+    const wasOpen = this.logItemId === id;
+    this.closeLog();
+    if (!wasOpen) {
+      // The server replies with the most recent lines, then streams new ones
+      this.logItemId = id;
+      this.socket.tail(id);
+    }
+  }
+
+  @action
+  closeLog() {
+    if (this.logItemId) {
+      this.socket.untail(this.logItemId);
+    }
+    this.logItemId = null;
+    this.logLines = [];
+  }
+
   @action
   async refresh() {
     try {
@@ -156,6 +156,7 @@ export default class SearchController extends Controller {
         episode: result.episode,
         channel: result.channel,
         media_type: this.type,
+        duration_secs: result.duration_secs ?? null,
         // Fall back to the parent show thumbnail when the episode has none
         thumbnail_url: result.thumbnail_url ?? this.selectedShow?.thumbnail_url ?? null,
       });
@@ -10,13 +10,19 @@ export default class SettingsController extends Controller {
   @tracked saved = false;
   @tracked error = null;
   @tracked saving = false;
+  // Per-key validation messages from the last failed save
+  @tracked fieldErrors = {};
 
   // Editable map is populated from model.map via setupController in the route
   @tracked map = {};
 
   @action
   updateField(key, event) {
-    this.map = { ...this.map, [key]: event.target.value };
+    const { type, checked, value } = event.target;
+    this.map = {
+      ...this.map,
+      [key]: type === 'checkbox' ? String(checked) : value,
+    };
   }
 
   @action
@@ -24,12 +30,16 @@ export default class SettingsController extends Controller {
     event.preventDefault();
     this.saving = true;
     this.error = null;
+    this.fieldErrors = {};
     try {
       await this.api.bulkUpdateSettings(this.map);
       this.saved = true;
       setTimeout(() => (this.saved = false), 3000);
     } catch (e) {
-      this.error = e.message ?? 'Failed to save settings';
+      this.fieldErrors = e.fields ?? {};
+      this.error = e.fields
+        ? 'Some settings are invalid — see below.'
+        : (e.message ?? 'Failed to save settings');
     } finally {
       this.saving = false;
     }
@@ -40,4 +50,13 @@ export default class SettingsController extends Controller {
     this.api.logout();
     this.router.transitionTo('login');
   }
+
+  @action
+  async logoutAll() {
+    try {
+      await this.api.logoutAll();
+    } finally {
+      this.router.transitionTo('login');
+    }
+  }
 }
@@ -0,0 +1,23 @@
+import { helper } from '@ember/component/helper';
+
+const REASONS = {
+  geo_blocked: 'Geo-blocked — only available from the UK (try a proxy)',
+  unavailable: 'Programme unavailable or expired',
+  no_quality: 'None of the requested qualities is available',
+  ffmpeg_missing: 'ffmpeg not found — check the ffmpeg path setting',
+  get_iplayer_missing: 'get_iplayer not found — check the get_iplayer path setting',
+  network: 'Network error',
+  disk_full: 'Output disk is full',
+  cancelled: 'Cancelled',
+  post_processing: 'Post-processing failed — retry the failed step',
+};
+
+/**
+ * {{error-reason kind}}
+ *
+ * A short, readable reason for a queue item's `error_kind`, or null for
+ * unknown failures (the raw error text is shown instead).
+ */
+export default helper(function errorReason([kind]) {
+  return REASONS[kind] ?? null;
+});
@@ -0,0 +1,31 @@
+import { helper } from '@ember/component/helper';
+
+function formatSize(bytes) {
+  const units = ['B', 'KB', 'MB', 'GB', 'TB'];
+  let size = bytes;
+  let unit = 0;
+  while (size >= 1024 && unit < units.length - 1) {
+    size /= 1024;
+    unit += 1;
+  }
+  return `${size.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+}
+
+/**
+ * {{media-summary item}}
+ *
+ * One line describing a finished download as probed by ffprobe, e.g.
+ * "1280×720 · h264 / aac · 2 ch · 812.4 MB · subtitles", or null if nothing
+ * is known about it yet.
+ */
+export default helper(function mediaSummary([item]) {
+  if (!item) return null;
+  const parts = [];
+  if (item.width && item.height) parts.push(`${item.width}×${item.height}`);
+  const codecs = [item.video_codec, item.audio_codec].filter(Boolean);
+  if (codecs.length) parts.push(codecs.join(' / '));
+  if (item.audio_channels) parts.push(`${item.audio_channels} ch`);
+  if (item.file_size) parts.push(formatSize(item.file_size));
+  if (item.embedded_subtitles || item.sidecar_subtitles) parts.push('subtitles');
+  return parts.length ? parts.join(' · ') : null;
+});
@@ -6,9 +6,12 @@ export default class ApplicationRoute extends Route {
   @service router;
   @service socket;
 
-  beforeModel() {
+  async beforeModel() {
     // Restore auth state from localStorage
     this.api.restore();
+    if (!this.api.isAuthenticated) {
+      await this.api.detectProxyAuth();
+    }
   }
 
   afterModel() {
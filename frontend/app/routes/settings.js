@@ -8,18 +8,34 @@ export default class SettingsRoute extends Route {
   beforeModel() {
     if (!this.api.isAuthenticated) {
       this.router.transitionTo('login');
+    } else if (!this.api.isAdmin) {
+      this.router.transitionTo('queue');
     }
   }
 
   async model() {
-    const settings = await this.api.fetchSettings();
+    const [settings, schema] = await Promise.all([
+      this.api.fetchSettings(),
+      this.api.fetchSettingsSchema(),
+    ]);
     // Convert array of {key, value} to a plain object for easy binding
     const map = Object.fromEntries(settings.map(({ key, value }) => [key, value]));
-    return { settings, map };
+    // Group the schema into sections, preserving the server's ordering
+    const sections = [];
+    for (const field of schema) {
+      let section = sections.find((s) => s.title === field.section);
+      if (!section) {
+        section = { title: field.section, fields: [] };
+        sections.push(section);
+      }
+      section.fields.push(field);
+    }
+    return { settings, map, sections };
   }
 
   setupController(controller, model) {
     super.setupController(controller, model);
     controller.map = { ...model.map };
+    controller.fieldErrors = {};
   }
 }
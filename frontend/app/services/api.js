@@ -4,6 +4,10 @@ import config from 'tapedeck-ui/config/environment';
 
 const TOKEN_KEY = 'tapedeck_token';
 const USER_KEY = 'tapedeck_user';
+const EXPIRES_KEY = 'tapedeck_expires_at';
+
+// Refresh the session on load once less than this much of it is left.
+const REFRESH_WITHIN_MS = 24 * 60 * 60 * 1000;
 
 /**
  * Centralized HTTP API client.
@@ -17,9 +21,15 @@ const USER_KEY = 'tapedeck_user';
 export default class ApiService extends Service {
   @tracked token = null;
   @tracked currentUser = null;
+  /** Logged in by a forward-auth proxy header rather than a token. */
+  @tracked proxyAuth = false;
 
   get isAuthenticated() {
-    return !!this.token;
+    return !!this.token || this.proxyAuth;
+  }
+
+  get isAdmin() {
+    return this.currentUser?.role === 'admin';
   }
 
   get base() {
@@ -38,20 +48,42 @@ export default class ApiService extends Service {
         this.currentUser = null;
       }
     }
+    const expiresAt = Date.parse(
+      `${localStorage.getItem(EXPIRES_KEY) ?? ''}Z`.replace(' ', 'T'),
+    );
+    if (this.token && expiresAt - Date.now() < REFRESH_WITHIN_MS) {
+      this.refreshSession().catch(() => {});
+    }
   }
 
-  persistSession(token, user) {
+  persistSession(token, user, expiresAt) {
     this.token = token;
     this.currentUser = user;
     localStorage.setItem(TOKEN_KEY, token);
     localStorage.setItem(USER_KEY, JSON.stringify(user));
+    localStorage.setItem(EXPIRES_KEY, expiresAt);
+  }
+
+  /**
+   * Behind a forward-auth proxy the server knows the user without a token.
+   * Ask it, so the login page can be skipped.
+   */
+  async detectProxyAuth() {
+    try {
+      this.currentUser = await this.get('/users/me');
+      this.proxyAuth = true;
+    } catch {
+      this.proxyAuth = false;
+    }
   }
 
   clearSession() {
     this.token = null;
+    this.proxyAuth = false;
     this.currentUser = null;
     localStorage.removeItem(TOKEN_KEY);
     localStorage.removeItem(USER_KEY);
+    localStorage.removeItem(EXPIRES_KEY);
   }
 
   // ── Core fetch wrapper ────────────────────────────────────────────────────
@@ -77,11 +109,14 @@ export default class ApiService extends Service {
 
     if (!res.ok) {
       const err = await res.json().catch(() => ({ error: res.statusText }));
-      throw new ApiError(res.status, err.error ?? res.statusText);
+      throw new ApiError(res.status, err.error ?? res.statusText, err.fields);
     }
 
     if (res.status === 204 || res.status === 202) return null;
 
+    if (!res.headers.get('Content-Type')?.includes('application/json')) {
+      return res.text();
+    }
     return res.json();
   }
 
@@ -112,17 +147,53 @@ export default class ApiService extends Service {
       username,
       password,
     });
-    this.persistSession(result.token, {
-      id: result.user_id,
-      username: result.username,
+    // With 2FA on, the caller must finish with loginTwoFactor().
+    if (!result.two_factor_required) {
+      this.#storeLogin(result);
+    }
+    return result;
+  }
+
+  async loginTwoFactor(challenge, code) {
+    const result = await this.request('POST', '/auth/login/2fa', {
+      challenge,
+      code,
     });
+    this.#storeLogin(result);
+    return result;
+  }
+
+  /** Swap the current token for a fresh one with a new expiry. */
+  async refreshSession() {
+    const result = await this.post('/auth/refresh');
+    this.#storeLogin(result);
     return result;
   }
 
   logout() {
+    // Revoke the token server-side, but don't hold the UI up waiting for it.
+    this.post('/auth/logout').catch(() => {});
+    this.clearSession();
+  }
+
+  /** Sign out of every browser and device, including this one. */
+  async logoutAll() {
+    await this.post('/auth/logout-all');
     this.clearSession();
   }
 
+  #storeLogin(result) {
+    this.persistSession(
+      result.token,
+      {
+        id: result.user_id,
+        username: result.username,
+        role: result.role,
+      },
+      result.expires_at,
+    );
+  }
+
   // ── Queue ─────────────────────────────────────────────────────────────────
 
   fetchQueue(params = {}) {
@@ -142,6 +213,24 @@ export default class ApiService extends Service {
     return this.post(`/queue/${id}/retry`);
   }
 
+  fetchAttempts(id) {
+    return this.get(`/queue/${id}/attempts`);
+  }
+
+  fetchSteps(id) {
+    return this.get(`/queue/${id}/steps`);
+  }
+
+  /** Re-run a failed post-processing step and the steps after it. */
+  retryStep(id, stepId) {
+    return this.post(`/queue/${id}/steps/${stepId}/retry`);
+  }
+
+  /** The item's get_iplayer log as plain text; `tail` limits it to the last lines. */
+  fetchLog(id, tail) {
+    return this.get(`/queue/${id}/log${tail ? `?tail=${tail}` : ''}`);
+  }
+
   reorderQueue(entries) {
     return this.post('/queue/reorder', entries);
   }
@@ -162,12 +251,114 @@ export default class ApiService extends Service {
     return this.get(`/search/episodes?${qs}`);
   }
 
+  // ── Library ───────────────────────────────────────────────────────────────
+
+  fetchLibrary(params = {}) {
+    const qs = new URLSearchParams(params).toString();
+    return this.get(`/library${qs ? '?' + qs : ''}`);
+  }
+
+  deleteLibraryFile(id) {
+    return this.delete(`/library/files/${id}`);
+  }
+
+  scanLibrary() {
+    return this.post('/library/scan');
+  }
+
+  /**
+   * URL for a `<video>`/`<audio>` source or download link. It carries a
+   * media token that only opens this file and expires after a few hours, so
+   * fetch a new one rather than keeping it.
+   */
+  async libraryStreamUrl(id, { download = false } = {}) {
+    const { url } = await this.post(`/library/files/${id}/link`);
+    return `${this.base}${url}${download ? '&download=true' : ''}`;
+  }
+
+  // ── Subscriptions ─────────────────────────────────────────────────────────
+
+  fetchSubscriptions() {
+    return this.get('/subscriptions');
+  }
+
+  subscribe(payload) {
+    return this.post('/subscriptions', payload);
+  }
+
+  updateSubscription(id, changes) {
+    return this.patch(`/subscriptions/${id}`, changes);
+  }
+
+  unsubscribe(id) {
+    return this.delete(`/subscriptions/${id}`);
+  }
+
+  checkSubscription(id) {
+    return this.post(`/subscriptions/${id}/check`);
+  }
+
+  // ── Watch rules ───────────────────────────────────────────────────────────
+
+  fetchWatchRules() {
+    return this.get('/watch-rules');
+  }
+
+  createWatchRule(rule) {
+    return this.post('/watch-rules', rule);
+  }
+
+  updateWatchRule(id, changes) {
+    return this.patch(`/watch-rules/${id}`, changes);
+  }
+
+  deleteWatchRule(id) {
+    return this.delete(`/watch-rules/${id}`);
+  }
+
+  previewWatchRule(rule) {
+    return this.post('/watch-rules/preview', rule);
+  }
+
+  // ── Webhooks (admin only) ─────────────────────────────────────────────────
+
+  fetchWebhooks() {
+    return this.get('/webhooks');
+  }
+
+  /** Resolves with the new webhook; its `secret` field is only returned once. */
+  createWebhook(webhook) {
+    return this.post('/webhooks', webhook);
+  }
+
+  updateWebhook(id, changes) {
+    return this.patch(`/webhooks/${id}`, changes);
+  }
+
+  deleteWebhook(id) {
+    return this.delete(`/webhooks/${id}`);
+  }
+
+  /** Delivery log, newest first. */
+  fetchWebhookDeliveries(id, params = {}) {
+    const qs = new URLSearchParams(params).toString();
+    return this.get(`/webhooks/${id}/deliveries${qs ? `?${qs}` : ''}`);
+  }
+
+  testWebhook(id) {
+    return this.post(`/webhooks/${id}/test`);
+  }
+
   // ── Settings ──────────────────────────────────────────────────────────────
 
   fetchSettings() {
     return this.get('/settings');
   }
 
+  fetchSettingsSchema() {
+    return this.get('/settings/schema');
+  }
+
   updateSetting(key, value) {
     return this.put(`/settings/${key}`, { value });
   }
@@ -176,14 +367,76 @@ export default class ApiService extends Service {
     return this.patch('/settings', map);
   }
 
+  // ── Two-factor authentication ─────────────────────────────────────────────
+
+  fetchTwoFactor() {
+    return this.get('/auth/2fa');
+  }
+
+  /** Resolves with `{ secret, otpauth_uri }` for the authenticator app. */
+  setupTwoFactor(password) {
+    return this.post('/auth/2fa/setup', { password });
+  }
+
+  /** Resolves with `{ recovery_codes }`; they are only returned once. */
+  enableTwoFactor(code) {
+    return this.post('/auth/2fa/enable', { code });
+  }
+
+  disableTwoFactor(password) {
+    return this.post('/auth/2fa/disable', { password });
+  }
+
+  regenerateRecoveryCodes(password) {
+    return this.post('/auth/2fa/recovery-codes', { password });
+  }
+
+  /** Admin only: clear 2FA for a user who has lost their authenticator. */
+  resetUserTwoFactor(id) {
+    return this.delete(`/users/${id}/2fa`);
+  }
+
+  /** Admin only: login and password-change audit trail, newest first. */
+  fetchAuthEvents(params = {}) {
+    const qs = new URLSearchParams(params).toString();
+    return this.get(`/auth/events${qs ? `?${qs}` : ''}`);
+  }
+
+  // ── API tokens ────────────────────────────────────────────────────────────
+
+  fetchApiTokens() {
+    return this.get('/tokens');
+  }
+
+  /** Resolves with the new token; its `token` field is only returned once. */
+  createApiToken(name, scopes = []) {
+    return this.post('/tokens', { name, scopes });
+  }
+
+  revokeApiToken(id) {
+    return this.delete(`/tokens/${id}`);
+  }
+
   // ── Users ─────────────────────────────────────────────────────────────────
 
   fetchUsers() {
     return this.get('/users');
   }
 
-  createUser(username, password) {
-    return this.post('/users', { username, password });
+  fetchPreferences() {
+    return this.get('/users/me/preferences');
+  }
+
+  updatePreferences(prefs) {
+    return this.put('/users/me/preferences', prefs);
+  }
+
+  setUserRole(id, role) {
+    return this.put(`/users/${id}/role`, { role });
+  }
+
+  createUser(username, password, role = 'user') {
+    return this.post('/users', { username, password, role });
   }
 
   deleteUser(id) {
@@ -201,8 +454,10 @@ export class UnauthenticatedError extends Error {
 
 export class ApiError extends Error {
   name = 'ApiError';
-  constructor(status, message) {
+  constructor(status, message, fields = null) {
     super(message);
     this.status = status;
+    // Per-field validation messages, keyed by field name (400 responses)
+    this.fields = fields;
   }
 }
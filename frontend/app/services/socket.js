@@ -9,6 +9,9 @@ import config from 'tapedeck-ui/config/environment';
  * unsubscribe with `off(type, handler)`.
  *
  * Automatically reconnects with exponential back-off.
+ *
+ * `tail(id)` streams a queue item's get_iplayer output as `log_line` events
+ * until `untail(id)`; tails are re-requested after a reconnect.
  */
 export default class SocketService extends Service {
   @service api;
@@ -20,6 +23,7 @@ export default class SocketService extends Service {
   #reconnectDelay = 1000;
   #reconnectTimer = null;
   #intentionalClose = false;
+  #tails = new Set(); // queue item ids being tailed
 
   // ── Connection management ──────────────────────────────────────────────────
 
@@ -51,6 +55,7 @@ export default class SocketService extends Service {
     ws.addEventListener('open', () => {
       this.connected = true;
       this.#reconnectDelay = 1000;
+      this.#tails.forEach((id) => this.#send({ action: 'tail', id }));
     });
 
     ws.addEventListener('close', () => {
@@ -87,6 +92,24 @@ export default class SocketService extends Service {
     this.#reconnectDelay = Math.min(this.#reconnectDelay * 2, 30_000);
   }
 
+  // ── Log tails ──────────────────────────────────────────────────────────────
+
+  tail(id) {
+    this.#tails.add(id);
+    this.#send({ action: 'tail', id });
+  }
+
+  untail(id) {
+    this.#tails.delete(id);
+    this.#send({ action: 'untail', id });
+  }
+
+  #send(message) {
+    if (this.#ws?.readyState === WebSocket.OPEN) {
+      this.#ws.send(JSON.stringify(message));
+    }
+  }
+
   // ── Event bus ──────────────────────────────────────────────────────────────
 
   on(type, handler) {